@@ -0,0 +1,196 @@
+//! Python bindings for [`markovish`], built using [`pyo3`](https://crates.io/crates/pyo3). Build
+//! the module using [`maturin`](https://www.maturin.rs/):
+//!
+//! ```sh
+//! cd markovish-py
+//! maturin develop
+//! ```
+//!
+//! ```python
+//! import pickle
+//! from markovish import Chain, ChainBuilder
+//!
+//! chain = Chain("I am a chain running in Python.")
+//! print(chain.generate_str(50))
+//!
+//! builder = ChainBuilder()
+//! builder.feed_str("I am a cat.")
+//! builder.feed_str("I am a dog.")
+//! chain = builder.build()
+//!
+//! # Chains are pickled using the same format as `Chain::save()`
+//! chain = pickle.loads(pickle.dumps(chain))
+//! ```
+
+use markovish::{Chain, ChainBuilder, IntoChainBuilder};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// A Markov chain, created from a text or using a `ChainBuilder`.
+#[pyclass(name = "Chain", module = "markovish", frozen)]
+#[derive(Clone, Debug)]
+pub struct PyChain {
+    chain: Chain,
+}
+
+#[pymethods]
+impl PyChain {
+    /// Creates a chain from a text. Raises `ValueError` if the text is too short.
+    #[new]
+    fn new(text: &str) -> PyResult<Self> {
+        Chain::from_text(text)
+            .map(Self::from)
+            .map_err(|_| PyValueError::new_err("text is too short to create a chain"))
+    }
+
+    /// Generates `n` tokens, randomly choosing a starting point, and joins them together.
+    fn generate_str(&self, n: usize) -> Option<String> {
+        self.chain
+            .generate_str(&mut rand::thread_rng(), n)
+            .map(|tokens| tokens.concat())
+    }
+
+    /// Generates `n` tokens, randomly choosing a starting point.
+    fn generate_tokens(&self, n: usize) -> Option<Vec<String>> {
+        self.chain
+            .generate_str(&mut rand::thread_rng(), n)
+            .map(|tokens| tokens.into_iter().map(str::to_string).collect())
+    }
+
+    /// Generates a random token that may follow `left` and `right`, or `None` if they have never
+    /// been seen together.
+    fn generate_next_token(&self, left: &str, right: &str) -> Option<String> {
+        self.chain
+            .generate_next_token(&mut rand::thread_rng(), &(left, right))
+            .map(str::to_string)
+    }
+
+    /// Returns every pair of tokens in the chain.
+    fn pairs(&self) -> Vec<(String, String)> {
+        self.chain
+            .pairs()
+            .map(|(left, right)| (left.to_string(), right.to_string()))
+            .collect()
+    }
+
+    /// Saves the chain to a file at `path`, readable by `Chain.load()` and `Chain::load()`.
+    fn save(&self, path: &str) -> PyResult<()> {
+        self.chain
+            .save(path)
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Loads a chain saved using `Chain.save()` or `Chain::save()`.
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        Chain::load(path)
+            .map(Self::from)
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Returns the chain in the same format as `Chain.save()`.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut bytes = Vec::new();
+        self.chain
+            .write_to(&mut bytes)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reads a chain returned by `Chain.to_bytes()`.
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        Chain::read_from(bytes)
+            .map(Self::from)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Pickles the chain as a call to `Chain.from_bytes()`, since the constructor needs a text.
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(Bound<'py, PyAny>, (Bound<'py, PyBytes>,))> {
+        let from_bytes = slf.get_type().getattr("from_bytes")?;
+        Ok((from_bytes, (slf.get().to_bytes(slf.py())?,)))
+    }
+
+    fn __len__(&self) -> usize {
+        self.chain.pairs().count()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Chain with {} pairs>", self.chain.pairs().count())
+    }
+}
+
+impl From<Chain> for PyChain {
+    fn from(chain: Chain) -> Self {
+        Self { chain }
+    }
+}
+
+impl From<PyChain> for Chain {
+    fn from(value: PyChain) -> Self {
+        value.chain
+    }
+}
+
+/// Builds a `Chain` by being fed texts. Unlike in Rust, feeding modifies the builder in place.
+#[pyclass(name = "ChainBuilder", module = "markovish")]
+#[derive(Clone, Debug, Default)]
+pub struct PyChainBuilder {
+    cb: ChainBuilder,
+}
+
+impl PyChainBuilder {
+    /// Feeds the builder using `feed`, returning `true` if the feed succeeded.
+    fn feed(&mut self, feed: impl FnOnce(ChainBuilder) -> markovish::chain::FeedResult) -> bool {
+        let res = feed(std::mem::take(&mut self.cb));
+        let fed = res.is_ok();
+        self.cb = res.into_cb();
+        fed
+    }
+}
+
+#[pymethods]
+impl PyChainBuilder {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the builder with a text. Returns `False` if the text was too short to be used.
+    fn feed_str(&mut self, text: &str) -> bool {
+        self.feed(|cb| cb.feed_str(text))
+    }
+
+    /// Feeds the builder with a text, also recording where its sentences start and end. Returns
+    /// `False` if the text was too short to be used.
+    fn feed_sentences(&mut self, text: &str) -> bool {
+        self.feed(|cb| cb.feed_sentences(text))
+    }
+
+    /// Feeds the builder with already split tokens. Returns `False` if there were too few
+    /// tokens to be used.
+    fn feed_tokens(&mut self, tokens: Vec<String>) -> bool {
+        self.feed(|cb| cb.feed_tokens(tokens.iter().map(String::as_str)))
+    }
+
+    /// Builds a chain from everything fed so far. The builder can still be fed afterwards.
+    /// Raises `ValueError` if nothing has been fed.
+    fn build(&self) -> PyResult<PyChain> {
+        self.cb
+            .clone()
+            .build()
+            .map(PyChain::from)
+            .map_err(|_| PyValueError::new_err("nothing has been fed to the builder"))
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "markovish")]
+fn markovish_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyChain>()?;
+    m.add_class::<PyChainBuilder>()?;
+    Ok(())
+}
@@ -0,0 +1,1018 @@
+//! Statistics and other information about a [`Chain`], useful for comparing and debugging chains.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem::size_of;
+
+use hashbrown::{HashMap, HashSet};
+use itertools::Itertools;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::distribution::{TokenDistribution, TokenDistributionBuilder};
+use crate::sentence::SentenceBounds;
+use crate::token::{Token, TokenPair, TokenPairRef, TokenRef};
+use crate::{Chain, ChainBuilder};
+
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+/// A summary of the size and shape of a [`Chain`], see [`Chain::stats()`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainStats {
+    /// The amount of unique pairs of tokens.
+    pub pairs: usize,
+    /// The amount of unique tokens.
+    pub tokens: usize,
+    /// The amount of transitions that was fed into the chain, i.e. the sum of all counts.
+    pub transitions: u64,
+    /// The largest amount of different tokens that may follow a single pair.
+    pub max_branching: usize,
+    /// The average amount of different tokens that may follow a pair.
+    pub mean_branching: f64,
+    /// A rough estimate of how many bytes the chain uses in memory.
+    pub memory_estimate: usize,
+}
+
+impl fmt::Display for ChainStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pairs, {} tokens, {} transitions, branching max {} mean {:.2}, ~{} KiB",
+            self.pairs,
+            self.tokens,
+            self.transitions,
+            self.max_branching,
+            self.mean_branching,
+            self.memory_estimate.div_ceil(1024),
+        )
+    }
+}
+
+/// How to score transitions a chain has never seen, when calculating how likely a text is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Unseen transitions have this probability, and all others keep their probability.
+    Floor(f64),
+    /// Additive (or Laplace) smoothing: every token is treated as if it had been seen this many
+    /// more times following every pair. Unlike [`Smoothing::Floor`], the probabilities of each
+    /// pair still sum to one.
+    Additive(f64),
+}
+
+impl Default for Smoothing {
+    /// A floor of one in a million.
+    fn default() -> Self {
+        Self::Floor(1e-6)
+    }
+}
+
+/// How much of a [`Chain`] can be reached from a pair, see [`Chain::reachable_from()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reachability {
+    /// The amount of pairs that generation can reach, including the pair it started from.
+    pub pairs: usize,
+    /// The amount of unique tokens that can be generated, including the two tokens of the pair it
+    /// started from.
+    pub tokens: usize,
+}
+
+/// FNV-1a, used since it is simple and gives the same result on every platform and version.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    /// Writes a string prefixed by its length, so that `("ab", "c")` and `("a", "bc")` differ.
+    fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Mixes the bits of a hash, so that summing many hashes does not cancel out patterns in them.
+/// This is the finalizer of SplitMix64.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl Chain {
+    /// Computes a fingerprint of the chain, which is the same for chains with the same pairs,
+    /// tokens and counts. It does not depend on the order of anything in the chain, and is the
+    /// same on every platform and every run, so it can be used to identify a chain in caches or
+    /// file names.
+    ///
+    /// As with any hash, different chains may (very rarely) have the same fingerprint.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let a = Chain::from_text("I am a chain").unwrap();
+    /// let b = Chain::from_text("I am a chain").unwrap();
+    /// let c = Chain::from_text("I am another chain").unwrap();
+    /// assert_eq!(a.fingerprint(), b.fingerprint());
+    /// assert_ne!(a.fingerprint(), c.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        // Every pair is hashed on its own, and then combined using addition so that the order
+        // of the pairs does not matter
+        let mut combined = 0_u64;
+        for (left, rights) in &self.map {
+            for (right, dist) in rights {
+                combined = combined.wrapping_add(mix(pair_hash(left, right, dist)));
+            }
+        }
+
+        let mut h = Fnv1a::new();
+        h.write(&combined.to_le_bytes());
+        h.finish()
+    }
+
+    /// Returns the entropy in bits of the tokens that may follow `prev`, see
+    /// [`TokenDistribution::entropy()`]. A pair that is always followed by the same token has an
+    /// entropy of zero, and a pair followed by two equally likely tokens has an entropy of one.
+    ///
+    /// If the chain has never seen the `prev` tokens together, `None` is returned.
+    pub fn pair_entropy(&self, prev: &TokenPairRef<'_>) -> Option<f64> {
+        Some(self.get_distribution(prev)?.entropy())
+    }
+
+    /// Returns the [perplexity](https://en.wikipedia.org/wiki/Perplexity) of `text` under this
+    /// chain, using [`Smoothing::default()`] for transitions the chain has never seen. The text
+    /// is split into tokens the same way as in
+    /// [`ChainBuilder::feed_str()`](crate::ChainBuilder::feed_str()).
+    ///
+    /// A lower perplexity means that the text is more similar to the text(s) the chain was built
+    /// from, so this can be used to find which of several chains best matches a new text.
+    ///
+    /// If the text is too short to contain any transitions, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let cats = Chain::from_text("I like cats. Cats like me. I like to pet cats.").unwrap();
+    /// let dogs = Chain::from_text("Dogs are loud. I like dogs anyway. Dogs bark.").unwrap();
+    ///
+    /// let text = "I like to pet cats.";
+    /// assert!(cats.perplexity(text).unwrap() < dogs.perplexity(text).unwrap());
+    /// ```
+    pub fn perplexity(&self, text: &str) -> Option<f64> {
+        self.perplexity_with(text, Smoothing::default())
+    }
+
+    /// Like [`Chain::perplexity()`], but with the given smoothing.
+    pub fn perplexity_with(&self, text: &str, smoothing: Smoothing) -> Option<f64> {
+        let (log_likelihood, n) = self.score(text.split_word_bounds(), smoothing)?;
+        Some((-log_likelihood / n as f64).exp())
+    }
+
+    /// Returns the [`Chain::perplexity()`] of every text in `texts`, in the same order. The
+    /// texts are scored in parallel, so that many texts can be scored quickly, such as when using
+    /// chains to tell languages or spam apart. Requires the `rayon` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I like cats. Cats like me.").unwrap();
+    /// let scores = chain.score_batch_par(&["I like cats.", "Dogs bark", "Hi"]);
+    /// assert!(scores[0].unwrap() < scores[1].unwrap());
+    /// assert_eq!(scores[2], None);
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn score_batch_par<S: AsRef<str> + Sync>(&self, texts: &[S]) -> Vec<Option<f64>> {
+        self.score_batch_par_with(texts, Smoothing::default())
+    }
+
+    /// Like [`Chain::score_batch_par()`], but with the given smoothing. Requires the `rayon`
+    /// feature.
+    #[cfg(feature = "rayon")]
+    pub fn score_batch_par_with<S: AsRef<str> + Sync>(
+        &self,
+        texts: &[S],
+        smoothing: Smoothing,
+    ) -> Vec<Option<f64>> {
+        texts
+            .par_iter()
+            .map(|text| self.perplexity_with(text.as_ref(), smoothing))
+            .collect()
+    }
+
+    /// Returns the natural logarithm of the probability that this chain generates the already
+    /// split `tokens`, given their first two tokens. If any transition has never been seen, `None`
+    /// is returned; use [`Chain::log_likelihood_with()`] to score those as well.
+    ///
+    /// If there are fewer than three tokens, there is nothing to score and `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am, I am not").unwrap();
+    ///
+    /// let ll = chain.log_likelihood(["I", " ", "am", " "].into_iter()).unwrap();
+    /// assert_eq!(ll, 0.5_f64.ln());
+    /// assert_eq!(chain.log_likelihood(["I", " ", "was"].into_iter()), None);
+    /// ```
+    pub fn log_likelihood<'a>(&self, tokens: impl Iterator<Item = TokenRef<'a>>) -> Option<f64> {
+        self.score(tokens, Smoothing::Floor(0.0))
+            .map(|(ll, _)| ll)
+            .filter(|ll| ll.is_finite())
+    }
+
+    /// Like [`Chain::log_likelihood()`], but transitions the chain has never seen are scored
+    /// using `smoothing`. The result is only `None` if there are fewer than three tokens.
+    pub fn log_likelihood_with<'a>(
+        &self,
+        tokens: impl Iterator<Item = TokenRef<'a>>,
+        smoothing: Smoothing,
+    ) -> Option<f64> {
+        self.score(tokens, smoothing).map(|(ll, _)| ll)
+    }
+
+    /// Scores every transition in `tokens`, returning the sum of the natural logarithm of their
+    /// probabilities and the amount of transitions. Returns `None` if there are no transitions.
+    fn score<'a>(
+        &self,
+        tokens: impl Iterator<Item = TokenRef<'a>>,
+        smoothing: Smoothing,
+    ) -> Option<(f64, usize)> {
+        // Only needed for additive smoothing, and expensive to find
+        let vocabulary = match smoothing {
+            Smoothing::Floor(_) => 0.0,
+            Smoothing::Additive(_) => self.tokens().count() as f64,
+        };
+
+        let mut sum = 0.0;
+        let mut n = 0;
+        for (left, right, next) in tokens.tuple_windows() {
+            let dist = self.get_distribution(&(left, right));
+            let p = match (smoothing, dist) {
+                (Smoothing::Floor(floor), Some(dist)) => {
+                    let p = dist.probability(next);
+                    if p > 0.0 {
+                        p
+                    } else {
+                        floor
+                    }
+                }
+                (Smoothing::Floor(floor), None) => floor,
+                (Smoothing::Additive(k), Some(dist)) => {
+                    (dist.count(next) as f64 + k) / (dist.total() as f64 + k * vocabulary)
+                }
+                (Smoothing::Additive(_), None) => 1.0 / vocabulary,
+            };
+            sum += p.ln();
+            n += 1;
+        }
+
+        (n > 0).then_some((sum, n))
+    }
+
+    /// Returns a [`ChainStats`] summary of the chain, useful for logging and checking that a
+    /// corpus was large enough.
+    ///
+    /// This goes through the entire chain, so it is not cheap to call for large chains.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let stats = chain.stats();
+    /// assert_eq!(stats.max_branching, 2);
+    /// println!("{stats}");
+    /// ```
+    pub fn stats(&self) -> ChainStats {
+        let mut pairs = 0;
+        let mut transitions = 0;
+        let mut branches = 0;
+        let mut max_branching = 0;
+        let mut memory_estimate = size_of::<Self>() + map_size(&self.map);
+
+        for (left, rights) in &self.map {
+            memory_estimate += token_size(left) + map_size(rights);
+            for (right, dist) in rights {
+                pairs += 1;
+                transitions += dist.total();
+                branches += dist.len();
+                max_branching = max_branching.max(dist.len());
+                memory_estimate += token_size(right) + distribution_size(dist);
+            }
+        }
+
+        ChainStats {
+            pairs,
+            tokens: self.tokens().count(),
+            transitions,
+            max_branching,
+            mean_branching: if pairs == 0 {
+                0.0
+            } else {
+                branches as f64 / pairs as f64
+            },
+            memory_estimate,
+        }
+    }
+
+    /// Returns the `n` pairs that were seen the most often, together with how many times they
+    /// were followed by another token. The most common pair comes first, and pairs seen equally
+    /// often are sorted by their tokens.
+    ///
+    /// This is useful for finding what dominates the chain, like a header repeated in every
+    /// document of a corpus.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("la la la la land").unwrap();
+    /// assert_eq!(chain.top_pairs(1), vec![(("la", " "), 4)]);
+    /// ```
+    pub fn top_pairs(&self, n: usize) -> Vec<(TokenPairRef<'_>, u64)> {
+        let pairs = self.map.iter().flat_map(|(left, rights)| {
+            rights
+                .iter()
+                .map(move |(right, dist)| ((left.as_str(), right.as_str()), dist.total()))
+        });
+        top_n(pairs, n)
+    }
+
+    /// Returns the `n` tokens that were seen the most often following a pair, together with how
+    /// many times they were seen. The most common token comes first, and tokens seen equally
+    /// often are sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("la la la la land").unwrap();
+    /// assert_eq!(chain.top_tokens(2), vec![(" ", 3), ("la", 3)]);
+    /// ```
+    pub fn top_tokens(&self, n: usize) -> Vec<(TokenRef<'_>, u64)> {
+        let mut counts: HashMap<&str, u64> = HashMap::new();
+        for dist in self.map.values().flat_map(|rights| rights.values()) {
+            for (token, count) in dist.iter() {
+                *counts.entry(token.as_str()).or_default() += count as u64;
+            }
+        }
+        top_n(counts, n)
+    }
+
+    /// Returns an iterator of all pairs where generation always has to restart after the next
+    /// token. That is, no matter which token follows the pair, the chain has never seen the new
+    /// pair of tokens followed by anything.
+    ///
+    /// Small corpora tend to have many dead ends, which makes the generated text jump between
+    /// topics as [`Chain::generate_str()`] picks new start tokens.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// // "a" followed by " " is always followed by "cat", but nothing ever follows " " "cat"
+    /// assert_eq!(chain.dead_ends().collect::<Vec<_>>(), vec![("a", " ")]);
+    /// ```
+    pub fn dead_ends(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        self.map.iter().flat_map(move |(left, rights)| {
+            rights
+                .iter()
+                .filter(move |(right, dist)| {
+                    dist.iter()
+                        .all(|(next, _)| self.get_distribution(&(right, next)).is_none())
+                })
+                .map(move |(right, _)| (left.as_str(), right.as_str()))
+        })
+    }
+
+    /// Finds how much of the chain can be reached when generating from `start`, without
+    /// restarting from new start tokens. If only a few pairs can be reached, `start` is in a
+    /// small island of the chain and generation from it will quickly loop or end.
+    ///
+    /// If the chain has never seen the `start` tokens together, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use markovish::analysis::Reachability;
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// assert_eq!(
+    ///     chain.reachable_from(&("a", " ")),
+    ///     Some(Reachability { pairs: 1, tokens: 3 })
+    /// );
+    /// assert_eq!(chain.reachable_from(&("dog", " ")), None);
+    /// ```
+    pub fn reachable_from(&self, start: &TokenPairRef<'_>) -> Option<Reachability> {
+        let (left, rights) = self.map.get_key_value(start.0)?;
+        let (right, dist) = rights.get_key_value(start.1)?;
+
+        let mut pairs: HashSet<TokenPairRef<'_>> = HashSet::new();
+        let mut tokens: HashSet<&str> = HashSet::new();
+        let mut queue = VecDeque::new();
+        pairs.insert((left, right));
+        tokens.extend([left.as_str(), right.as_str()]);
+        queue.push_back((right, dist));
+
+        // Breadth first search, where a pair leads to the pairs made of its right token and each
+        // token that may follow it
+        while let Some((right, dist)) = queue.pop_front() {
+            for (next, _) in dist.iter() {
+                tokens.insert(next);
+                let Some((next, next_dist)) = self
+                    .map
+                    .get(right.as_str())
+                    .and_then(|rights| rights.get_key_value(next.as_str()))
+                else {
+                    continue;
+                };
+                if pairs.insert((right, next)) {
+                    queue.push_back((next, next_dist));
+                }
+            }
+        }
+
+        Some(Reachability {
+            pairs: pairs.len(),
+            tokens: tokens.len(),
+        })
+    }
+
+    /// Returns how many pairs have each amount of possible next tokens; the value at index `k` is
+    /// the amount of pairs that can be followed by `k` different tokens. Since every pair can be
+    /// followed by at least one token, the first value is always zero. The last value is never
+    /// zero, unless the chain is empty.
+    ///
+    /// If almost every pair only has a single possible next token, the chain will mostly repeat
+    /// its source text, and a larger corpus is needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let histogram = chain.branching_histogram();
+    /// // Only "a" " " can be followed by two different tokens
+    /// assert_eq!(histogram, vec![0, chain.pairs().count() - 1, 1]);
+    /// ```
+    pub fn branching_histogram(&self) -> Vec<usize> {
+        let mut histogram = vec![0];
+        for dist in self.map.values().flat_map(|rights| rights.values()) {
+            if histogram.len() <= dist.len() {
+                histogram.resize(dist.len() + 1, 0);
+            }
+            histogram[dist.len()] += 1;
+        }
+        histogram
+    }
+
+    /// Compares how this chain and `other` choose the next token, returning a number between
+    /// zero (the same) and one (completely different). Can be used to find out how similar the
+    /// texts two chains were built from are, for example to compare authors.
+    ///
+    /// Only pairs found in both chains are compared, using the
+    /// [Jensen-Shannon divergence](https://en.wikipedia.org/wiki/Jensen%E2%80%93Shannon_divergence)
+    /// in bits. This is a symmetric version of the Kullback-Leibler divergence that does not
+    /// become infinite when a token is only found in one of the chains. The result is the
+    /// average of all pairs, weighted by how often they were seen in both chains. If the chains
+    /// do not share any pairs, they are completely different.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let a = Chain::from_text("I am a cat. I am a cat.").unwrap();
+    /// let b = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let c = Chain::from_text("You are a dog. You are a dog.").unwrap();
+    /// assert_eq!(a.divergence(&a), 0.0);
+    /// assert!(a.divergence(&b) < a.divergence(&c));
+    /// assert_eq!(a.divergence(&b), b.divergence(&a));
+    /// ```
+    pub fn divergence(&self, other: &Chain) -> f64 {
+        let mut weighted = 0.0;
+        let mut total = 0.0;
+        for (left, rights) in &self.map {
+            let Some(other_rights) = other.map.get(left) else {
+                continue;
+            };
+            for (right, p) in rights {
+                let Some(q) = other_rights.get(right) else {
+                    continue;
+                };
+                let n = (p.total() + q.total()) as f64;
+                weighted += jensen_shannon(p, q) * n;
+                total += n;
+            }
+        }
+
+        if total == 0.0 {
+            1.0
+        } else {
+            weighted / total
+        }
+    }
+
+    /// Returns the average entropy in bits of all pairs in the chain, weighted by how many times
+    /// each pair was seen. This is how many bits of randomness an average generated token has,
+    /// and can be used to compare how "creative" chains built from different texts are; a chain
+    /// with a mean entropy close to zero will mostly repeat its source text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let boring = Chain::from_text("I am a parrot").unwrap();
+    /// assert_eq!(boring.mean_entropy(), 0.0);
+    ///
+    /// let creative = Chain::from_text("I am a cat. I am a dog. I am a bird.").unwrap();
+    /// assert!(creative.mean_entropy() > 0.0);
+    /// ```
+    pub fn mean_entropy(&self) -> f64 {
+        let mut weighted = 0.0;
+        let mut total = 0.0;
+        for dist in self.map.values().flat_map(|rights| rights.values()) {
+            let n = dist.total() as f64;
+            weighted += dist.entropy() * n;
+            total += n;
+        }
+
+        if total == 0.0 {
+            0.0
+        } else {
+            weighted / total
+        }
+    }
+
+    /// Returns how often each token is generated in the long run, by running
+    /// [power iteration](https://en.wikipedia.org/wiki/Power_iteration) over the pairs of the
+    /// chain. The frequencies sum to one, and can be compared to how often tokens appear in the
+    /// source text to see what generated text converges to.
+    ///
+    /// When generation reaches a pair with no possible next token it restarts from a random pair,
+    /// like [`Chain::generate_str()`]. Iteration stops once the total change of all pairs is less
+    /// than `tolerance`, or after `max_iters` iterations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("a a b a a b").unwrap();
+    /// let freqs = chain.stationary_distribution(1000, 1e-9);
+    /// let total: f64 = freqs.values().sum();
+    /// assert!((total - 1.0).abs() < 1e-9);
+    /// assert!(freqs[" "] > freqs["b"]);
+    /// ```
+    pub fn stationary_distribution(
+        &self,
+        max_iters: usize,
+        tolerance: f64,
+    ) -> HashMap<TokenRef<'_>, f64> {
+        let graph = PairGraph::new(self);
+        let n = graph.pairs.len();
+        if n == 0 {
+            return HashMap::new();
+        }
+
+        let mut rank = vec![1.0 / n as f64; n];
+        for _ in 0..max_iters {
+            let mut next = vec![0.0; n];
+            let mut restarted = 0.0;
+            for (i, edges) in graph.edges.iter().enumerate() {
+                for &(_, to, p) in edges {
+                    match to {
+                        Some(j) => next[j] += rank[i] * p,
+                        None => restarted += rank[i] * p,
+                    }
+                }
+            }
+            next.iter_mut().for_each(|r| *r += restarted / n as f64);
+
+            let change: f64 = rank.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+            rank = next;
+            if change < tolerance {
+                break;
+            }
+        }
+
+        let mut freqs: HashMap<TokenRef<'_>, f64> = HashMap::new();
+        for (i, edges) in graph.edges.iter().enumerate() {
+            for &(token, _, p) in edges {
+                *freqs.entry(token).or_default() += rank[i] * p;
+            }
+        }
+        freqs
+    }
+
+    /// Ranks every token by how central it is to generation, using
+    /// [PageRank](https://en.wikipedia.org/wiki/PageRank) over the graph where a token links to
+    /// every token that may follow it, weighted by how often it did. Tokens that many common paths
+    /// lead through, such as spaces and common words, rank highest, and are candidates to prune
+    /// or down-weight if they dominate generated text.
+    ///
+    /// The ranks sum to one, and are sorted with the highest rank first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I like cats. I like dogs. I like birds.").unwrap();
+    /// let ranks = chain.token_centrality();
+    /// assert_eq!(ranks[0].0, " ");
+    /// ```
+    pub fn token_centrality(&self) -> Vec<(TokenRef<'_>, f64)> {
+        const DAMPING: f64 = 0.85;
+        const MAX_ITERS: usize = 100;
+        const TOLERANCE: f64 = 1e-10;
+
+        let tokens: Vec<TokenRef<'_>> = self.tokens().collect();
+        let index: HashMap<TokenRef<'_>, usize> =
+            tokens.iter().enumerate().map(|(i, t)| (*t, i)).collect();
+        let n = tokens.len();
+
+        // How many times each token was followed by each other token
+        let mut links: Vec<HashMap<usize, u64>> = vec![HashMap::new(); n];
+        for rights in self.map.values() {
+            for (right, dist) in rights {
+                let from = &mut links[index[right.as_str()]];
+                for (next, count) in dist.iter() {
+                    *from.entry(index[next.as_str()]).or_default() += count as u64;
+                }
+            }
+        }
+        let links: Vec<Vec<(usize, f64)>> = links
+            .into_iter()
+            .map(|to| {
+                let total: u64 = to.values().sum();
+                to.into_iter()
+                    .map(|(j, count)| (j, count as f64 / total as f64))
+                    .collect()
+            })
+            .collect();
+
+        let mut rank = vec![1.0 / n as f64; n];
+        for _ in 0..MAX_ITERS {
+            let mut next = vec![0.0; n];
+            // Tokens that are never followed by anything link to every token
+            let mut dangling = 0.0;
+            for (i, to) in links.iter().enumerate() {
+                if to.is_empty() {
+                    dangling += rank[i];
+                }
+                for &(j, p) in to {
+                    next[j] += rank[i] * p;
+                }
+            }
+            let base = (1.0 - DAMPING + DAMPING * dangling) / n as f64;
+            next.iter_mut().for_each(|r| *r = base + DAMPING * *r);
+
+            let change: f64 = rank.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+            rank = next;
+            if change < TOLERANCE {
+                break;
+            }
+        }
+
+        let mut ranks: Vec<_> = tokens.into_iter().zip(rank).collect();
+        ranks.sort_unstable_by(|(a, a_rank), (b, b_rank)| b_rank.total_cmp(a_rank).then(a.cmp(b)));
+        ranks
+    }
+}
+
+/// The pairs of a chain as a graph, where each pair has an edge to the pairs made of its right
+/// token and each token that may follow it.
+struct PairGraph<'a> {
+    pairs: Vec<TokenPairRef<'a>>,
+    /// The next token, the index of the pair it leads to (if the chain has it) and its
+    /// probability, for every pair.
+    edges: Vec<Vec<(TokenRef<'a>, Option<usize>, f64)>>,
+}
+
+impl<'a> PairGraph<'a> {
+    fn new(chain: &'a Chain) -> Self {
+        let pairs: Vec<TokenPairRef<'a>> = chain.pairs().collect();
+        let index: HashMap<TokenPairRef<'a>, usize> =
+            pairs.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+        let edges = pairs
+            .iter()
+            .map(|pair| {
+                // Unwrap is safe, since the pair is from the chain
+                let dist = chain.get_distribution(pair).unwrap();
+                let total = dist.total() as f64;
+                dist.iter()
+                    .map(|(next, n)| {
+                        let next = next.as_str();
+                        (next, index.get(&(pair.1, next)).copied(), n as f64 / total)
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { pairs, edges }
+    }
+}
+
+/// The Jensen-Shannon divergence in bits between two distributions.
+fn jensen_shannon(p: &TokenDistribution, q: &TokenDistribution) -> f64 {
+    // The sum of both Kullback-Leibler divergences to the mixture of the distributions, where
+    // tokens only found in one distribution do not contribute to the other divergence
+    let kl_to_mixture = |a: &TokenDistribution, b: &TokenDistribution| {
+        a.iter()
+            .map(|(token, _)| {
+                let pa = a.probability(token);
+                let m = (pa + b.probability(token)) / 2.0;
+                pa * (pa / m).log2()
+            })
+            .sum::<f64>()
+    };
+    // Rounding can make this slightly negative for equal distributions
+    ((kl_to_mixture(p, q) + kl_to_mixture(q, p)) / 2.0).max(0.0)
+}
+
+/// Returns the `n` items with the highest count, sorted by count and then by item.
+fn top_n<T: Ord>(items: impl IntoIterator<Item = (T, u64)>, n: usize) -> Vec<(T, u64)> {
+    let mut items: Vec<_> = items.into_iter().collect();
+    items.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    items.truncate(n);
+    items
+}
+
+impl ChainBuilder {
+    /// Returns a rough estimate of how many bytes the builder uses in memory, like
+    /// [`ChainStats::memory_estimate`] does for a [`Chain`].
+    ///
+    /// This goes through the entire builder, so it is not cheap to call for large builders.
+    pub fn memory_estimate(&self) -> usize {
+        size_of::<Self>()
+            + map_size(&self.map)
+            + self
+                .map
+                .iter()
+                .map(|(pair, b)| builder_pair_size(pair, b))
+                .sum::<usize>()
+            + sentences_size(&self.sentences)
+    }
+}
+
+/// Bytes on the heap owned by sentence bounds.
+fn sentences_size(sentences: &SentenceBounds) -> usize {
+    sentences
+        .sets()
+        .iter()
+        .map(|set| {
+            set.capacity() * (size_of::<TokenPair>() + 1)
+                + set
+                    .iter()
+                    .map(|TokenPair(left, right)| token_size(left) + token_size(right))
+                    .sum::<usize>()
+        })
+        .sum()
+}
+
+/// Bytes on the heap owned by a pair of a [`ChainBuilder`] and its distribution, not counting
+/// its bucket in the map of the builder.
+pub(crate) fn builder_pair_size(pair: &TokenPair, b: &TokenDistributionBuilder) -> usize {
+    token_size(&pair.0)
+        + token_size(&pair.1)
+        + map_size(&b.map)
+        + b.map.keys().map(token_size).sum::<usize>()
+}
+
+/// Bytes on the heap used by the table of a map, ignoring whatever its keys and values own.
+fn map_size<K, V>(map: &HashMap<K, V>) -> usize {
+    // Each bucket also has a control byte
+    map.capacity() * (size_of::<K>() + size_of::<V>() + 1)
+}
+
+/// Bytes on the heap owned by a token.
+fn token_size(token: &Token) -> usize {
+    #[cfg(feature = "compact_str")]
+    if !token.is_heap_allocated() {
+        return 0;
+    }
+    token.capacity()
+}
+
+/// Bytes on the heap owned by a distribution.
+fn distribution_size(dist: &TokenDistribution) -> usize {
+    dist.heap_size()
+        + dist
+            .iter()
+            .map(|(token, _)| token_size(token))
+            .sum::<usize>()
+}
+
+fn pair_hash(left: &str, right: &str, dist: &TokenDistribution) -> u64 {
+    let (choices, weights) = dist.parts();
+    let mut counts: Vec<_> = choices.iter().zip(weights).collect();
+    counts.sort_unstable();
+
+    let mut h = Fnv1a::new();
+    h.write_str(left);
+    h.write_str(right);
+    for (token, n) in counts {
+        h.write_str(token);
+        h.write(&(*n as u64).to_le_bytes());
+    }
+    h.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Chain, ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn stationary_distribution_of_cycle_and_dead_end() {
+        let cb = ChainBuilder::new()
+            .feed_tokens(["a", "b", "c", "a", "b", "c", "a"].into_iter())
+            .into_cb();
+        let chain = cb.build().unwrap();
+        let freqs = chain.stationary_distribution(100, 1e-12);
+        for token in ["a", "b", "c"] {
+            assert!((freqs[token] - 1.0 / 3.0).abs() < 1e-9);
+        }
+
+        // Generation always restarts at the only pair
+        let cb = ChainBuilder::new()
+            .feed_tokens(["x", "y", "z"].into_iter())
+            .into_cb();
+        let chain = cb.build().unwrap();
+        let freqs = chain.stationary_distribution(100, 1e-12);
+        assert_eq!(freqs.len(), 1);
+        assert!((freqs["z"] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn centrality_ranks_hubs_first() {
+        // Every token leads to and from "hub"
+        let cb = ChainBuilder::new()
+            .feed_tokens(["a", "hub", "b", "hub", "c", "hub", "a", "hub"].into_iter())
+            .into_cb();
+        let chain = cb.build().unwrap();
+        let ranks = chain.token_centrality();
+
+        assert_eq!(ranks.len(), 4);
+        assert_eq!(ranks[0].0, "hub");
+        let total: f64 = ranks.iter().map(|(_, r)| r).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fingerprint_is_stable() {
+        let chain = Chain::from_text("I am-full!of?cats").unwrap();
+        // This value must never change, since it may be stored by users
+        assert_eq!(chain.fingerprint(), 0x0d82_72c3_1009_1fff);
+    }
+
+    #[test]
+    fn entropy_of_even_split() {
+        let chain = ChainBuilder::new()
+            .feed_tokens("a b c a b d a b e a b f".split_whitespace())
+            .into_cb()
+            .build()
+            .unwrap();
+        assert_eq!(chain.pair_entropy(&("a", "b")), Some(2.0));
+        assert_eq!(chain.pair_entropy(&("b", "c")), Some(0.0));
+        assert_eq!(chain.pair_entropy(&("x", "y")), None);
+    }
+
+    #[test]
+    fn perplexity_of_source_text() {
+        use super::Smoothing;
+
+        let text = "a b c a b d";
+        let chain = Chain::from_text(text).unwrap();
+        assert!(chain.perplexity("a").is_none());
+
+        // Every transition of the source text is certain, except after "a b" where there are
+        // two equally likely choices. There are 9 transitions, two of which have a probability
+        // of 0.5
+        let expected = 2.0_f64.powf(2.0 / 9.0);
+        assert!((chain.perplexity(text).unwrap() - expected).abs() < 1e-9);
+
+        let unseen = chain
+            .perplexity_with("x y z", Smoothing::Floor(0.5))
+            .unwrap();
+        assert!((unseen - 2.0).abs() < 1e-9);
+        let additive = chain.perplexity_with("x y z", Smoothing::Additive(1.0));
+        assert!(additive.unwrap() > 1.0);
+    }
+
+    #[test]
+    fn log_likelihood_of_tokens() {
+        use super::Smoothing;
+
+        let chain = Chain::from_text("a b c a b d").unwrap();
+        let tokens = ["a", " ", "b", " ", "c"];
+        let ll = chain.log_likelihood(tokens.into_iter()).unwrap();
+        assert!((ll - 0.5_f64.ln()).abs() < 1e-12);
+
+        let unseen = ["a", " ", "b", " ", "x"];
+        assert_eq!(chain.log_likelihood(unseen.into_iter()), None);
+        let floored = chain
+            .log_likelihood_with(unseen.into_iter(), Smoothing::Floor(0.25))
+            .unwrap();
+        assert!((floored - 0.25_f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn stats_of_small_chain() {
+        let chain = Chain::from_text("a b c a b d").unwrap();
+        let stats = chain.stats();
+        assert_eq!(stats.pairs, 6);
+        // "a", "b", "c", "d" and " "
+        assert_eq!(stats.tokens, 5);
+        assert_eq!(stats.transitions, 9);
+        assert_eq!(stats.max_branching, 2);
+        assert!((stats.mean_branching - 7.0 / 6.0).abs() < 1e-12);
+        assert!(stats.memory_estimate > 0);
+    }
+
+    #[test]
+    fn top_pairs_and_tokens() {
+        let chain = Chain::from_text("a b c a b d").unwrap();
+        assert_eq!(
+            chain.top_pairs(3),
+            vec![((" ", "b"), 2), (("a", " "), 2), (("b", " "), 2)]
+        );
+        assert_eq!(chain.top_tokens(2), vec![(" ", 4), ("b", 2)]);
+        assert_eq!(chain.top_tokens(100).len(), 5);
+        assert!(chain.top_pairs(0).is_empty());
+    }
+
+    #[test]
+    fn dead_ends_need_every_continuation_to_end() {
+        // After "b " comes either "c", which ends the text, or "a", which continues it
+        let chain = Chain::from_text("a b a b c").unwrap();
+        assert_eq!(chain.dead_ends().count(), 0);
+
+        let chain = Chain::from_text("a b c").unwrap();
+        assert_eq!(chain.dead_ends().collect::<Vec<_>>(), vec![("b", " ")]);
+    }
+
+    #[test]
+    fn reachable_follows_loops() {
+        use super::Reachability;
+
+        let chain = Chain::from_text("a b a b c").unwrap();
+        // Every pair can be reached from the first one, even though "b " loops back
+        let all = Reachability {
+            pairs: chain.pairs().count(),
+            tokens: chain.tokens().count(),
+        };
+        assert_eq!(chain.reachable_from(&("a", " ")), Some(all));
+        assert_eq!(chain.reachable_from(&(" ", "b")), Some(all));
+
+        let chain = Chain::from_text("a b c").unwrap();
+        assert_eq!(
+            chain.reachable_from(&(" ", "b")),
+            Some(Reachability {
+                pairs: 2,
+                tokens: 3
+            })
+        );
+    }
+
+    #[test]
+    fn divergence_of_disjoint_continuations() {
+        let a = Chain::from_text("a b c").unwrap();
+        let b = Chain::from_text("a b d").unwrap();
+        // "a" " " and " " "b" are followed by the same tokens, but "b" " " is not
+        let expected = 2.0 / 6.0;
+        assert!((a.divergence(&b) - expected).abs() < 1e-12);
+
+        let unrelated = Chain::from_text("x y z").unwrap();
+        assert_eq!(a.divergence(&unrelated), 1.0);
+    }
+
+    #[test]
+    fn fingerprint_depends_on_counts() {
+        let once = Chain::from_text("a b c").unwrap();
+        let twice = ChainBuilder::new()
+            .feed_str("a b c")
+            .into_cb()
+            .feed_str("a b c")
+            .into_cb()
+            .build()
+            .unwrap();
+        assert_ne!(once.fingerprint(), twice.fingerprint());
+    }
+}
@@ -0,0 +1,290 @@
+//! An [`ApproxChainBuilder`] builds a [`Chain`] using a fixed amount of memory, by only keeping
+//! approximate counts of how often tokens are seen.
+
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use hashbrown::{DefaultHashBuilder, HashMap};
+use itertools::Itertools;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::distribution::{TokenDistributionBuilder, Weight};
+use crate::token::{Token, TokenPair, TokenPairRef, TokenRef};
+use crate::{Chain, ChainBuilder};
+
+/// Default depth (amount of rows) of the count-min sketch.
+const DEFAULT_SKETCH_DEPTH: usize = 4;
+
+/// The most memory the count-min sketch uses, unless set using
+/// [`ApproxChainBuilder::sketch_bytes()`].
+const DEFAULT_MAX_SKETCH_BYTES: usize = 16 * 1024 * 1024;
+
+/// The longest token that is counted, unless set using [`ApproxChainBuilder::max_token_bytes()`].
+const DEFAULT_MAX_TOKEN_BYTES: usize = 64;
+
+/// Builds a [`Chain`] from an unbounded amount of text without ever growing past a fixed size.
+///
+/// Instead of counting every token exactly like [`ChainBuilder`], occurances are counted using a
+/// [count-min sketch](https://en.wikipedia.org/wiki/Count%E2%80%93min_sketch), which may
+/// overestimate counts but never uses more memory than it was created with. On top of that, at most
+/// `max_pairs` token pairs are tracked, and each pair keeps at most `max_candidates` possible next
+/// tokens. When a pair already has `max_candidates` next tokens, a new token replaces the least
+/// common one if it is estimated to be more common.
+///
+/// Once `max_pairs` pairs have been seen, new pairs are ignored. Tokens longer than 64 bytes
+/// are never counted (see [`ApproxChainBuilder::max_token_bytes()`]), so that the memory used
+/// by the tracked pairs is bounded too.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::approx::ApproxChainBuilder;
+///
+/// let mut acb = ApproxChainBuilder::new(10_000, 8);
+/// acb.feed_str("I am a firehose of text. I am never going to stop.");
+/// let chain = acb.build().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ApproxChainBuilder {
+    sketch: CountMinSketch,
+    /// The tracked next tokens of each pair, together with their estimated count.
+    candidates: HashMap<TokenPair, Vec<(Token, Weight)>>,
+    max_pairs: usize,
+    max_candidates: usize,
+    max_token_bytes: usize,
+}
+
+impl ApproxChainBuilder {
+    /// Creates a new builder that tracks at most `max_pairs` pairs, each with at most
+    /// `max_candidates` possible next tokens.
+    ///
+    /// The sketch used for counting gets one counter per possible pair and next token in each
+    /// row, but never uses more than 16 MiB; use [`ApproxChainBuilder::sketch_bytes()`] to change
+    /// it.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `max_candidates` is zero.
+    pub fn new(max_pairs: usize, max_candidates: usize) -> Self {
+        assert!(max_candidates > 0, "max_candidates must not be zero");
+        let bytes = max_pairs
+            .checked_mul(max_candidates)
+            .and_then(|width| width.checked_mul(DEFAULT_SKETCH_DEPTH))
+            .and_then(|counters| counters.checked_mul(mem::size_of::<Weight>()))
+            .map_or(DEFAULT_MAX_SKETCH_BYTES, |b| {
+                b.min(DEFAULT_MAX_SKETCH_BYTES)
+            });
+        Self {
+            sketch: CountMinSketch::with_bytes(bytes, DEFAULT_SKETCH_DEPTH),
+            candidates: HashMap::new(),
+            max_pairs,
+            max_candidates,
+            max_token_bytes: DEFAULT_MAX_TOKEN_BYTES,
+        }
+    }
+
+    /// Replaces the counting sketch with one using at most `max_bytes` bytes, split into `depth`
+    /// rows. Larger sketches overestimate less, and deeper sketches are less likely to
+    /// overestimate at all. Any counts made so far are lost.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `depth` is zero, or if `max_bytes` is too small for a single counter in each
+    /// row.
+    pub fn sketch_bytes(mut self, max_bytes: usize, depth: usize) -> Self {
+        assert!(depth > 0, "sketch depth must not be zero");
+        assert!(
+            max_bytes / depth >= mem::size_of::<Weight>(),
+            "sketch must have room for a counter in each row"
+        );
+        self.sketch = CountMinSketch::with_bytes(max_bytes, depth);
+        self
+    }
+
+    /// Sets the longest token that is counted, in bytes. Occurances where the pair or the next
+    /// token has a longer token are ignored. The default is 64.
+    pub fn max_token_bytes(mut self, max_token_bytes: usize) -> Self {
+        self.max_token_bytes = max_token_bytes;
+        self
+    }
+
+    /// Feeds the builder with text, split the same way as [`ChainBuilder::feed_str()`].
+    ///
+    /// Returns the amount of times a token was seen following a pair.
+    pub fn feed_str(&mut self, content: &str) -> usize {
+        self.feed_tokens(content.split_word_bounds())
+    }
+
+    /// Feeds the builder with pre-split tokens, like [`ChainBuilder::feed_tokens()`].
+    ///
+    /// Returns the amount of times a token was seen following a pair.
+    pub fn feed_tokens<'a, T: Iterator<Item = TokenRef<'a>>>(&mut self, tokens: T) -> usize {
+        let mut seen = 0;
+        for (left, right, next) in tokens.tuple_windows() {
+            self.add_occurance(&(left, right), next);
+            seen += 1;
+        }
+        seen
+    }
+
+    /// Add the occurance of `next` following `prev`.
+    pub fn add_occurance(&mut self, prev: &TokenPairRef<'_>, next: &str) {
+        if [prev.0, prev.1, next]
+            .iter()
+            .any(|t| t.len() > self.max_token_bytes)
+        {
+            return;
+        }
+
+        let estimate = self.sketch.increment(&(prev.0, prev.1, next));
+
+        let is_full = self.candidates.len() >= self.max_pairs;
+        let candidates = match self.candidates.get_mut(prev) {
+            Some(c) => c,
+            None if is_full => return,
+            None => self
+                .candidates
+                .entry(TokenPair::from(prev))
+                .or_insert_with(|| Vec::with_capacity(1)),
+        };
+
+        if let Some(c) = candidates.iter_mut().find(|(t, _)| t == next) {
+            c.1 = estimate;
+        } else if candidates.len() < self.max_candidates {
+            candidates.push((Token::from(next), estimate));
+        } else {
+            // Unwrap is safe, since `max_candidates` is never zero
+            let least_common = candidates.iter_mut().min_by_key(|(_, n)| *n).unwrap();
+            if least_common.1 < estimate {
+                *least_common = (Token::from(next), estimate);
+            }
+        }
+    }
+
+    /// Uses up the builder and creates a new chain, using the estimated counts.
+    ///
+    /// Will return an error if the builder have not been fed any strings.
+    pub fn build(self) -> Result<Chain, ApproxChainBuilder> {
+        if self.candidates.is_empty() {
+            return Err(self);
+        }
+
+        let mut cb = ChainBuilder::new();
+        for (pair, candidates) in self.candidates {
+            let mut b = TokenDistributionBuilder::new();
+            for (token, n) in candidates {
+                b.add_token_n(&token, n);
+            }
+            cb.map.insert(pair, b);
+        }
+
+        // Cannot fail, since we have at least one pair
+        Ok(cb.build().unwrap())
+    }
+}
+
+/// A fixed size table of counters, where each item is counted in one counter per row. The
+/// estimated count of an item is the smallest of its counters.
+#[derive(Clone, Debug)]
+struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<Weight>,
+    hasher: DefaultHashBuilder,
+}
+
+impl CountMinSketch {
+    /// Creates a sketch with `depth` rows that are as wide as possible without using more than
+    /// `max_bytes` bytes, but always with at least one counter per row.
+    fn with_bytes(max_bytes: usize, depth: usize) -> Self {
+        let width = (max_bytes / depth / mem::size_of::<Weight>()).max(1);
+        Self {
+            width,
+            depth,
+            // Cannot overflow, since it is at most `max_bytes` counters
+            counters: vec![0; width * depth],
+            hasher: DefaultHashBuilder::default(),
+        }
+    }
+
+    /// Counts `item` once more, and returns its new estimated count.
+    fn increment(&mut self, item: &impl Hash) -> Weight {
+        let mut estimate = Weight::MAX;
+        for row in 0..self.depth {
+            let column = (self.hasher.hash_one((row, item)) % self.width as u64) as usize;
+            let counter = &mut self.counters[row * self.width + column];
+            *counter = counter.saturating_add(1);
+            estimate = estimate.min(*counter);
+        }
+        estimate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApproxChainBuilder, DEFAULT_MAX_SKETCH_BYTES};
+    use crate::distribution::Weight;
+    use crate::Chain;
+
+    /// How many times `next` has been seen after `left` and `right`.
+    fn count(chain: &Chain, left: &str, right: &str, next: &str) -> Weight {
+        let (choices, weights) = chain.map[left][right].parts();
+        choices
+            .iter()
+            .position(|c| c == next)
+            .map_or(0, |i| weights[i])
+    }
+
+    #[test]
+    fn empty_approx_builder_fails() {
+        assert!(ApproxChainBuilder::new(10, 2).build().is_err());
+    }
+
+    #[test]
+    fn candidates_are_capped() {
+        let mut acb = ApproxChainBuilder::new(100, 2);
+        acb.feed_tokens("a b c a b d a b e a b c a b c".split_whitespace());
+        let chain = acb.build().unwrap();
+        assert_eq!(chain.map["a"]["b"].parts().0.len(), 2);
+        assert!(count(&chain, "a", "b", "c") > 0, "most common must be kept");
+    }
+
+    #[test]
+    fn heavy_newcomer_evicts_light_candidate() {
+        let mut acb = ApproxChainBuilder::new(100, 2);
+        // "e" is ignored the first time, since it is not more common than "d" yet
+        for next in ["c", "c", "c", "d", "e", "e"] {
+            acb.add_occurance(&("a", "b"), next);
+        }
+        let chain = acb.build().unwrap();
+        assert_eq!(
+            (
+                count(&chain, "a", "b", "c"),
+                count(&chain, "a", "b", "d"),
+                count(&chain, "a", "b", "e")
+            ),
+            (3, 0, 2)
+        );
+    }
+
+    #[test]
+    fn memory_is_bounded() {
+        // Would need far more memory than exists, if the sketch was not capped
+        let mut acb = ApproxChainBuilder::new(usize::MAX, usize::MAX).max_token_bytes(3);
+        let sketch_bytes = acb.sketch.counters.len() * std::mem::size_of::<Weight>();
+        assert_eq!(sketch_bytes, DEFAULT_MAX_SKETCH_BYTES);
+
+        acb.feed_tokens("aaa b aaaa b aaa c".split_whitespace());
+        let chain = acb.build().unwrap();
+        // Only "b" "aaa" "c" has no token longer than three bytes
+        assert_eq!(chain.pairs().count(), 1);
+        assert_eq!(count(&chain, "b", "aaa", "c"), 1);
+    }
+
+    #[test]
+    fn pairs_are_capped() {
+        let mut acb = ApproxChainBuilder::new(3, 4);
+        acb.feed_tokens("a b c d e f g h".split_whitespace());
+        assert_eq!(acb.build().unwrap().pairs().count(), 3);
+    }
+}
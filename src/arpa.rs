@@ -0,0 +1,482 @@
+//! Importing and exporting n-gram language models in the ARPA format, as used by tools such as
+//! [SRILM](http://www.speech.sri.com/projects/srilm/) and [KenLM](https://kheafield.com/code/kenlm/).
+//!
+//! ARPA files store the log probability (in base 10) of every n-gram, not how many times it was
+//! seen, so probabilities are turned into weights by multiplying them with [`ARPA_WEIGHT_SCALE`].
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use hashbrown::HashMap;
+
+use crate::distribution::Weight;
+use crate::token::TokenPair;
+use crate::{Chain, ChainBuilder};
+
+/// The token ARPA models use to mark the start of every sentence.
+pub const ARPA_BEGIN: &str = "<s>";
+
+/// The token ARPA models use to mark the end of every sentence.
+pub const ARPA_END: &str = "</s>";
+
+/// What probabilities are multiplied with to get the weight of a trigram. Trigrams are always
+/// given a weight of at least one, so no trigram is lost.
+pub const ARPA_WEIGHT_SCALE: f64 = 1_000_000.0;
+
+/// Errors that can occur when importing an ARPA model.
+#[derive(Debug)]
+pub enum ArpaError {
+    /// Failed to read the model.
+    Io(io::Error),
+    /// A line of the model could not be parsed.
+    Syntax {
+        /// The line number, starting from one.
+        line: usize,
+        /// What was wrong with the line.
+        reason: &'static str,
+    },
+    /// The model does not contain any 3-grams, so it cannot be used as a second order chain.
+    NoTrigrams,
+}
+
+impl fmt::Display for ArpaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Syntax { line, reason } => {
+                write!(f, "invalid ARPA model on line {line}: {reason}")
+            }
+            Self::NoTrigrams => write!(f, "ARPA model does not contain any 3-grams"),
+        }
+    }
+}
+
+impl std::error::Error for ArpaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ArpaError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// The part of an ARPA file being read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Section {
+    /// Anything before `\data\` is ignored.
+    Preamble,
+    /// The number of n-grams of each order.
+    Data,
+    /// The n-grams of an order.
+    NGrams(usize),
+    End,
+}
+
+impl ChainBuilder {
+    /// Creates a builder from the 3-grams of a language model in the ARPA format. Lower order
+    /// n-grams and backoff weights are not used, since a [`Chain`] only looks at 3-grams.
+    ///
+    /// ARPA models are usually made from text split on whitespace, so the tokens will be words
+    /// without any whitespace between them. 3-grams containing the sentence start and end
+    /// markers ([`ARPA_BEGIN`] and [`ARPA_END`]) are not imported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// let arpa = "\
+    /// \\data\\
+    /// ngram 1=3
+    /// ngram 3=2
+    ///
+    /// \\1-grams:
+    /// -0.5 I
+    /// -0.5 am
+    /// -0.5 here
+    ///
+    /// \\3-grams:
+    /// -0.3 I am here
+    /// -0.3 <s> I am
+    ///
+    /// \\end\\
+    /// ";
+    /// let chain = ChainBuilder::from_arpa(arpa.as_bytes()).unwrap().build().unwrap();
+    /// assert_eq!(chain.pairs().collect::<Vec<_>>(), vec![("I", "am")]);
+    /// ```
+    pub fn from_arpa(reader: impl BufRead) -> Result<Self, ArpaError> {
+        let mut section = Section::Preamble;
+        let mut declared: HashMap<usize, usize> = HashMap::new();
+        let mut found: HashMap<usize, usize> = HashMap::new();
+        let mut cb = ChainBuilder::new();
+        let mut line_number = 0;
+
+        for line in reader.lines() {
+            line_number += 1;
+            let syntax = |reason| ArpaError::Syntax {
+                line: line_number,
+                reason,
+            };
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line == "\\data\\" {
+                section = Section::Data;
+                continue;
+            }
+            if line == "\\end\\" {
+                section = Section::End;
+                break;
+            }
+            if let Some(order) = line
+                .strip_prefix('\\')
+                .and_then(|l| l.strip_suffix("-grams:"))
+            {
+                let order = order.parse().map_err(|_| syntax("invalid n-gram order"))?;
+                section = Section::NGrams(order);
+                continue;
+            }
+
+            match section {
+                Section::Preamble | Section::End => {}
+                Section::Data => {
+                    let (order, count) = line
+                        .strip_prefix("ngram ")
+                        .and_then(|l| l.split_once('='))
+                        .ok_or(syntax("expected `ngram N=COUNT`"))?;
+                    let order = order.trim().parse().map_err(|_| syntax("invalid order"))?;
+                    let count = count.trim().parse().map_err(|_| syntax("invalid count"))?;
+                    declared.insert(order, count);
+                }
+                Section::NGrams(order) => {
+                    *found.entry(order).or_default() += 1;
+                    if order != 3 {
+                        continue;
+                    }
+
+                    let mut fields = line.split_whitespace();
+                    let log_prob: f64 = fields
+                        .next()
+                        .and_then(|p| p.parse().ok())
+                        .ok_or(syntax("invalid log probability"))?;
+                    let (Some(left), Some(right), Some(next)) =
+                        (fields.next(), fields.next(), fields.next())
+                    else {
+                        return Err(syntax("expected three words"));
+                    };
+
+                    if [left, right, next]
+                        .iter()
+                        .any(|t| *t == ARPA_BEGIN || *t == ARPA_END)
+                    {
+                        continue;
+                    }
+
+                    let weight = (10_f64.powf(log_prob) * ARPA_WEIGHT_SCALE).round();
+                    // `as` saturates, so probabilities that are too large are capped
+                    let weight = (weight as Weight).max(1);
+                    cb.map
+                        .entry(TokenPair::new(left, right))
+                        .or_default()
+                        .add_token_n(next, weight);
+                }
+            }
+        }
+
+        let syntax = |reason| ArpaError::Syntax {
+            line: line_number,
+            reason,
+        };
+        if section != Section::End {
+            return Err(syntax("missing `\\end\\`"));
+        }
+        if declared
+            .iter()
+            .any(|(order, n)| found.get(order).copied().unwrap_or(0) != *n)
+        {
+            return Err(syntax("number of n-grams does not match `\\data\\`"));
+        }
+        if !declared.contains_key(&3) {
+            return Err(ArpaError::NoTrigrams);
+        }
+
+        Ok(cb)
+    }
+}
+
+/// What whitespace in tokens is replaced with when exporting, since ARPA uses whitespace to
+/// separate words. The same character is used by
+/// [SentencePiece](https://github.com/google/sentencepiece).
+pub const ARPA_SPACE: char = '\u{2581}';
+
+/// Formats a token as an ARPA word, replacing any whitespace with [`ARPA_SPACE`].
+fn arpa_word(token: &str) -> String {
+    token
+        .chars()
+        .map(|c| if c.is_whitespace() { ARPA_SPACE } else { c })
+        .collect()
+}
+
+impl Chain {
+    /// Writes the chain as a language model in the ARPA format, without backoff weights. The
+    /// 3-grams are the transitions of the chain, and the 2-grams and 1-grams are counted from
+    /// the same transitions. All probabilities are maximum likelihood estimates.
+    ///
+    /// ARPA words cannot contain whitespace, so whitespace in tokens is replaced with
+    /// [`ARPA_SPACE`]. Chains fed with tokens split on whitespace (such as with
+    /// [`Words`](crate::tokenize::Words)) are therefore the best fit for other tools.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let cb = ChainBuilder::new().feed_tokens("I am a cat".split_whitespace()).into_cb();
+    /// let chain = cb.build().unwrap();
+    /// let mut arpa = Vec::new();
+    /// chain.to_arpa(&mut arpa).unwrap();
+    ///
+    /// let arpa = String::from_utf8(arpa).unwrap();
+    /// assert!(arpa.contains("ngram 3=2"));
+    /// assert!(arpa.contains("0.000000\tI am a\n"));
+    /// ```
+    pub fn to_arpa(&self, writer: impl Write) -> io::Result<()> {
+        self.write_arpa(writer, None)
+    }
+
+    /// Like [`Chain::to_arpa()`], but probabilities are smoothed using absolute discounting:
+    /// `discount` is subtracted from every count, and the probability left over is given to
+    /// unseen continuations using backoff weights. This lets other tools score text with
+    /// n-grams the chain has never seen.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `discount` is not between zero and one (exclusive).
+    pub fn to_arpa_with_backoff(&self, writer: impl Write, discount: f64) -> io::Result<()> {
+        assert!(
+            discount > 0.0 && discount < 1.0,
+            "discount must be between zero and one"
+        );
+        self.write_arpa(writer, Some(discount))
+    }
+
+    fn write_arpa(&self, writer: impl Write, discount: Option<f64>) -> io::Result<()> {
+        let d = discount.unwrap_or(0.0);
+
+        // Counts of lower orders, taken from the last tokens of every transition
+        let mut unigrams: HashMap<&str, u64> = HashMap::new();
+        let mut bigrams: HashMap<(&str, &str), u64> = HashMap::new();
+        for (left, rights) in &self.map {
+            for (right, dist) in rights {
+                for (next, n) in dist.iter() {
+                    *bigrams.entry((right.as_str(), next.as_str())).or_default() += n as u64;
+                    *unigrams.entry(next.as_str()).or_default() += n as u64;
+                }
+                // The first pair of a text is never the end of a transition
+                bigrams.entry((left.as_str(), right.as_str())).or_insert(1);
+                unigrams.entry(left.as_str()).or_insert(1);
+                unigrams.entry(right.as_str()).or_insert(1);
+            }
+        }
+
+        // The total count and number of continuations of every bigram context
+        let mut contexts: HashMap<&str, (u64, u64)> = HashMap::new();
+        for ((first, _), n) in &bigrams {
+            let (total, types) = contexts.entry(first).or_default();
+            *total += n;
+            *types += 1;
+        }
+        let unigram_total: u64 = unigrams.values().sum();
+
+        let p_unigram = |w: &str| unigrams[w] as f64 / unigram_total as f64;
+        let p_bigram = |first: &str, second: &str| {
+            (bigrams[&(first, second)] as f64 - d) / contexts[first].0 as f64
+        };
+
+        // The backoff weight of a context, given its total count, number of continuations and
+        // the lower order probabilities of those continuations
+        let backoff = |total: u64, types: u64, seen: f64| {
+            let left_over = d * types as f64 / total as f64;
+            (left_over / (1.0 - seen).max(f64::EPSILON)).log10()
+        };
+
+        // The unigram probability of every continuation of a bigram context
+        let mut seen_unigrams: HashMap<&str, f64> = HashMap::new();
+        for (first, second) in bigrams.keys() {
+            *seen_unigrams.entry(first).or_default() += p_unigram(second);
+        }
+
+        let mut w = io::BufWriter::new(writer);
+        let trigram_count: usize = self
+            .map
+            .values()
+            .flat_map(|r| r.values())
+            .map(|d| d.len())
+            .sum();
+        writeln!(w, "\\data\\")?;
+        writeln!(w, "ngram 1={}", unigrams.len())?;
+        writeln!(w, "ngram 2={}", bigrams.len())?;
+        writeln!(w, "ngram 3={trigram_count}")?;
+
+        writeln!(w, "\n\\1-grams:")?;
+        let mut sorted: Vec<_> = unigrams.keys().copied().collect();
+        sorted.sort_unstable();
+        for word in sorted {
+            write!(w, "{:.6}\t{}", p_unigram(word).log10(), arpa_word(word))?;
+            match (contexts.get(word), seen_unigrams.get(word)) {
+                (Some(&(total, types)), Some(&seen)) if discount.is_some() => {
+                    writeln!(w, "\t{:.6}", backoff(total, types, seen))?;
+                }
+                _ => writeln!(w)?,
+            }
+        }
+
+        writeln!(w, "\n\\2-grams:")?;
+        let mut sorted: Vec<_> = bigrams.keys().copied().collect();
+        sorted.sort_unstable();
+        for (first, second) in sorted {
+            let p = p_bigram(first, second).log10();
+            write!(w, "{p:.6}\t{} {}", arpa_word(first), arpa_word(second))?;
+            match self.get_distribution(&(first, second)) {
+                Some(dist) if discount.is_some() => {
+                    let seen = dist.iter().map(|(next, _)| p_bigram(second, next)).sum();
+                    let bow = backoff(dist.total(), dist.len() as u64, seen);
+                    writeln!(w, "\t{bow:.6}")?;
+                }
+                _ => writeln!(w)?,
+            }
+        }
+
+        writeln!(w, "\n\\3-grams:")?;
+        let mut pairs: Vec<_> = self.pairs().collect();
+        pairs.sort_unstable();
+        for pair in pairs {
+            // Unwrap is safe, since the pair is from the chain
+            let dist = self.get_distribution(&pair).unwrap();
+            let total = dist.total() as f64;
+            let mut nexts: Vec<_> = dist.iter().collect();
+            nexts.sort_unstable();
+            for (next, n) in nexts {
+                let p = ((n as f64 - d) / total).log10();
+                writeln!(
+                    w,
+                    "{p:.6}\t{} {} {}",
+                    arpa_word(pair.0),
+                    arpa_word(pair.1),
+                    arpa_word(next)
+                )?;
+            }
+        }
+
+        writeln!(w, "\n\\end\\")?;
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::ArpaError;
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    const MODEL: &str = "Made by hand
+
+\\data\\
+ngram 1=4
+ngram 2=2
+ngram 3=3
+
+\\1-grams:
+-0.6 <s> -0.2
+-0.6 I -0.2
+-0.6 am -0.2
+-0.6 here
+
+\\2-grams:
+-0.1 I am -0.1
+-0.1 am here
+
+\\3-grams:
+-0.1 I am here
+-1 I am there
+-0.3 <s> I am
+
+\\end\\
+";
+
+    #[test]
+    fn import_trigrams() {
+        let chain = ChainBuilder::from_arpa(MODEL.as_bytes())
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(chain.pairs().count(), 1);
+        // 10^-0.1 vs 10^-1
+        let p = chain.probability(&("I", "am"), "here").unwrap();
+        assert!((p - 0.794 / (0.794 + 0.1)).abs() < 0.001);
+        assert!(chain
+            .generate_next_token(&mut thread_rng(), &("I", "am"))
+            .is_some());
+    }
+
+    #[test]
+    fn reject_broken_models() {
+        let missing_end = MODEL.replace("\\end\\", "");
+        assert!(matches!(
+            ChainBuilder::from_arpa(missing_end.as_bytes()),
+            Err(ArpaError::Syntax { line: 23, .. })
+        ));
+
+        let wrong_count = MODEL.replace("ngram 3=3", "ngram 3=4");
+        assert!(matches!(
+            ChainBuilder::from_arpa(wrong_count.as_bytes()),
+            Err(ArpaError::Syntax { .. })
+        ));
+
+        let bigrams = "\\data\\\nngram 1=1\n\n\\1-grams:\n-1 a\n\\end\\\n";
+        assert!(matches!(
+            ChainBuilder::from_arpa(bigrams.as_bytes()),
+            Err(ArpaError::NoTrigrams)
+        ));
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let text = "I am a cat and I am a dog and I was a cat";
+        let chain = ChainBuilder::new()
+            .feed_tokens(text.split_whitespace())
+            .into_cb()
+            .build()
+            .unwrap();
+
+        let mut arpa = Vec::new();
+        chain.to_arpa(&mut arpa).unwrap();
+        let imported = ChainBuilder::from_arpa(arpa.as_slice())
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(imported.pairs().count(), chain.pairs().count());
+        let p = imported.probability(&("I", "am"), "a").unwrap();
+        assert!((p - 1.0).abs() < 1e-6);
+        let p = imported.probability(&("a", "cat"), "and").unwrap();
+        assert!((p - 1.0).abs() < 1e-6);
+
+        let mut arpa = Vec::new();
+        chain.to_arpa_with_backoff(&mut arpa, 0.5).unwrap();
+        let arpa = String::from_utf8(arpa).unwrap();
+        // "I am" has continuations, so it has a backoff weight
+        let line = arpa.lines().find(|l| l.contains("\tI am\t"));
+        assert_eq!(line.map(|l| l.split('\t').count()), Some(3));
+        assert!(ChainBuilder::from_arpa(arpa.as_bytes()).is_ok());
+    }
+}
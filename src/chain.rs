@@ -1,21 +1,28 @@
 //! See the top level crate documentation for information about the [`Chain`] type.
 
-use hashbrown::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+
+use hashbrown::{HashMap, HashSet};
 
 use itertools::Itertools;
-use rand::seq::IteratorRandom;
 use rand::Rng;
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::distribution::{TokenDistribution, TokenDistributionBuilder};
-use crate::token::{TokenPair, TokenPairRef, TokenRef};
+use crate::distribution::{TokenDistribution, TokenDistributionBuilder, Weight};
+use crate::sentence::SentenceBounds;
+use crate::store::{TransitionStore, Walk};
+use crate::token::{Token, TokenPair, TokenPairRef, TokenRef};
 
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 /// Simple second order Markov chain. This chain might behave in ways you do not expect; Since we
-/// are looking at [`Token`](crate::token::Token)s, and not words. If this is not desired, you
-/// can use your own splitting of tokens and use [`ChainBuilder::feed_tokens()`].
+/// are looking at [`Token`]s, and not words. If this is not desired, you can use your own
+/// splitting of tokens and use [`ChainBuilder::feed_tokens()`].
 ///
 /// ```
 /// # use markovish::{Chain, ChainBuilder};
@@ -41,7 +48,22 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Chain {
-    map: HashMap<TokenPair, TokenDistribution>,
+    /// Maps the left token of a pair to all right tokens it has been seen with, which in turn
+    /// maps to the distribution of the next token. This way the left token is only stored once,
+    /// and all pairs starting with the same token can be found quickly.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::sorted::nested_map"))]
+    pub(crate) map: HashMap<Token, HashMap<Token, TokenDistribution>>,
+    /// Where sentences start and end, if fed using [`ChainBuilder::feed_sentences()`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) sentences: SentenceBounds,
+    /// The left tokens of all pairs with each right token, used by [`Chain::pairs_containing()`].
+    /// Created the first time it is needed, and cleared whenever the pairs change.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) lefts_by_right: OnceLock<HashMap<Token, Vec<Token>>>,
+    /// Every pair in sorted order, used by [`Chain::start_tokens()`]. Created the first time it
+    /// is needed, and cleared whenever the pairs change.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) pair_index: OnceLock<Vec<TokenPair>>,
 }
 impl Chain {
     /// Creates a new second order Markov chain from a string.
@@ -58,6 +80,231 @@ impl Chain {
         ChainBuilder::new()
     }
 
+    /// Turns the chain back into a builder with the same counts, so that it can be fed more text.
+    /// This way only the chain needs to be saved, and not the builder it was built from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// let chain = chain.into_builder().feed_str("I am a dog").into_cb().build().unwrap();
+    /// assert_eq!(chain.probability(&("a", " "), "dog"), Some(0.5));
+    /// ```
+    pub fn into_builder(self) -> ChainBuilder {
+        let map = self
+            .map
+            .into_iter()
+            .flat_map(|(left, rights)| {
+                rights
+                    .into_iter()
+                    .map(move |(right, dist)| (TokenPair(left.clone(), right), dist.into_builder()))
+            })
+            .collect();
+        ChainBuilder {
+            map,
+            sentences: self.sentences,
+        }
+    }
+
+    /// Creates a chain with only the pairs that can be reached when generating from `start`
+    /// without restarting, such as to carve a small chain about a single topic out of a large
+    /// one. Sentence bounds of pairs that cannot be reached are removed.
+    ///
+    /// Returns `None` if the chain does not contain `start`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("Cats purr a lot").unwrap();
+    /// let sub = chain.subchain_from(&("purr", " ")).unwrap();
+    /// assert_eq!(sub.pairs().count(), 3);
+    /// assert!(!sub.contains_token("Cats"));
+    /// ```
+    pub fn subchain_from(&self, start: &TokenPairRef<'_>) -> Option<Chain> {
+        let (left, rights) = self.map.get_key_value(start.0)?;
+        let (right, _) = rights.get_key_value(start.1)?;
+
+        let mut visited: HashSet<TokenPairRef<'_>> = HashSet::new();
+        let mut stack = vec![(left.as_str(), right.as_str())];
+        let mut map: HashMap<Token, HashMap<Token, TokenDistribution>> = HashMap::new();
+        while let Some(pair) = stack.pop() {
+            if !visited.insert(pair) {
+                continue;
+            }
+            let Some(dist) = self.get_distribution(&pair) else {
+                continue;
+            };
+            map.entry(Token::from(pair.0))
+                .or_default()
+                .insert(Token::from(pair.1), dist.clone());
+            stack.extend(dist.iter().map(|(next, _)| (pair.1, next.as_str())));
+        }
+
+        let mut sentences = self.sentences.clone();
+        sentences.retain(|pair| visited.contains(&pair.as_ref()));
+        Some(Chain::from_parts(map, sentences))
+    }
+
+    /// Keeps only the pairs for which `f` returns `true`, such as to remove pairs containing
+    /// unwanted tokens without feeding the text again. Sentence starts of removed pairs are
+    /// removed as well.
+    ///
+    /// Tokens are only removed as pairs; a removed token can still be generated by the
+    /// distribution of another pair.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let mut chain = Chain::from_text("I am 42 years old").unwrap();
+    /// let has_digits = |t: &str| t.contains(char::is_numeric);
+    /// chain.retain(|(left, right), _| !has_digits(left) && !has_digits(right));
+    /// assert_eq!(chain.pairs().count(), 5);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(TokenPairRef<'_>, &TokenDistribution) -> bool,
+    {
+        self.map.retain(|left, rights| {
+            rights.retain(|right, dist| f((left, right), dist));
+            !rights.is_empty()
+        });
+        self.clear_indexes();
+        let map = &self.map;
+        self.sentences.retain_starts(|TokenPair(left, right)| {
+            map.get(left).is_some_and(|r| r.contains_key(right))
+        });
+    }
+
+    /// Replaces every token with what `f` returns for it, such as to lowercase or redact tokens
+    /// before sharing a chain. If several tokens are replaced with the same token, their counts
+    /// are added together. `f` is only called once for every unique token.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("The cat. the dog. THE cat.").unwrap();
+    /// let chain = chain.map_tokens(|t| t.to_lowercase().into());
+    /// assert_eq!(chain.count(&("the", " "), "cat"), Some(2));
+    /// assert_eq!(chain.count(&("the", " "), "dog"), Some(1));
+    /// ```
+    pub fn map_tokens<F: FnMut(&str) -> Token>(mut self, f: F) -> Chain {
+        self.edit_as_builder(|cb| *cb = std::mem::take(cb).map_tokens(f));
+        self
+    }
+
+    /// Creates a chain with only the transitions found in both `self` and `other`, such as to
+    /// find the language two authors have in common. How the counts of the two chains are
+    /// combined is chosen using `weight`.
+    ///
+    /// Returns `None` if the chains have no transitions in common.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// use markovish::chain::IntersectWeight;
+    ///
+    /// let alice = Chain::from_text("I think that I think so").unwrap();
+    /// let bob = Chain::from_text("I think not").unwrap();
+    /// let common = alice.intersect(&bob, IntersectWeight::Min).unwrap();
+    /// assert_eq!(common.count(&("I", " "), "think"), Some(1));
+    /// assert!(!common.contains_token("that"));
+    ///
+    /// let common = alice.intersect(&bob, IntersectWeight::Product).unwrap();
+    /// assert_eq!(common.count(&("I", " "), "think"), Some(2));
+    /// ```
+    pub fn intersect(&self, other: &Chain, weight: IntersectWeight) -> Option<Chain> {
+        let mut cb = ChainBuilder::new();
+        for (left, rights) in &self.map {
+            let Some(other_rights) = other.map.get(left) else {
+                continue;
+            };
+            for (right, dist) in rights {
+                let Some(other_dist) = other_rights.get(right) else {
+                    continue;
+                };
+                for (token, n) in dist.iter() {
+                    let m = other_dist.count(token);
+                    if m == 0 {
+                        continue;
+                    }
+                    let combined = match weight {
+                        IntersectWeight::Min => n.min(m),
+                        IntersectWeight::Product => n.saturating_mul(m),
+                    };
+                    cb.map
+                        .entry(TokenPair(left.clone(), right.clone()))
+                        .or_default()
+                        .add_token_n(token, combined);
+                }
+            }
+        }
+
+        cb.sentences = self.sentences.intersection(&other.sentences);
+        cb.build().ok()
+    }
+
+    /// Removes `token` from the chain, so that it can never be generated again. Pairs containing
+    /// the token are removed, as are the sentence bounds containing it. Returns `false` if the
+    /// token was not in the chain.
+    ///
+    /// Text that used to continue through the token may now stop there. Use
+    /// [`Chain::remove_token_relinked()`] to instead continue as if the token had never been in
+    /// the text.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let mut chain = Chain::from_text("You are a dumb cat").unwrap();
+    /// assert!(chain.remove_token("dumb"));
+    /// assert!(!chain.contains_token("dumb"));
+    /// assert!(!chain.remove_token("dumb"));
+    /// ```
+    pub fn remove_token(&mut self, token: &str) -> bool {
+        self.edit_as_builder(|cb| cb.remove_token(token))
+    }
+
+    /// Removes `token` like [`Chain::remove_token()`], but links the tokens on each side of it,
+    /// as if it had never been in the text. The counts of the new transitions are estimated from
+    /// the counts of the removed ones, so they are only exact if the token was always
+    /// surrounded by the same tokens.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let mut chain = Chain::from_text("You are a very good cat").unwrap();
+    /// assert!(chain.remove_token_relinked("very"));
+    /// assert_eq!(chain.count(&("a", " "), " "), Some(1));
+    /// assert_eq!(chain.count(&(" ", " "), "good"), Some(1));
+    /// ```
+    pub fn remove_token_relinked(&mut self, token: &str) -> bool {
+        self.edit_as_builder(|cb| cb.remove_token_relinked(token))
+    }
+
+    /// Runs `f` on the chain as a [`ChainBuilder`], and then builds it again.
+    fn edit_as_builder<T>(&mut self, f: impl FnOnce(&mut ChainBuilder) -> T) -> T {
+        let chain = std::mem::replace(
+            self,
+            Chain::from_parts(HashMap::new(), SentenceBounds::default()),
+        );
+        let mut cb = chain.into_builder();
+        let res = f(&mut cb);
+        *self = match cb.build() {
+            Ok(chain) => chain,
+            // Everything was removed
+            Err(cb) => Chain::from_parts(HashMap::new(), cb.sentences),
+        };
+        res
+    }
+
     /// Returns an iterator of all pairs that have been found in the source text(s). When calling
     /// [`Chain::start_tokens()`], a [`TokenPair`] is randomly chosen from this list.
     ///
@@ -71,12 +318,141 @@ impl Chain {
     /// # use markovish::Chain;
     /// let chain = Chain::from_text("I am but a tiny example! I have three sentences. U?").unwrap();
     /// let good_starting_points: Vec<_> = chain.pairs()
-    ///                                         .filter(|tp| tp.0.as_str() == "." || tp.0.as_str() == "!")
+    ///                                         .filter(|tp| tp.0 == "." || tp.0 == "!")
     ///                                         .collect();
     /// assert_eq!(good_starting_points.len(), 2);
     /// ```
-    pub fn pairs(&self) -> impl Iterator<Item = &TokenPair> {
-        self.map.keys()
+    pub fn pairs(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        self.map.iter().flat_map(|(left, rights)| {
+            rights
+                .keys()
+                .map(move |right| (left.as_str(), right.as_str()))
+        })
+    }
+
+    /// Returns an iterator of all pairs that have `first` as their left token. Unlike filtering
+    /// [`Chain::pairs()`], this does not have to look at every pair in the chain: pairs are
+    /// stored grouped by their left token, so finding them is a single lookup and the iterator
+    /// only ever visits the matching pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am here. I have been here.").unwrap();
+    /// let mut after_i: Vec<_> = chain.pairs_with_first("I").collect();
+    /// after_i.sort();
+    /// assert_eq!(after_i, vec![("I", " ")]);
+    /// ```
+    pub fn pairs_with_first<'a>(&'a self, first: &str) -> impl Iterator<Item = TokenPairRef<'a>> {
+        self.map
+            .get_key_value(first)
+            .into_iter()
+            .flat_map(|(left, rights)| {
+                rights
+                    .keys()
+                    .map(move |right| (left.as_str(), right.as_str()))
+            })
+    }
+
+    /// Returns an iterator of all pairs that contain `token`, as either their left or right
+    /// token. Each pair is only returned once.
+    ///
+    /// Pairs with `token` as their right token are found using an index of the chain, which is
+    /// created the first time this is called. Later calls only have to look up the pairs, so
+    /// finding the pairs of a keyword does not require looking at every pair of a large chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am here. Am I?").unwrap();
+    /// let mut with_i: Vec<_> = chain.pairs_containing("I").collect();
+    /// with_i.sort();
+    /// assert_eq!(with_i, vec![(" ", "I"), ("I", " ")]);
+    /// ```
+    pub fn pairs_containing<'a>(&'a self, token: &str) -> impl Iterator<Item = TokenPairRef<'a>> {
+        let with_first = self.pairs_with_first(token);
+        let with_second = self
+            .pairs_with_second(token)
+            // Already returned as a pair with the token first
+            .filter(|(left, right)| left != right);
+        with_first.chain(with_second)
+    }
+
+    /// Returns an iterator of all pairs with `second` as their right token, using the index of
+    /// [`Chain::pairs_containing()`].
+    pub(crate) fn pairs_with_second<'a>(
+        &'a self,
+        second: &str,
+    ) -> impl Iterator<Item = TokenPairRef<'a>> {
+        self.lefts_by_right()
+            .get_key_value(second)
+            .into_iter()
+            .flat_map(|(right, lefts)| {
+                lefts
+                    .iter()
+                    .map(move |left| (left.as_str(), right.as_str()))
+            })
+    }
+
+    /// Returns the left tokens of all pairs with each right token, creating the index if needed.
+    fn lefts_by_right(&self) -> &HashMap<Token, Vec<Token>> {
+        self.lefts_by_right.get_or_init(|| {
+            let mut index: HashMap<Token, Vec<Token>> = HashMap::new();
+            for (left, rights) in &self.map {
+                for right in rights.keys() {
+                    match index.get_mut(right) {
+                        Some(lefts) => lefts.push(left.clone()),
+                        None => {
+                            index.insert(right.clone(), vec![left.clone()]);
+                        }
+                    }
+                }
+            }
+            index
+        })
+    }
+
+    /// Returns an iterator of every unique token in the chain, in no particular order. Each token
+    /// is only returned once, no matter where in a pair or how often it was seen.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am, I am").unwrap();
+    /// let mut tokens: Vec<_> = chain.tokens().collect();
+    /// tokens.sort();
+    /// assert_eq!(tokens, vec![" ", ",", "I", "am"]);
+    /// ```
+    pub fn tokens(&self) -> impl Iterator<Item = TokenRef<'_>> {
+        let mut tokens: HashSet<&str> = HashSet::new();
+        for (left, rights) in &self.map {
+            tokens.insert(left);
+            for (right, dist) in rights {
+                tokens.insert(right);
+                tokens.extend(dist.iter().map(|(t, _)| t.as_str()));
+            }
+        }
+        tokens.into_iter()
+    }
+
+    /// Returns `true` if `token` is anywhere in the chain, either in a pair or following one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// assert!(chain.contains_token("cat"));
+    /// assert!(!chain.contains_token("dog"));
+    /// ```
+    pub fn contains_token(&self, token: &str) -> bool {
+        self.map.contains_key(token)
+            || self.map.values().any(|rights| {
+                rights.contains_key(token) || rights.values().any(|dist| dist.count(token) > 0)
+            })
     }
 
     /// Randomly chooses two tokens that are known to be able to generate a new token. If no
@@ -85,8 +461,16 @@ impl Chain {
     /// While this is an easy way, the returned value can be any two pairs of token in
     /// the source text. If you need more control, you could first filter on [`Chain::pairs()`],
     /// and then randomly choose starting tokens from that subset.
-    pub fn start_tokens(&self, rng: &mut impl Rng) -> Option<&TokenPair> {
-        self.pairs().choose(rng)
+    ///
+    /// The pairs are chosen from a sorted index of the chain, which is created the first time
+    /// this is called. Later calls run in constant time, and two chains with the same pairs
+    /// choose the same pair when given random number generators in the same state.
+    pub fn start_tokens<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenPairRef<'_>> {
+        let pairs = self.pair_index();
+        if pairs.is_empty() {
+            return None;
+        }
+        Some(pairs[rng.gen_range(0..pairs.len())].as_ref())
     }
 
     /// Generates a string with `n` tokens, randomly choosing a starting point.
@@ -95,23 +479,214 @@ impl Chain {
     /// ```
     /// # let s = "I am an example string hello I very cool";
     /// ```
-    pub fn generate_str(&self, rng: &mut impl Rng, n: usize) -> Option<Vec<&str>> {
+    pub fn generate_str<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<&str>> {
         let start = self.start_tokens(rng)?;
-        self.generate_n_tokens(rng, &start.as_ref(), n)
+        self.generate_n_tokens(rng, &start, n)
+    }
+
+    /// Like [`Chain::generate_str()`], but the tokens are joined into a [`String`] that does not
+    /// borrow from the chain, so it can outlive it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// fn fortune() -> String {
+    ///     let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    ///     chain.generate_string(&mut thread_rng(), 10).unwrap()
+    /// }
+    ///
+    /// assert!(!fortune().is_empty());
+    /// ```
+    pub fn generate_string<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<String> {
+        if self.map.is_empty() {
+            return None;
+        }
+
+        let mut s = String::new();
+        // Writing to a `String` never fails
+        self.generate_fmt(rng, n, &mut s).ok()?;
+        Some(s)
+    }
+
+    /// Like [`Chain::generate_str()`], but generates tokens until `n` of them are words, instead
+    /// of `n` tokens in total. A word is any token containing a letter or a digit, so whitespace
+    /// and punctuation are not counted. Since about half of the tokens from
+    /// [`ChainBuilder::feed_str()`] are whitespace, this makes it easier to ask for a length.
+    ///
+    /// The last token is always the `n`th word. If the chain has no words at all, `None` is
+    /// returned. Generation restarts from new start tokens at dead ends, and also when more
+    /// tokens in a row than there are pairs are not words, so that loops of punctuation are left.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat, and you are a dog.").unwrap();
+    /// let text = chain.generate_n_words(&mut thread_rng(), 5).unwrap();
+    /// let words = text.iter().filter(|t| t.contains(char::is_alphanumeric)).count();
+    /// assert_eq!(words, 5);
+    /// ```
+    pub fn generate_n_words<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<&str>> {
+        let is_word = |token: &str| token.contains(char::is_alphanumeric);
+        // Otherwise we would never stop
+        let has_word = self.map.iter().any(|(left, rights)| {
+            is_word(left)
+                || rights.iter().any(|(right, dist)| {
+                    is_word(right) || dist.iter().any(|(token, _)| is_word(token))
+                })
+        });
+        if !has_word {
+            return None;
+        }
+
+        // Going longer than this without a word means we are stuck in a part of the chain
+        // without any, such as a loop of punctuation, so we restart like at a dead end
+        let max_without_word = self.pair_index().len();
+        let mut walk = Walk::new(Some(self.start_tokens(rng)?));
+        let mut res = Vec::new();
+        let mut words = 0;
+        let mut without_word = 0;
+        while words < n {
+            if without_word > max_without_word {
+                walk.start_over();
+                without_word = 0;
+            }
+            let Some(next) = walk.step_in(self, rng) else {
+                break;
+            };
+            res.push(next);
+            if is_word(next) {
+                words += 1;
+                without_word = 0;
+            } else {
+                without_word += 1;
+            }
+        }
+        Some(res)
+    }
+
+    /// Generates `samples` independent strings of `tokens_per_sample` tokens each, like calling
+    /// [`Chain::generate_string()`] `samples` times. The same buffer is used for the tokens of
+    /// every sample, which makes generating many short candidates faster.
+    ///
+    /// If the chain is empty, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let candidates = chain.generate_batch(&mut thread_rng(), 5, 10).unwrap();
+    /// assert_eq!(candidates.len(), 5);
+    /// ```
+    pub fn generate_batch<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        samples: usize,
+        tokens_per_sample: usize,
+    ) -> Option<Vec<String>> {
+        if self.map.is_empty() {
+            return None;
+        }
+
+        let mut tokens = Vec::with_capacity(tokens_per_sample);
+        let batch = (0..samples)
+            .map(|_| {
+                let start = self.start_tokens(rng);
+                self.fill_tokens(rng, start, tokens_per_sample, &mut tokens);
+                tokens.concat()
+            })
+            .collect();
+        Some(batch)
+    }
+
+    /// Like [`Chain::generate_str()`], but each of the `n` tokens is written directly to `out`
+    /// instead of being collected into a vector. Useful for writing into a pre-allocated
+    /// [`String`], or inside of a [`Display`](fmt::Display) implementation.
+    ///
+    /// Only fails if writing to `out` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let mut s = String::with_capacity(1024);
+    /// chain.generate_fmt(&mut thread_rng(), 100, &mut s).unwrap();
+    /// assert!(!s.is_empty());
+    /// ```
+    pub fn generate_fmt<R: Rng + ?Sized, W: fmt::Write + ?Sized>(
+        &self,
+        rng: &mut R,
+        n: usize,
+        out: &mut W,
+    ) -> fmt::Result {
+        let Some(start) = self.start_tokens(rng) else {
+            return Ok(());
+        };
+
+        let mut walk = Walk::new(Some(start));
+        for _ in 0..n {
+            let Some(token) = walk.step_in(self, rng) else {
+                break;
+            };
+            out.write_str(token)?;
+        }
+
+        Ok(())
     }
 
     /// Generates a random new token using the previous tokens.
     ///
     /// If the chain has never seen the `prev` tokens together, `None` is returned.
-    pub fn generate_next_token(
+    pub fn generate_next_token<R: Rng + ?Sized>(
         &self,
-        rng: &mut impl Rng,
+        rng: &mut R,
         prev: &TokenPairRef<'_>,
     ) -> Option<TokenRef<'_>> {
-        let dist = self.map.get(prev)?;
+        let dist = self.get_distribution(prev)?;
         Some(dist.get_random_token(rng))
     }
 
+    /// Creates a chain from its pairs and sentence bounds.
+    pub(crate) fn from_parts(
+        map: HashMap<Token, HashMap<Token, TokenDistribution>>,
+        sentences: SentenceBounds,
+    ) -> Self {
+        Self {
+            map,
+            sentences,
+            lefts_by_right: OnceLock::new(),
+            pair_index: OnceLock::new(),
+        }
+    }
+
+    /// Forgets the indexes of the chain, which must be done whenever its pairs change. They are
+    /// created again the next time they are needed.
+    pub(crate) fn clear_indexes(&mut self) {
+        self.lefts_by_right.take();
+        self.pair_index.take();
+    }
+
+    /// Returns every pair in sorted order, creating the index if needed.
+    pub(crate) fn pair_index(&self) -> &[TokenPair] {
+        self.pair_index.get_or_init(|| {
+            let mut pairs: Vec<TokenPair> = self.pairs().map(|tp| TokenPair::from(&tp)).collect();
+            pairs.sort_unstable();
+            pairs
+        })
+    }
+
+    /// Finds the distribution of tokens that can follow `prev`, if any.
+    pub(crate) fn get_distribution(&self, prev: &TokenPairRef<'_>) -> Option<&TokenDistribution> {
+        self.map.get(prev.0)?.get(prev.1)
+    }
+
     /// Generates `n` tokens, using previously used tokens to generate new ones. If two tokens are found that have never been seen before,
     /// two new starting tokens are generated using [`Chain::start_tokens()`].
     ///
@@ -120,9 +695,179 @@ impl Chain {
     /// # Panics
     ///
     /// Will panic if `n` is so big no vector can hold that many elements.
-    pub fn generate_n_tokens(
+    pub fn generate_n_tokens<R: Rng + ?Sized>(
         &self,
-        rng: &mut impl Rng,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+        n: usize,
+    ) -> Option<Vec<TokenRef<'_>>> {
+        if n < 1 {
+            return Some(Vec::new());
+        }
+
+        let prev = self.known_pair(prev)?;
+        self.generate_tokens(rng, &prev, n)
+    }
+
+    /// Like [`Chain::generate_n_tokens()`], but generation is never restarted from new start
+    /// tokens. Instead, if there is no possible next token, generation stops and the tokens
+    /// generated so far are returned as a [`GenerationOutcome::DeadEnd`]. This way the text
+    /// never jumps to an unrelated part of the source text.
+    ///
+    /// If the chain has never seen the `prev` tokens together, nothing can be generated and an
+    /// empty [`GenerationOutcome::DeadEnd`] is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::chain::GenerationOutcome;
+    ///
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// let outcome = chain.generate_n_tokens_strict(&mut thread_rng(), &("I", " "), 10);
+    /// assert_eq!(
+    ///     outcome,
+    ///     GenerationOutcome::DeadEnd { produced: vec!["am", " ", "a", " ", "cat"] }
+    /// );
+    /// ```
+    pub fn generate_n_tokens_strict<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+        n: usize,
+    ) -> GenerationOutcome<'_> {
+        let mut produced = Vec::with_capacity(n);
+        let Some(first) = self.generate_next_token(rng, prev).filter(|_| n > 0) else {
+            return if n == 0 {
+                GenerationOutcome::Complete(produced)
+            } else {
+                GenerationOutcome::DeadEnd { produced }
+            };
+        };
+        produced.push(first);
+
+        let mut left: TokenRef<'_> = prev.1;
+        let mut right = first;
+        while produced.len() < n {
+            let Some(next) = self.generate_next_token(rng, &(left, right)) else {
+                return GenerationOutcome::DeadEnd { produced };
+            };
+            produced.push(next);
+            left = right;
+            right = next;
+        }
+        GenerationOutcome::Complete(produced)
+    }
+
+    /// Like [`Chain::generate_n_tokens()`], but the tokens are copied into [`String`]s that do not
+    /// borrow from the chain, so they can outlive it.
+    ///
+    /// If the chain has never seen the `prev` tokens together, `None` is returned.
+    pub fn generate_n_tokens_owned<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+        n: usize,
+    ) -> Option<Vec<String>> {
+        let tokens = self.generate_n_tokens(rng, prev, n)?;
+        Some(tokens.into_iter().map(String::from).collect())
+    }
+
+    /// Like [`Chain::generate_n_tokens()`], but the tokens are written to `out` instead of a new
+    /// vector. `out` is cleared first, so that the same vector (and its allocation) can be
+    /// reused when generating many short texts.
+    ///
+    /// If the chain has never seen the `prev` tokens together, `false` is returned and `out` is
+    /// left empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let mut rng = thread_rng();
+    /// let mut buf = Vec::with_capacity(10);
+    /// for _ in 0..1000 {
+    ///     assert!(chain.generate_n_tokens_into(&mut rng, &("I", " "), 10, &mut buf));
+    ///     assert_eq!(buf.len(), 10);
+    /// }
+    /// ```
+    pub fn generate_n_tokens_into<'a, R: Rng + ?Sized>(
+        &'a self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+        n: usize,
+        out: &mut Vec<TokenRef<'a>>,
+    ) -> bool {
+        out.clear();
+        if n < 1 {
+            return true;
+        }
+
+        let Some(prev) = self.known_pair(prev) else {
+            return false;
+        };
+        self.fill_tokens(rng, Some(prev), n, out);
+        true
+    }
+
+    /// Like [`Chain::generate_str()`], but the tokens are written to `out` like in
+    /// [`Chain::generate_n_tokens_into()`].
+    pub fn generate_str_into<'a, R: Rng + ?Sized>(
+        &'a self,
+        rng: &mut R,
+        n: usize,
+        out: &mut Vec<TokenRef<'a>>,
+    ) -> bool {
+        match self.start_tokens(rng) {
+            Some(start) => self.generate_n_tokens_into(rng, &start, n, out),
+            None => {
+                out.clear();
+                false
+            }
+        }
+    }
+
+    /// Replaces the tokens in `out` with `n` tokens generated after `prev`, or after random start
+    /// tokens if it is `None`, restarting using [`Chain::start_tokens()`] when needed.
+    fn fill_tokens<'a, R: Rng + ?Sized>(
+        &'a self,
+        rng: &mut R,
+        prev: Option<TokenPairRef<'a>>,
+        n: usize,
+        out: &mut Vec<TokenRef<'a>>,
+    ) {
+        out.clear();
+        out.reserve(n);
+        let mut walk = Walk::new(prev);
+        while out.len() < n {
+            let Some(token) = walk.step_in(self, rng) else {
+                break;
+            };
+            out.push(token);
+        }
+    }
+
+    /// Returns `pair` borrowed from the chain, if the chain has it.
+    pub(crate) fn known_pair(&self, pair: &TokenPairRef<'_>) -> Option<TokenPairRef<'_>> {
+        let (left, rights) = self.map.get_key_value(pair.0)?;
+        let (right, _) = rights.get_key_value(pair.1)?;
+        Some((left.as_str(), right.as_str()))
+    }
+
+    /// Generates `n` tokens, using previously used tokens to generate new ones. Less tokens may
+    /// be generated, if two tokens are found that have never been seen before.
+    ///
+    /// If the chain has never seen the `prev` tokens together, `None` is returned.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `n` is so big no vector can hold that many elements.
+    pub fn generate_max_n_tokens<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
         prev: &TokenPairRef<'_>,
         n: usize,
     ) -> Option<Vec<TokenRef<'_>>> {
@@ -136,84 +881,212 @@ impl Chain {
         let mut res = Vec::with_capacity(n);
 
         res.push(first);
+        let remaining = n - 1;
 
         let (mut left, mut right) = (prev.1, first);
 
-        // Since we are not including n, we don't take (n - 1)
-        while res.len() < n {
-            if let Some(next) = self.generate_next_token(rng, &(&left, &right)) {
+        for _ in 0..remaining {
+            if let Some(next) = self.generate_next_token(rng, &(left, right)) {
                 res.push(next);
                 left = right;
                 right = next;
             } else {
-                // We found two tokens that have never been seen together, we have to get new start
-                // tokens. Unwrap is safe, since we could never get this far without any start
-                // tokens.
-                let tp = self.start_tokens(rng).unwrap();
-
-                // Figure out if we have room for both
-                let r = n - res.len();
-                if r >= 2 {
-                    left = &tp.0;
-                    right = &tp.1;
-                    res.push(&tp.0);
-                    res.push(&tp.1);
-                } else if r == 1 {
-                    res.push(&tp.0);
-                    break;
-                } else {
-                    // Should never happen
-                    break;
-                }
+                // We found two tokens that have never been seen together
+                break;
             }
         }
 
         Some(res)
     }
 
-    /// Generates `n` tokens, using previously used tokens to generate new ones. Less tokens may
-    /// be generated, if two tokens are found that have never been seen before.
+    /// Returns the probability that `next` follows `prev`, that is, how likely it is to be chosen
+    /// by [`Chain::generate_next_token()`]. If `next` has never been seen following `prev`, the
+    /// probability is zero.
     ///
     /// If the chain has never seen the `prev` tokens together, `None` is returned.
     ///
-    /// # Panics
+    /// # Examples
     ///
-    /// Will panic if `n` is so big no vector can hold that many elements.
-    pub fn generate_max_n_tokens(
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am. I am. I was.").unwrap();
+    /// assert_eq!(chain.probability(&("I", " "), "was"), Some(1.0 / 3.0));
+    /// assert_eq!(chain.probability(&("I", " "), "cat"), Some(0.0));
+    /// assert_eq!(chain.probability(&("You", " "), "are"), None);
+    /// ```
+    pub fn probability(&self, prev: &TokenPairRef<'_>, next: &str) -> Option<f64> {
+        Some(self.get_distribution(prev)?.probability(next))
+    }
+
+    /// Returns an iterator of every token that may follow `prev`, together with the probability
+    /// that it does. The probabilities sum to one, and the order is unspecified.
+    ///
+    /// If the chain has never seen the `prev` tokens together, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am. I am. I was.").unwrap();
+    /// let mut dist: Vec<_> = chain.distribution(&("I", " ")).unwrap().collect();
+    /// dist.sort_by(|a, b| a.0.cmp(b.0));
+    /// assert_eq!(dist, vec![("am", 2.0 / 3.0), ("was", 1.0 / 3.0)]);
+    /// ```
+    pub fn distribution(
         &self,
-        rng: &mut impl Rng,
         prev: &TokenPairRef<'_>,
-        n: usize,
-    ) -> Option<Vec<TokenRef<'_>>> {
-        if n < 1 {
-            return Some(Vec::new());
+    ) -> Option<impl Iterator<Item = (TokenRef<'_>, f64)>> {
+        let dist = self.get_distribution(prev)?;
+        let total = dist.total() as f64;
+        Some(
+            dist.iter()
+                .map(move |(t, n)| (t.as_str(), n as f64 / total)),
+        )
+    }
+
+    /// Returns how many times `next` has been seen following `prev` in the text(s) the chain
+    /// was built from. If `next` has never been seen following `prev`, the count is zero.
+    ///
+    /// If the chain has never seen the `prev` tokens together, `None` is returned.
+    pub fn count(&self, prev: &TokenPairRef<'_>, next: &str) -> Option<Weight> {
+        Some(self.get_distribution(prev)?.count(next))
+    }
+
+    /// Writes every transition in the chain as CSV to `writer`, with the columns
+    /// `left,right,next,count,probability`. `count` is how many times `next` was seen following
+    /// the pair, and `probability` is how likely it is to follow the pair. Rows are sorted, and
+    /// fields are quoted when needed.
+    ///
+    /// This makes it easy to analyze a chain using other tools, for example `pandas`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am a CSV").unwrap();
+    /// let mut csv = Vec::new();
+    /// chain.export_csv(&mut csv).unwrap();
+    /// let csv = String::from_utf8(csv).unwrap();
+    /// assert!(csv.starts_with("left,right,next,count,probability\n"));
+    /// assert!(csv.contains("I,\" \",am,1,1\n"));
+    /// ```
+    pub fn export_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "left,right,next,count,probability")?;
+
+        let mut pairs: Vec<_> = self.pairs().collect();
+        pairs.sort_unstable();
+        for pair in pairs {
+            // Unwrap is safe, since the pair comes from the chain
+            let (choices, weights) = self.get_distribution(&pair).unwrap().parts();
+            let total: f64 = weights.iter().map(|w| *w as f64).sum();
+
+            let mut rows: Vec<_> = choices.iter().zip(weights).collect();
+            rows.sort_unstable();
+            for (next, n) in rows {
+                writeln!(
+                    writer,
+                    "{},{},{},{},{}",
+                    CsvField(pair.0),
+                    CsvField(pair.1),
+                    CsvField(next),
+                    n,
+                    *n as f64 / total
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compact binary serialization using [`postcard`](https://crates.io/crates/postcard), which is
+/// suitable for embedding chains in firmware images and other constrained environments.
+#[cfg(feature = "postcard")]
+impl Chain {
+    /// Serializes the chain using `postcard`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am very small").unwrap();
+    /// let bytes = chain.to_postcard().unwrap();
+    /// let same = Chain::from_postcard(&bytes).unwrap();
+    /// ```
+    pub fn to_postcard(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_allocvec(self)
+    }
+
+    /// Deserializes a chain serialized using [`Chain::to_postcard()`].
+    pub fn from_postcard(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        postcard::from_bytes(bytes)
+    }
+}
+
+/// Writes a string as a CSV field, quoting it if it contains anything that would otherwise
+/// break the row or be trimmed by a reader.
+struct CsvField<'a>(&'a str);
+
+impl fmt::Display for CsvField<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let needs_quotes = self.0.is_empty()
+            || self.0.contains([',', '"', '\n', '\r'])
+            || self.0.starts_with(char::is_whitespace)
+            || self.0.ends_with(char::is_whitespace);
+        if needs_quotes {
+            write!(f, "\"{}\"", self.0.replace('"', "\"\""))
+        } else {
+            f.write_str(self.0)
         }
+    }
+}
 
-        // We first make sure the `prev` tokens have ever been seen together before
-        // allocating the result
-        let first = self.generate_next_token(rng, prev)?;
-        let mut res = Vec::with_capacity(n);
-
-        res.push(first);
-        let remaining = n - 1;
+/// The result of [`Chain::generate_n_tokens_strict()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GenerationOutcome<'a> {
+    /// All tokens were generated.
+    Complete(Vec<TokenRef<'a>>),
+    /// There was no possible next token after the tokens that were `produced`, so fewer tokens
+    /// than requested were generated.
+    DeadEnd {
+        /// The tokens generated before the dead end.
+        produced: Vec<TokenRef<'a>>,
+    },
+}
 
-        let (mut left, mut right) = (prev.1, first);
+impl<'a> GenerationOutcome<'a> {
+    /// Returns `true` if all tokens were generated.
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Self::Complete(_))
+    }
 
-        for _ in 0..remaining {
-            if let Some(next) = self.generate_next_token(rng, &(&left, &right)) {
-                res.push(next);
-                left = right;
-                right = next;
-            } else {
-                // We found two tokens that have never been seen together
-                break;
-            }
+    /// Returns the generated tokens, no matter if all tokens were generated or not.
+    pub fn into_tokens(self) -> Vec<TokenRef<'a>> {
+        match self {
+            Self::Complete(tokens) | Self::DeadEnd { produced: tokens } => tokens,
         }
-
-        Some(res)
     }
 }
 
+/// How the counts of two chains are combined by [`Chain::intersect()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntersectWeight {
+    /// The smallest of the two counts is used.
+    Min,
+    /// The counts are multiplied, which favours transitions that are common in both chains.
+    Product,
+}
+
+/// Spreads `n` over the tokens of `counts`, in proportion to their counts. Every token gets at
+/// least one.
+fn spread(n: Weight, counts: &HashMap<Token, Weight>) -> impl Iterator<Item = (&Token, Weight)> {
+    let total: u128 = counts.values().map(|c| *c as u128).sum();
+    counts.iter().map(move |(token, c)| {
+        let share = (n as u128 * *c as u128 + total / 2) / total.max(1);
+        (token, (share as Weight).max(1))
+    })
+}
+
 /// The result of feeding some tokens to a [`ChainBuilder`]. The `Err` variant means that the feed
 /// failed, and that an unmodified [`ChainBuilder`] was returned.
 ///
@@ -236,30 +1109,230 @@ pub type FeedResult = Result<UpdatedChainBuilder, ChainBuilder>;
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ChainBuilder {
-    map: HashMap<TokenPair, TokenDistributionBuilder>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::sorted::map"))]
+    pub(crate) map: HashMap<TokenPair, TokenDistributionBuilder>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) sentences: SentenceBounds,
 }
 
 impl ChainBuilder {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            sentences: SentenceBounds::default(),
+        }
+    }
+
+    /// Creates a new builder with room for at least `capacity` token pairs without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: HashMap::with_capacity(capacity),
+            sentences: SentenceBounds::default(),
         }
     }
 
+    /// Creates a new builder with room for the amount of token pairs usually found in a text of
+    /// `n` bytes, when fed using [`ChainBuilder::feed_str()`]. This avoids having to grow the
+    /// builder over and over again when feeding it a large text.
+    ///
+    /// The estimate is based on the number of unique pairs growing roughly like `n^0.62`, as
+    /// measured on English text. Texts with a richer vocabulary will still need to grow the
+    /// builder somewhat.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// let text = "Imagine this being a very large text";
+    /// let cb = ChainBuilder::for_corpus_bytes(text.len()).feed_str(text);
+    /// ```
+    pub fn for_corpus_bytes(n: usize) -> Self {
+        Self::with_capacity(estimate_pairs(n))
+    }
+
     /// Uses up the builder and creates a new chain.
     ///
     /// Will return an error if the builder have not been fed any strings.
+    ///
+    /// The chain keeps how many times each token has been seen alongside the tables used for
+    /// generation, so nothing fed to the builder is lost. The counts can be queried using
+    /// [`Chain::count()`], and are what is compared, merged, and saved in the rest of the crate.
+    ///
+    /// If the `rayon` feature is enabled, the distributions are built in parallel.
     pub fn build(self) -> Result<Chain, ChainBuilder> {
         if self.map.is_empty() {
             return Err(self);
         }
+        let sentences = self.sentences;
+
+        #[cfg(feature = "rayon")]
+        let dists: Vec<(TokenPair, TokenDistribution)> = self
+            .map
+            .into_par_iter()
+            .map(|(pair, dist_builder)| (pair, dist_builder.build()))
+            .collect();
+        #[cfg(not(feature = "rayon"))]
+        let dists = self
+            .map
+            .into_iter()
+            .map(|(pair, dist_builder)| (pair, dist_builder.build()));
+
+        let mut chain_map: HashMap<Token, HashMap<Token, TokenDistribution>> = HashMap::new();
+        for (TokenPair(left, right), dist) in dists {
+            chain_map.entry(left).or_default().insert(right, dist);
+        }
+
+        Ok(Chain::from_parts(chain_map, sentences))
+    }
+
+    /// Like [`ChainBuilder::build()`], but first rescales the counts of every pair so that its
+    /// most common next token has a count of at most `max_weight`. See
+    /// [`TokenDistributionBuilder::quantize()`].
+    ///
+    /// Exact counts barely affect the generated text, so this makes chains (and especially
+    /// serialized ones) smaller at little cost. For example, a `max_weight` of `255` makes every
+    /// count fit in a byte.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::{ChainBuilder, IntoChainBuilder};
+    /// let cb = ChainBuilder::new().feed_str("I am quantized").into_cb();
+    /// let chain = cb.build_quantized(255).unwrap();
+    /// ```
+    pub fn build_quantized(mut self, max_weight: Weight) -> Result<Chain, ChainBuilder> {
+        for dist_builder in self.map.values_mut() {
+            dist_builder.quantize(max_weight);
+        }
+        self.build()
+    }
+
+    /// Multiplies every count in the builder with `factor`, so that texts fed before are
+    /// worth less than texts fed after. Calling this between feeds makes old texts fade away
+    /// exponentially, which lets a long-running builder favor recent texts. Tokens and pairs
+    /// whose counts reach zero are removed, so stale transitions do not pile up, and so are the
+    /// sentence starts of removed pairs. See [`TokenDistributionBuilder::decay()`] for how counts
+    /// are rounded.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `factor` is not between zero and one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// # use rand::thread_rng;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let mut cb = ChainBuilder::new().feed_str("I am old.").into_cb();
+    /// // Forgets everything
+    /// cb.decay(&mut thread_rng(), 0.0);
+    /// cb = cb.feed_str("I am new.").into_cb();
+    /// let chain = cb.build().unwrap();
+    /// assert_eq!(chain.count(&("am", " "), "old"), Some(0));
+    /// ```
+    pub fn decay<R: Rng + ?Sized>(&mut self, rng: &mut R, factor: f64) {
+        self.map.retain(|_, b| {
+            b.decay(rng, factor);
+            !b.map.is_empty()
+        });
+        let map = &self.map;
+        self.sentences.retain_starts(|pair| map.contains_key(pair));
+    }
+
+    /// Keeps only the pairs for which `f` returns `true`, like [`Chain::retain()`].
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(TokenPairRef<'_>, &TokenDistributionBuilder) -> bool,
+    {
+        self.map.retain(|pair, b| f(pair.as_ref(), b));
+        let map = &self.map;
+        self.sentences.retain_starts(|pair| map.contains_key(pair));
+    }
+
+    /// Replaces every token with what `f` returns for it, like [`Chain::map_tokens()`].
+    pub fn map_tokens<F: FnMut(&str) -> Token>(self, mut f: F) -> ChainBuilder {
+        let mut mapped: HashMap<Token, Token> = HashMap::new();
+        let mut map_token = |token: &str| match mapped.get(token) {
+            Some(m) => m.clone(),
+            None => {
+                let m = f(token);
+                mapped.insert(Token::from(token), m.clone());
+                m
+            }
+        };
+
+        let mut map: HashMap<TokenPair, TokenDistributionBuilder> =
+            HashMap::with_capacity(self.map.len());
+        for (TokenPair(left, right), b) in self.map {
+            let pair = TokenPair(map_token(&left), map_token(&right));
+            let target = map.entry(pair).or_default();
+            for (token, n) in b.map {
+                target.add_token_n(&map_token(&token), n);
+            }
+        }
+        let sentences = self.sentences.map_tokens(map_token);
+        ChainBuilder { map, sentences }
+    }
+
+    /// Removes `token` like [`Chain::remove_token()`].
+    pub fn remove_token(&mut self, token: &str) -> bool {
+        let mut found = false;
+        self.map.retain(|TokenPair(left, right), b| {
+            if left == token || right == token {
+                found = true;
+                return false;
+            }
+            found |= b.map.remove(token).is_some();
+            !b.map.is_empty()
+        });
+        self.sentences
+            .retain(|TokenPair(left, right)| left != token && right != token);
+        let map = &self.map;
+        self.sentences.retain_starts(|pair| map.contains_key(pair));
+        found
+    }
 
-        let mut chain_map = HashMap::with_capacity(self.map.len());
-        for (pair, dist_builder) in self.map {
-            chain_map.insert(pair, dist_builder.build());
+    /// Removes `token` like [`Chain::remove_token_relinked()`].
+    pub fn remove_token_relinked(&mut self, token: &str) -> bool {
+        // For every `a b token c d` in the text, `a b -> token` becomes `a b -> c`, and
+        // `b token -> c` becomes `b c -> d`. Since only pairs are known, the counts of `c` and
+        // `d` are spread out over the counts of what is replaced.
+        let mut added: Vec<(TokenPair, Token, Weight)> = Vec::new();
+        for (TokenPair(left, right), b) in &self.map {
+            if left == token || right == token {
+                continue;
+            }
+            let Some(&n) = b.map.get(token) else {
+                continue;
+            };
+            if let Some(after) = self.map.get(&(right.as_str(), token)) {
+                for (next, m) in spread(n, &after.map) {
+                    added.push((TokenPair(left.clone(), right.clone()), next.clone(), m));
+                }
+            }
+        }
+        for (TokenPair(left, right), b) in &self.map {
+            if right != token || left == token {
+                continue;
+            }
+            for (next, &n) in &b.map {
+                if let Some(after) = self.map.get(&(token, next.as_str())) {
+                    for (next_next, m) in spread(n, &after.map) {
+                        added.push((TokenPair(left.clone(), next.clone()), next_next.clone(), m));
+                    }
+                }
+            }
         }
 
-        Ok(Chain { map: chain_map })
+        let found = self.remove_token(token);
+        for (pair, next, n) in added {
+            if pair.0 != token && pair.1 != token && next != token {
+                self.map.entry(pair).or_default().add_token_n(&next, n);
+            }
+        }
+        found
     }
 
     /// Add the occurance of `next` following `prev`.
@@ -311,6 +1384,51 @@ impl ChainBuilder {
         self.feed_tokens(tokens)
     }
 
+    /// Feeds the chain builder with raw bytes that should be UTF-8, but may contain some invalid
+    /// sequences, such as text from a corpus with occasional encoding errors. Invalid sequences
+    /// are replaced by `U+FFFD` like in [`String::from_utf8_lossy()`], or removed if `drop_invalid`
+    /// is `true`. The text is then split like in [`ChainBuilder::feed_str()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let bytes = b"I am a \xffcat";
+    /// let chain = ChainBuilder::new()
+    ///     .feed_bytes_lossy(bytes, false)
+    ///     .into_cb()
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(chain.count(&("a", " "), "\u{FFFD}"), Some(1));
+    ///
+    /// let chain = ChainBuilder::new()
+    ///     .feed_bytes_lossy(bytes, true)
+    ///     .into_cb()
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(chain.count(&("a", " "), "cat"), Some(1));
+    /// ```
+    pub fn feed_bytes_lossy(self, content: &[u8], drop_invalid: bool) -> FeedResult {
+        if !drop_invalid {
+            return self.feed_str(&String::from_utf8_lossy(content));
+        }
+
+        let mut chunks = content.utf8_chunks().map(|chunk| chunk.valid());
+        match chunks.next() {
+            // Valid all the way, so there is no need to copy
+            Some(valid) if valid.len() == content.len() => self.feed_str(valid),
+            Some(valid) => {
+                let mut text = String::with_capacity(content.len());
+                text.push_str(valid);
+                text.extend(chunks);
+                self.feed_str(&text)
+            }
+            None => Err(self),
+        }
+    }
+
     /// Feeds the chain builder with pre-split tokens. Useful if you want to just split on
     /// whitespace and then join the result. May fail if the input is too short, in which case
     /// the (not updated) [`ChainBuilder`] is returned.
@@ -345,6 +1463,76 @@ impl ChainBuilder {
             updated_pairs,
         })
     }
+
+    /// Feeds the chain builder with 3-grams that have already been counted, such as from the
+    /// Google Books n-gram dataset. Each item is the three tokens of the n-gram, and how many
+    /// times it was seen. N-grams seen zero times are skipped.
+    ///
+    /// May fail if no n-grams were fed, in which case the (not updated) [`ChainBuilder`] is
+    /// returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let tsv = "I am here\t20\nI am there\t5\n";
+    /// let ngrams = tsv.lines().filter_map(|line| {
+    ///     let (ngram, count) = line.split_once('\t')?;
+    ///     let mut words = ngram.split(' ');
+    ///     let ngram = (words.next()?, words.next()?, words.next()?);
+    ///     Some((ngram, count.parse().ok()?))
+    /// });
+    /// let chain = ChainBuilder::new().feed_ngram_counts(ngrams).into_cb().build().unwrap();
+    /// assert_eq!(chain.probability(&("I", "am"), "here"), Some(0.8));
+    /// ```
+    pub fn feed_ngram_counts<'a, I>(mut self, ngrams: I) -> FeedResult
+    where
+        I: IntoIterator<Item = ((TokenRef<'a>, TokenRef<'a>, TokenRef<'a>), Weight)>,
+    {
+        let mut new_pairs = 0_usize;
+        let mut updated_pairs = 0_usize;
+
+        for ((left, right, next), n) in ngrams {
+            if n == 0 {
+                continue;
+            }
+            match self.map.get_mut(&(left, right)) {
+                Some(b) => {
+                    b.add_token_n(next, n);
+                    updated_pairs += 1;
+                }
+                None => {
+                    let mut b = TokenDistributionBuilder::new();
+                    b.add_token_n(next, n);
+                    self.map.insert(TokenPair::new(left, right), b);
+                    new_pairs += 1;
+                }
+            }
+        }
+
+        if new_pairs + updated_pairs == 0 {
+            return Err(self);
+        }
+
+        Ok(UpdatedChainBuilder {
+            chain_builder: self,
+            new_pairs,
+            updated_pairs,
+        })
+    }
+}
+
+/// Estimates how many unique token pairs a text of `n` bytes contains. There can never be more
+/// pairs than tokens, and a token is on average at least a few bytes long.
+fn estimate_pairs(n: usize) -> usize {
+    const SCALE: f64 = 10.0;
+    const EXPONENT: f64 = 0.62;
+    const MIN_BYTES_PER_TOKEN: usize = 3;
+
+    let estimate = (SCALE * (n as f64).powf(EXPONENT)) as usize;
+    estimate.min(n / MIN_BYTES_PER_TOKEN)
 }
 
 impl Default for ChainBuilder {
@@ -454,8 +1642,280 @@ impl IntoChainBuilder for UpdatedChainBuilder {
 mod tests {
     use rand::thread_rng;
 
+    use crate::chain::{GenerationOutcome, IntersectWeight};
+    use crate::token::Token;
     use crate::{chain::IntoChainBuilder, distribution::TokenDistribution, Chain, ChainBuilder};
 
+    #[test]
+    fn generate_into_reuses_buffer() {
+        let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+        let mut buf = vec!["old"; 3];
+        assert!(!chain.generate_n_tokens_into(&mut thread_rng(), &("You", " "), 5, &mut buf));
+        assert!(buf.is_empty());
+
+        assert!(chain.generate_str_into(&mut thread_rng(), 7, &mut buf));
+        assert_eq!(buf.len(), 7);
+        assert!(chain.generate_n_tokens_into(&mut thread_rng(), &("I", " "), 1, &mut buf));
+        assert_eq!(buf, vec!["am"]);
+    }
+
+    #[test]
+    fn generate_fmt_writes_n_tokens() {
+        let chain = Chain::from_text("a b").unwrap();
+        let mut s = String::new();
+        chain.generate_fmt(&mut thread_rng(), 5, &mut s).unwrap();
+        // There is only a single pair, so generation is restarted every time
+        assert_eq!(s, "ba ba");
+    }
+
+    #[test]
+    fn owned_tokens_outlive_chain() {
+        let tokens = {
+            let chain = Chain::from_text("I am a cat.").unwrap();
+            chain.generate_n_tokens_owned(&mut thread_rng(), &("I", " "), 3)
+        };
+        assert_eq!(tokens, Some(vec!["am".into(), " ".into(), "a".into()]));
+    }
+
+    #[test]
+    fn decay_scales_counts() {
+        let mut cb = ChainBuilder::new();
+        for _ in 0..1000 {
+            cb.add_occurance(&("a", "b"), "c");
+        }
+        cb.add_occurance(&("x", "y"), "z");
+        cb.decay(&mut thread_rng(), 0.5);
+
+        let chain = cb.build().unwrap();
+        assert_eq!(chain.count(&("a", "b"), "c"), Some(500));
+        // The single occurance was either kept or forgotten
+        assert!(matches!(chain.count(&("x", "y"), "z"), Some(1) | None));
+
+        let mut cb = ChainBuilder::new().feed_sentences("I am old.").into_cb();
+        cb.decay(&mut thread_rng(), 0.0);
+        assert!(cb.sentences.is_empty());
+    }
+
+    #[test]
+    fn into_builder_keeps_counts_and_sentences() {
+        let cb = ChainBuilder::new()
+            .feed_sentences("I am a cat. I am a dog.")
+            .into_cb();
+        let chain = cb.clone().build().unwrap();
+        let rebuilt = chain.clone().into_builder();
+        assert_eq!(rebuilt.map, cb.map);
+        assert_eq!(rebuilt.sentences, cb.sentences);
+
+        let chain = rebuilt.feed_str("I am").into_cb().build().unwrap();
+        assert_eq!(chain.count(&("I", " "), "am"), Some(3));
+    }
+
+    #[test]
+    fn subchain_only_keeps_reachable_pairs() {
+        let cb = ChainBuilder::new().feed_sentences("x y z. A b").into_cb();
+        let chain = cb.build().unwrap();
+        assert!(chain.subchain_from(&("q", " ")).is_none());
+
+        let sub = chain.subchain_from(&(" ", "y")).unwrap();
+        let mut pairs: Vec<_> = sub.pairs().collect();
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            vec![
+                (" ", "A"),
+                (" ", "y"),
+                (" ", "z"),
+                (".", " "),
+                ("A", " "),
+                ("y", " "),
+                ("z", ".")
+            ]
+        );
+        let mut starts: Vec<_> = sub.sentence_bounds().starts().collect();
+        starts.sort();
+        assert_eq!(starts, vec![("A", " ")]);
+    }
+
+    #[test]
+    fn retain_removes_pairs_and_sentence_starts() {
+        let mut cb = ChainBuilder::new()
+            .feed_sentences("A b c. D e f.")
+            .into_cb();
+        cb.retain(|(left, _), _| left != "A");
+        assert!(!cb.map.contains_key(&("A", " ")));
+
+        let mut chain = cb.build().unwrap();
+        assert_eq!(
+            chain.sentence_bounds().starts().collect::<Vec<_>>(),
+            vec![("D", " ")]
+        );
+        chain.retain(|_, dist| dist.count("f") == 0);
+        assert_eq!(chain.count(&("e", " "), "f"), None);
+        assert_eq!(
+            chain.sentence_bounds().starts().collect::<Vec<_>>(),
+            vec![("D", " ")]
+        );
+
+        chain.retain(|(left, _), _| left != "D");
+        assert_eq!(chain.sentence_bounds().starts().count(), 0);
+        assert!(chain.sentence_bounds().is_end(&("f", ".")));
+    }
+
+    #[test]
+    fn map_tokens_merges_collisions() {
+        let cb = ChainBuilder::new()
+            .feed_sentences("My name is Ann. My name is Bob.")
+            .into_cb();
+        let redact = |t: &str| match t {
+            "Ann" | "Bob" => Token::from("NAME"),
+            t => Token::from(t),
+        };
+        let chain = cb.build().unwrap().map_tokens(redact);
+
+        assert!(!chain.contains_token("Ann"));
+        assert_eq!(chain.count(&("is", " "), "NAME"), Some(2));
+        assert_eq!(chain.count(&(" ", "NAME"), "."), Some(2));
+        assert!(chain.sentence_bounds().is_end(&("NAME", ".")));
+    }
+
+    #[test]
+    fn remove_token_relinks_across_gap() {
+        let text = "a b X c d. a b X c e. a b c";
+        let tokens = || text.split(' ').flat_map(|t| t.split_inclusive('.'));
+        let cb = ChainBuilder::new().feed_tokens(tokens()).into_cb();
+
+        let mut removed = cb.clone();
+        assert!(removed.remove_token("X"));
+        assert!(!removed.map.contains_key(&("b", "X")));
+        assert_eq!(removed.map[&("a", "b")].map.get("X"), None);
+
+        let mut relinked = cb;
+        assert!(relinked.remove_token_relinked("X"));
+        let chain = relinked.build().unwrap();
+        assert_eq!(chain.count(&("a", "b"), "c"), Some(3));
+        assert_eq!(chain.count(&("b", "c"), "d."), Some(1));
+        assert_eq!(chain.count(&("b", "c"), "e."), Some(1));
+        assert!(!chain.contains_token("X"));
+    }
+
+    #[test]
+    fn intersect_keeps_common_transitions() {
+        let a = ChainBuilder::new()
+            .feed_sentences("A cat is here. A cat is here. A dog is.")
+            .into_cb()
+            .build()
+            .unwrap();
+        let b = ChainBuilder::new()
+            .feed_sentences("A cat is there. A cat is here.")
+            .into_cb()
+            .build()
+            .unwrap();
+
+        let common = a.intersect(&b, IntersectWeight::Min).unwrap();
+        assert_eq!(common.count(&("A", " "), "cat"), Some(2));
+        assert_eq!(common.count(&("A", " "), "dog"), Some(0));
+        assert_eq!(common.count(&("is", " "), "here"), Some(1));
+        assert_eq!(common.count(&("is", " "), "there"), Some(0));
+        assert!(common.sentence_bounds().is_end(&("here", ".")));
+        assert!(!common.sentence_bounds().is_end(&("is", ".")));
+
+        let other = Chain::from_text("Nothing in common").unwrap();
+        assert!(a.intersect(&other, IntersectWeight::Product).is_none());
+    }
+
+    #[test]
+    fn pairs_containing_is_updated() {
+        let mut chain = Chain::from_text("a b a b. b a").unwrap();
+        let sorted = |chain: &Chain| {
+            let mut pairs: Vec<_> = chain
+                .pairs_containing("b")
+                .map(|(l, r)| (l.to_string(), r.to_string()))
+                .collect();
+            pairs.sort();
+            pairs
+        };
+        assert_eq!(
+            sorted(&chain),
+            vec![
+                (" ".into(), "b".into()),
+                ("b".into(), " ".into()),
+                ("b".into(), ".".into())
+            ]
+        );
+
+        chain.retain(|(left, _), _| left != " ");
+        assert_eq!(
+            sorted(&chain),
+            vec![("b".into(), " ".into()), ("b".into(), ".".into())]
+        );
+    }
+
+    #[test]
+    fn generate_batch_restarts_at_dead_ends() {
+        let chain = Chain::from_text("a b").unwrap();
+        let batch = chain.generate_batch(&mut thread_rng(), 3, 4).unwrap();
+        // The only pair is "a" " ", followed by "b" which is a dead end
+        assert_eq!(batch, vec!["ba b"; 3]);
+        assert!(chain
+            .generate_batch(&mut thread_rng(), 0, 4)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn generate_n_words_ends_with_word() {
+        let chain = Chain::from_text("Oh... I see!!! Do you? Yes.").unwrap();
+        let mut rng = thread_rng();
+        for n in 0..20 {
+            let text = chain.generate_n_words(&mut rng, n).unwrap();
+            let words: Vec<_> = text
+                .iter()
+                .filter(|t| t.contains(char::is_alphanumeric))
+                .collect();
+            assert_eq!(words.len(), n);
+            assert_eq!(text.last(), words.last().copied());
+        }
+
+        let chain = Chain::from_text("?! ...").unwrap();
+        assert_eq!(chain.generate_n_words(&mut rng, 1), None);
+
+        // Every start leads into a loop without words, which must be left again
+        let chain = Chain::from_text("Hi . . . .").unwrap();
+        let text = chain.generate_n_words(&mut rng, 2).unwrap();
+        assert_eq!(text.iter().filter(|t| **t == "Hi").count(), 2);
+        assert_eq!(text.last(), Some(&"Hi"));
+    }
+
+    #[test]
+    fn strict_generation_never_restarts() {
+        let chain = Chain::from_text("a b c").unwrap();
+        let mut rng = thread_rng();
+
+        let outcome = chain.generate_n_tokens_strict(&mut rng, &("a", " "), 2);
+        assert_eq!(outcome, GenerationOutcome::Complete(vec!["b", " "]));
+
+        let outcome = chain.generate_n_tokens_strict(&mut rng, &("a", " "), 100);
+        assert!(!outcome.is_complete());
+        assert_eq!(outcome.into_tokens(), vec!["b", " ", "c"]);
+
+        assert_eq!(
+            chain.generate_n_tokens_strict(&mut rng, &("x", "y"), 1),
+            GenerationOutcome::DeadEnd { produced: vec![] }
+        );
+        assert!(chain
+            .generate_n_tokens_strict(&mut rng, &("x", "y"), 0)
+            .is_complete());
+    }
+
+    #[test]
+    fn generate_with_dyn_rng() {
+        let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+        let mut thread_rng = thread_rng();
+        let rng: &mut dyn rand::RngCore = &mut thread_rng;
+        assert_eq!(chain.generate_str(rng, 5).map(|t| t.len()), Some(5));
+        assert_eq!(chain.generate_next_token(rng, &("I", " ")), Some("am"));
+    }
+
     #[test]
     #[should_panic]
     fn empty_chain_builder_panics() {
@@ -475,6 +1935,24 @@ mod tests {
         assert!(Chain::builder().feed_str(s).is_err());
     }
 
+    #[test]
+    fn feed_bytes_lossy() {
+        let bytes = b"I \xf0\x9f am\xff\xfe";
+        let cb = Chain::builder().feed_bytes_lossy(bytes, false).into_cb();
+        let chain = cb.build().unwrap();
+        assert_eq!(chain.count(&("I", " "), "\u{FFFD}"), Some(1));
+        assert_eq!(chain.count(&(" ", "am"), "\u{FFFD}"), Some(1));
+
+        let cb = Chain::builder().feed_bytes_lossy(bytes, true).into_cb();
+        assert_eq!(cb.map, Chain::builder().feed_str("I  am").into_cb().map);
+
+        assert!(Chain::builder().feed_bytes_lossy(b"", true).is_err());
+        assert!(Chain::builder()
+            .feed_bytes_lossy(b"\xff\xff", true)
+            .is_err());
+        assert!(Chain::builder().feed_bytes_lossy(b"I am", true).is_ok());
+    }
+
     #[test]
     fn simple_single_possible_token() {
         let s = "I am";
@@ -631,7 +2109,7 @@ Norm:  Thirsty guy walks into a bar.  You finish it.
         let mut rng = thread_rng();
         for _ in 0..100 {
             let start = chain.start_tokens(&mut rng).unwrap();
-            let _ = chain.generate_n_tokens(&mut rng, &start.as_ref(), 100);
+            let _ = chain.generate_n_tokens(&mut rng, &start, 100);
         }
     }
 
@@ -677,11 +2155,119 @@ There are many like it, but this one is mine.
         -- Unknown
         "#;
         let chain = Chain::from_text(s).unwrap();
-        let good_starting_points: Vec<_> =
-            chain.pairs().filter(|tp| tp.0.as_str() == "\n").collect();
+        let good_starting_points: Vec<_> = chain.pairs().filter(|tp| tp.0 == "\n").collect();
         assert_eq!(good_starting_points.len(), 3);
     }
 
+    #[test]
+    fn pairs_with_first_matches_filtered_pairs() {
+        let chain = Chain::from_text("a b a c a b. b a!").unwrap();
+        let mut indexed: Vec<_> = chain.pairs_with_first("a").collect();
+        let mut filtered: Vec<_> = chain.pairs().filter(|tp| tp.0 == "a").collect();
+        indexed.sort();
+        filtered.sort();
+        assert_eq!(indexed, filtered);
+        assert!(chain.pairs_with_first("nope").next().is_none());
+    }
+
+    #[test]
+    fn corpus_size_estimate() {
+        assert_eq!(super::estimate_pairs(0), 0);
+        // Tiny texts are limited by the amount of tokens
+        assert_eq!(super::estimate_pairs(30), 10);
+        let big = super::estimate_pairs(100_000_000);
+        assert!(big > 100_000 && big < 10_000_000);
+        assert!(ChainBuilder::for_corpus_bytes(1_000_000).map.capacity() >= 50_000);
+    }
+
+    #[test]
+    fn export_csv_quotes_fields() {
+        let chain = Chain::builder()
+            .feed_tokens(["a", ",", "\"", "a", ",", "b"].into_iter())
+            .unwrap()
+            .chain_builder
+            .build()
+            .unwrap();
+        let mut csv = Vec::new();
+        chain.export_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv,
+            "left,right,next,count,probability\n\
+             \"\"\"\",a,\",\",1,1\n\
+             \",\",\"\"\"\",a,1,1\n\
+             a,\",\",\"\"\"\",1,0.5\n\
+             a,\",\",b,1,0.5\n"
+        );
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn postcard_roundtrip() {
+        // postcard is not self-describing, so this makes sure no serde implementation relies
+        // on being able to peek at the input
+        let cb = Chain::builder().feed_str("I am-full!of?cats").into_cb();
+        let bytes = postcard::to_allocvec(&cb).unwrap();
+        let cb: ChainBuilder = postcard::from_bytes(&bytes).unwrap();
+
+        let chain = Chain::from_postcard(&cb.build().unwrap().to_postcard().unwrap()).unwrap();
+        assert_eq!(
+            chain
+                .generate_n_tokens(&mut thread_rng(), &("I", " "), 7)
+                .unwrap(),
+            vec!["am", "-", "full", "!", "of", "?", "cats"],
+        );
+
+        let frozen = crate::FrozenChain::from(chain);
+        let bytes = postcard::to_allocvec(&frozen).unwrap();
+        let frozen: crate::FrozenChain = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(frozen.pairs().count(), 7);
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn serialization_is_canonical() {
+        let texts = ["I am a cat. I am a dog.", "You are a bird. I am a cow."];
+        let feed = |texts: &[&str]| {
+            let mut cb = ChainBuilder::new();
+            for text in texts {
+                cb = cb.feed_sentences(text).into_cb();
+            }
+            cb
+        };
+        let a = feed(&texts);
+        let b = feed(&[texts[1], texts[0]]);
+
+        assert_eq!(
+            postcard::to_allocvec(&a).unwrap(),
+            postcard::to_allocvec(&b).unwrap()
+        );
+        assert_eq!(
+            a.build().unwrap().to_postcard().unwrap(),
+            b.build().unwrap().to_postcard().unwrap()
+        );
+    }
+
+    #[test]
+    fn probability_and_count() {
+        let chain = Chain::builder()
+            .feed_tokens("a b c a b c a b d".split_whitespace())
+            .unwrap()
+            .chain_builder
+            .build()
+            .unwrap();
+        assert_eq!(chain.count(&("a", "b"), "c"), Some(2));
+        assert_eq!(chain.count(&("a", "b"), "d"), Some(1));
+        assert_eq!(chain.count(&("a", "b"), "a"), Some(0));
+        assert_eq!(chain.count(&("b", "a"), "a"), None);
+
+        let p: f64 = ["c", "d"]
+            .iter()
+            .map(|t| chain.probability(&("a", "b"), t).unwrap())
+            .sum();
+        assert!((p - 1.0).abs() < f64::EPSILON);
+    }
+
     #[test]
     fn feed_stats() {
         let cb = ChainBuilder::new();
@@ -0,0 +1,172 @@
+//! A [`ConcurrentChainBuilder`], which can be fed from several threads at once without first
+//! building one [`ChainBuilder`] per thread and merging them.
+
+use std::hash::BuildHasher;
+use std::sync::{Mutex, PoisonError};
+
+use hashbrown::{DefaultHashBuilder, HashMap};
+use itertools::Itertools;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::distribution::TokenDistributionBuilder;
+use crate::token::{TokenPair, TokenPairRef, TokenRef};
+use crate::{Chain, ChainBuilder};
+
+/// The pairs of one shard, and the distributions of the tokens following them.
+type Shard = HashMap<TokenPair, TokenDistributionBuilder>;
+
+/// Builds a chain like [`ChainBuilder`], but is fed using `&self` so it can be shared between
+/// threads. The pairs are split into shards with a lock each, so that threads feeding different
+/// texts seldom have to wait for each other.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::concurrent::ConcurrentChainBuilder;
+///
+/// let texts = ["I am a cat.", "I am a dog.", "I am a bird."];
+/// let cb = ConcurrentChainBuilder::new();
+/// std::thread::scope(|s| {
+///     for text in texts {
+///         s.spawn(|| cb.feed_str(text));
+///     }
+/// });
+/// let chain = cb.build().unwrap();
+/// assert_eq!(chain.count(&("a", " "), "cat"), Some(1));
+/// ```
+#[derive(Debug)]
+pub struct ConcurrentChainBuilder {
+    shards: Box<[Mutex<Shard>]>,
+    hasher: DefaultHashBuilder,
+}
+
+impl Default for ConcurrentChainBuilder {
+    fn default() -> Self {
+        let threads = std::thread::available_parallelism().map_or(1, usize::from);
+        Self::with_shards(threads * 4)
+    }
+}
+
+impl ConcurrentChainBuilder {
+    /// Creates a builder with four shards for every thread the system can run in parallel.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder with `n` shards. More shards means less waiting when many threads feed
+    /// the builder at once, but uses a bit more memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn with_shards(n: usize) -> Self {
+        assert!(n > 0, "a concurrent chain builder needs at least one shard");
+        Self {
+            shards: (0..n).map(|_| Mutex::default()).collect(),
+            hasher: DefaultHashBuilder::default(),
+        }
+    }
+
+    /// Returns the index of the shard `pair` belongs to.
+    fn shard_of(&self, pair: &TokenPairRef<'_>) -> usize {
+        (self.hasher.hash_one(pair) % self.shards.len() as u64) as usize
+    }
+
+    /// Feeds the builder with more text, split like in [`ChainBuilder::feed_str()`].
+    ///
+    /// Returns how many transitions were added, which is zero if the text was too short.
+    pub fn feed_str(&self, content: &str) -> usize {
+        self.feed_tokens(content.split_word_bounds())
+    }
+
+    /// Feeds the builder with pre-split tokens, like [`ChainBuilder::feed_tokens()`].
+    ///
+    /// Returns how many transitions were added, which is zero if there were too few tokens.
+    pub fn feed_tokens<'a>(&self, tokens: impl Iterator<Item = TokenRef<'a>>) -> usize {
+        // Transitions are grouped by shard first, so that every shard is only locked once
+        let mut grouped: Vec<Vec<(TokenRef<'a>, TokenRef<'a>, TokenRef<'a>)>> =
+            vec![Vec::new(); self.shards.len()];
+        let mut added = 0;
+        for (left, right, next) in tokens.tuple_windows() {
+            grouped[self.shard_of(&(left, right))].push((left, right, next));
+            added += 1;
+        }
+
+        for (shard, transitions) in self.shards.iter().zip(grouped) {
+            if transitions.is_empty() {
+                continue;
+            }
+            let mut shard = shard.lock().unwrap_or_else(PoisonError::into_inner);
+            for (left, right, next) in transitions {
+                match shard.get_mut(&(left, right)) {
+                    Some(b) => b.add_token(next),
+                    None => {
+                        let mut b = TokenDistributionBuilder::new();
+                        b.add_token(next);
+                        shard.insert(TokenPair::new(left, right), b);
+                    }
+                }
+            }
+        }
+        added
+    }
+
+    /// Returns `true` if the builder has not been fed anything yet.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| {
+            shard
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_empty()
+        })
+    }
+
+    /// Uses up the builder and returns a [`ChainBuilder`] with everything it has been fed.
+    pub fn into_builder(self) -> ChainBuilder {
+        let mut cb = ChainBuilder::new();
+        for shard in self.shards.into_vec() {
+            // Every pair is only in one shard, so nothing has to be merged
+            cb.map
+                .extend(shard.into_inner().unwrap_or_else(PoisonError::into_inner));
+        }
+        cb
+    }
+
+    /// Uses up the builder and creates a new chain, like [`ChainBuilder::build()`]. Will return
+    /// an error if the builder has not been fed any texts.
+    pub fn build(self) -> Result<Chain, ChainBuilder> {
+        self.into_builder().build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConcurrentChainBuilder;
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn threads_feed_like_one() {
+        let texts = [
+            "I am a cat. I am a dog.",
+            "I am a bird",
+            "a",
+            "You are a cat.",
+        ];
+        let mut expected = ChainBuilder::new();
+        for text in texts {
+            expected = expected.feed_str(text).into_cb();
+        }
+
+        let cb = ConcurrentChainBuilder::with_shards(3);
+        assert!(cb.is_empty());
+        let added: usize = std::thread::scope(|s| {
+            let handles: Vec<_> = texts.iter().map(|t| s.spawn(|| cb.feed_str(t))).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(added, 15 + 5 + 6);
+        assert!(!cb.is_empty());
+        assert_eq!(cb.into_builder().map, expected.map);
+
+        assert!(ConcurrentChainBuilder::new().build().is_err());
+    }
+}
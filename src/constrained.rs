@@ -0,0 +1,268 @@
+//! Generation with constraints on what the text must contain, such as filling in the text
+//! between a given start and end.
+
+use std::collections::VecDeque;
+
+use hashbrown::HashMap;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+
+use crate::distribution::Weight;
+use crate::token::{TokenPairRef, TokenRef};
+use crate::Chain;
+
+/// For every pair `b c` the chain can generate, every token `a` that may come before it, and how
+/// many times `a b` was followed by `c`.
+type Predecessors<'a> = HashMap<TokenPairRef<'a>, Vec<(TokenRef<'a>, Weight)>>;
+
+impl Chain {
+    /// Generates text that starts with `prefix` and ends with `suffix`, such as to fill in a
+    /// template. The returned tokens include both pairs, and are at most `max_len` tokens long.
+    ///
+    /// Only tokens that can still reach `suffix` in time are chosen, so generation never has to
+    /// be restarted. Otherwise tokens are chosen like in [`Chain::generate_str()`]. If there is
+    /// no way of reaching `suffix` from `prefix` in `max_len` tokens, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("Dear Ann, I miss you. Dear Eve, I am sad.").unwrap();
+    /// let greeting = chain
+    ///     .bridge(&mut thread_rng(), &("Dear", " "), &(",", " "), 5)
+    ///     .unwrap()
+    ///     .concat();
+    /// assert!(greeting == "Dear Ann, " || greeting == "Dear Eve, ");
+    /// ```
+    pub fn bridge<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prefix: &TokenPairRef<'_>,
+        suffix: &TokenPairRef<'_>,
+        max_len: usize,
+    ) -> Option<Vec<TokenRef<'_>>> {
+        let (left, rights) = self.map.get_key_value(prefix.0)?;
+        let (right, _) = rights.get_key_value(prefix.1)?;
+        let max_steps = max_len.checked_sub(2)?;
+        if prefix == suffix {
+            return Some(vec![left, right]);
+        }
+
+        // How many tokens must be generated to reach the suffix from each pair, found by
+        // searching backwards from the suffix
+        let predecessors = self.predecessors();
+        let suffix = predecessors.get_key_value(suffix).map(|(pair, _)| *pair)?;
+        let mut steps: HashMap<TokenPairRef<'_>, usize> = HashMap::from([(suffix, 0)]);
+        let mut queue = VecDeque::from([suffix]);
+        while let Some(pair) = queue.pop_front() {
+            let n = steps[&pair];
+            if n == max_steps {
+                continue;
+            }
+            for (before, _) in predecessors.get(&pair).into_iter().flatten() {
+                let prev = (*before, pair.0);
+                if !steps.contains_key(&prev) {
+                    steps.insert(prev, n + 1);
+                    queue.push_back(prev);
+                }
+            }
+        }
+
+        let mut pair = (left.as_str(), right.as_str());
+        if !steps.contains_key(&pair) {
+            return None;
+        }
+        let mut steps_left = max_steps;
+        let mut res = vec![pair.0, pair.1];
+        while pair != suffix {
+            steps_left -= 1;
+            // Unwrap is safe, since only pairs that can reach the suffix are visited
+            let dist = self.get_distribution(&pair).unwrap();
+            let candidates: Vec<_> = dist
+                .iter()
+                .filter(|(next, _)| {
+                    steps
+                        .get(&(pair.1, next.as_str()))
+                        .is_some_and(|n| *n <= steps_left)
+                })
+                .collect();
+
+            let total: u64 = candidates.iter().map(|(_, n)| *n as u64).sum();
+            let mut target = rng.gen_range(0..total);
+            let mut chosen = candidates[0].0;
+            for (next, n) in candidates {
+                if target < n as u64 {
+                    chosen = next;
+                    break;
+                }
+                target -= n as u64;
+            }
+
+            res.push(chosen.as_str());
+            pair = (pair.1, chosen.as_str());
+        }
+        Some(res)
+    }
+
+    /// Generates `n` tokens that are guaranteed to contain `keyword`. Generation starts from a
+    /// random pair containing the keyword, and then extends the text both backwards using
+    /// [`Chain::generate_prev_token()`] and forwards like [`Chain::generate_n_tokens()`], so the
+    /// keyword may end up anywhere in the text.
+    ///
+    /// If no pair contains `keyword`, or if `n` is zero, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("Buy a Widget today. You will love it. Tell a friend.").unwrap();
+    /// let ad = chain.generate_containing(&mut thread_rng(), "Widget", 10).unwrap();
+    /// assert_eq!(ad.len(), 10);
+    /// assert!(ad.contains(&"Widget"));
+    /// ```
+    pub fn generate_containing<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        keyword: &str,
+        n: usize,
+    ) -> Option<Vec<TokenRef<'_>>> {
+        if n == 0 {
+            return None;
+        }
+        let seed = self.pairs_containing(keyword).choose(rng)?;
+        if n == 1 {
+            return Some(vec![if seed.0 == keyword { seed.0 } else { seed.1 }]);
+        }
+
+        let wanted_before = rng.gen_range(0..=n - 2);
+        let mut before = Vec::with_capacity(wanted_before);
+        let (mut left, mut right) = seed;
+        while before.len() < wanted_before {
+            let Some(prev) = self.generate_prev_token(rng, &(left, right)) else {
+                break;
+            };
+            before.push(prev);
+            right = left;
+            left = prev;
+        }
+
+        let mut res: Vec<_> = before.into_iter().rev().collect();
+        res.extend([seed.0, seed.1]);
+        // Unwrap is safe, since the seed is a pair of the chain
+        let after = self.generate_n_tokens(rng, &seed, n - res.len()).unwrap();
+        res.extend(after);
+        Some(res)
+    }
+
+    /// Generates a token that could come before `next`, the opposite of
+    /// [`Chain::generate_next_token()`]. A token is chosen depending on how often it was followed
+    /// by `next`.
+    ///
+    /// The pairs ending with the first token of `next` are found using the index of
+    /// [`Chain::pairs_containing()`]. If nothing has come before `next`, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// assert_eq!(chain.generate_prev_token(&mut thread_rng(), &("a", " ")), Some(" "));
+    /// assert_eq!(chain.generate_prev_token(&mut thread_rng(), &("I", " ")), None);
+    /// ```
+    pub fn generate_prev_token<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        next: &TokenPairRef<'_>,
+    ) -> Option<TokenRef<'_>> {
+        let candidates: Vec<(TokenRef<'_>, Weight)> = self
+            .pairs_with_second(next.0)
+            .filter_map(|pair| {
+                let n = self.get_distribution(&pair)?.count(next.1);
+                (n > 0).then_some((pair.0, n))
+            })
+            .collect();
+
+        let total: u64 = candidates.iter().map(|(_, n)| *n as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut target = rng.gen_range(0..total);
+        for (prev, n) in candidates {
+            if target < n as u64 {
+                return Some(prev);
+            }
+            target -= n as u64;
+        }
+        None
+    }
+
+    /// Finds every token that may come before each pair the chain can generate.
+    fn predecessors(&self) -> Predecessors<'_> {
+        let mut predecessors: Predecessors<'_> = HashMap::new();
+        for (left, rights) in &self.map {
+            for (right, dist) in rights {
+                for (next, n) in dist.iter() {
+                    predecessors
+                        .entry((right.as_str(), next.as_str()))
+                        .or_default()
+                        .push((left.as_str(), n));
+                }
+            }
+        }
+        predecessors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use crate::{Chain, ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn bridge_respects_max_len() {
+        // "a b" can reach "d e" in one step through "c", or in three through "x y"
+        let cb = ChainBuilder::new()
+            .feed_tokens(["a", "b", "c", "d", "e"].into_iter())
+            .into_cb()
+            .feed_tokens(["a", "b", "x", "y", "c", "d", "e"].into_iter())
+            .into_cb()
+            .feed_tokens(["q", "b", "c", "d", "e"].into_iter())
+            .into_cb();
+        let chain = cb.build().unwrap();
+        let mut rng = thread_rng();
+
+        for _ in 0..20 {
+            let short = chain.bridge(&mut rng, &("a", "b"), &("d", "e"), 5).unwrap();
+            assert_eq!(short, vec!["a", "b", "c", "d", "e"]);
+            let long = chain.bridge(&mut rng, &("a", "b"), &("d", "e"), 7).unwrap();
+            assert!(long.len() == 5 || long.len() == 7);
+            assert!(long.ends_with(&["d", "e"]));
+        }
+        assert_eq!(chain.bridge(&mut rng, &("a", "b"), &("d", "e"), 4), None);
+        assert_eq!(chain.bridge(&mut rng, &("a", "b"), &("q", "b"), 10), None);
+        assert_eq!(
+            chain.bridge(&mut rng, &("a", "b"), &("a", "b"), 2),
+            Some(vec!["a", "b"])
+        );
+
+        let chain = Chain::from_text("x y").unwrap();
+        assert_eq!(chain.bridge(&mut rng, &("x", " "), &("x", " "), 1), None);
+    }
+
+    #[test]
+    fn keyword_is_always_included() {
+        let chain = Chain::from_text("one two three four five six seven").unwrap();
+        let mut rng = thread_rng();
+        for n in 1..14 {
+            let text = chain.generate_containing(&mut rng, "four", n).unwrap();
+            assert_eq!(text.len(), n);
+            assert!(text.contains(&"four"));
+        }
+        assert_eq!(chain.generate_containing(&mut rng, "four", 0), None);
+        assert_eq!(chain.generate_containing(&mut rng, "eight", 5), None);
+    }
+}
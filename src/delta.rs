@@ -0,0 +1,359 @@
+//! A [`ChainDelta`] contains the changes between two versions of a [`Chain`], so that a chain that
+//! slowly changes over time can be persisted without writing the whole chain every time. To
+//! inspect what changed instead, use a [`ChainDiff`].
+
+use crate::distribution::{TokenDistribution, Weight};
+use crate::sentence::SentenceBounds;
+use crate::token::{TokenPair, TokenPairRef, TokenRef};
+use crate::Chain;
+
+#[cfg(feature = "save")]
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+#[cfg(feature = "save")]
+use crate::file::{self, ChainFileError};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The first bytes of every delta file.
+#[cfg(feature = "save")]
+pub const DELTA_MAGIC: [u8; 8] = *b"MARKOVDL";
+
+/// The current version of the delta file format.
+#[cfg(feature = "save")]
+pub const DELTA_FORMAT_VERSION: u16 = 1;
+
+/// The changes made to a [`Chain`] since a checkpoint. Created using [`Chain::delta_since()`] and
+/// applied using [`Chain::apply_delta()`].
+///
+/// # Examples
+///
+/// ```
+/// # use markovish::{Chain, ChainBuilder, IntoChainBuilder};
+/// let cb = ChainBuilder::new().feed_str("I am the first version").into_cb();
+/// let checkpoint = cb.clone().build().unwrap();
+/// let latest = cb.feed_sentences("I am newer.").into_cb().build().unwrap();
+///
+/// let delta = latest.delta_since(&checkpoint);
+/// let mut restored = checkpoint.clone();
+/// restored.apply_delta(&delta);
+/// assert!(latest.delta_since(&restored).is_empty());
+/// assert_eq!(restored.sentence_bounds(), latest.sentence_bounds());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChainDelta {
+    /// Pairs that are new or whose distribution changed, together with their new distribution.
+    pub changed: Vec<(TokenPair, TokenDistribution)>,
+    /// Pairs that no longer exist.
+    pub removed: Vec<TokenPair>,
+    /// The new sentence bounds, if they changed.
+    pub sentences: Option<SentenceBounds>,
+}
+
+impl ChainDelta {
+    /// Returns `true` if there are no changes.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.removed.is_empty() && self.sentences.is_none()
+    }
+
+    /// Saves the delta to a file at `path`, using the same kind of header and checksum as
+    /// [`Chain::save()`]. Requires the `save` feature.
+    #[cfg(feature = "save")]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ChainFileError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&DELTA_MAGIC)?;
+        writer.write_all(&DELTA_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&file::current_flags().to_le_bytes())?;
+        file::write_payload(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a delta saved using [`ChainDelta::save()`]. Requires the `save` feature.
+    #[cfg(feature = "save")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChainFileError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut header = [0; DELTA_MAGIC.len() + 2 + 4];
+        reader.read_exact(&mut header)?;
+        let (magic, rest) = header.split_at(DELTA_MAGIC.len());
+        if magic != DELTA_MAGIC {
+            return Err(ChainFileError::NotAChainFile);
+        }
+        let version = u16::from_le_bytes([rest[0], rest[1]]);
+        if version != DELTA_FORMAT_VERSION {
+            return Err(ChainFileError::UnsupportedVersion(version));
+        }
+        file::check_flags(u32::from_le_bytes([rest[2], rest[3], rest[4], rest[5]]))?;
+
+        file::read_payload(reader)
+    }
+}
+
+/// The differences between two chains, meant to be read by humans. Created using
+/// [`Chain::diff()`]. Everything is sorted, so that the same chains always give the same diff.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChainDiff<'a> {
+    /// Pairs only found in the first chain.
+    pub removed: Vec<TokenPairRef<'a>>,
+    /// Pairs only found in the second chain.
+    pub added: Vec<TokenPairRef<'a>>,
+    /// Tokens following a pair found in both chains, whose count is different. A count of zero
+    /// means that the token never follows the pair in that chain.
+    pub changed: Vec<CountChange<'a>>,
+}
+
+impl ChainDiff<'_> {
+    /// Returns `true` if the chains are the same.
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A change in how many times `next` has followed `pair`, see [`ChainDiff`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CountChange<'a> {
+    pub pair: TokenPairRef<'a>,
+    pub next: TokenRef<'a>,
+    /// The count in the first chain.
+    pub before: Weight,
+    /// The count in the second chain.
+    pub after: Weight,
+}
+
+impl Chain {
+    /// Finds the differences between this chain and `other`. Pairs found in only one of them are
+    /// listed as added or removed, and for pairs found in both, every token whose count differs
+    /// is listed.
+    ///
+    /// This is useful to see what training on a new text actually changed. Unlike
+    /// [`Chain::delta_since()`], the result is meant to be read and not to be applied to chains.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use markovish::delta::CountChange;
+    /// let old = Chain::from_text("I am a cat.").unwrap();
+    /// let new = Chain::from_text("I am a dog.").unwrap();
+    ///
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.removed, vec![(" ", "cat")]);
+    /// assert_eq!(diff.added, vec![(" ", "dog")]);
+    /// assert_eq!(
+    ///     diff.changed,
+    ///     vec![
+    ///         CountChange { pair: ("a", " "), next: "cat", before: 1, after: 0 },
+    ///         CountChange { pair: ("a", " "), next: "dog", before: 0, after: 1 },
+    ///     ]
+    /// );
+    /// ```
+    pub fn diff<'a>(&'a self, other: &'a Chain) -> ChainDiff<'a> {
+        let mut diff = ChainDiff::default();
+
+        for (left, rights) in &self.map {
+            let other_rights = other.map.get(left);
+            for (right, dist) in rights {
+                let pair = (left.as_str(), right.as_str());
+                let Some(other_dist) = other_rights.and_then(|r| r.get(right)) else {
+                    diff.removed.push(pair);
+                    continue;
+                };
+
+                for (next, before) in dist.iter() {
+                    let after = other_dist.count(next);
+                    if before != after {
+                        diff.changed.push(CountChange {
+                            pair,
+                            next,
+                            before,
+                            after,
+                        });
+                    }
+                }
+                for (next, after) in other_dist.iter() {
+                    if dist.count(next) == 0 {
+                        diff.changed.push(CountChange {
+                            pair,
+                            next,
+                            before: 0,
+                            after,
+                        });
+                    }
+                }
+            }
+        }
+
+        for (left, rights) in &other.map {
+            let self_rights = self.map.get(left);
+            for right in rights.keys() {
+                if self_rights.is_none_or(|r| !r.contains_key(right)) {
+                    diff.added.push((left, right));
+                }
+            }
+        }
+
+        diff.removed.sort_unstable();
+        diff.added.sort_unstable();
+        diff.changed.sort_unstable();
+        diff
+    }
+
+    /// Finds every pair that has been added, changed, or removed in this chain compared to
+    /// `checkpoint`, which usually is the last version of the chain that was saved, as well as
+    /// the sentence bounds if they changed.
+    pub fn delta_since(&self, checkpoint: &Chain) -> ChainDelta {
+        let mut delta = ChainDelta::default();
+
+        for (left, rights) in &self.map {
+            let old_rights = checkpoint.map.get(left);
+            for (right, dist) in rights {
+                if old_rights.and_then(|r| r.get(right)) != Some(dist) {
+                    delta
+                        .changed
+                        .push((TokenPair::new(left, right), dist.clone()));
+                }
+            }
+        }
+
+        for (left, rights) in &checkpoint.map {
+            let new_rights = self.map.get(left);
+            for right in rights.keys() {
+                if new_rights.is_none_or(|r| !r.contains_key(right)) {
+                    delta.removed.push(TokenPair::new(left, right));
+                }
+            }
+        }
+
+        if self.sentences != checkpoint.sentences {
+            delta.sentences = Some(self.sentences.clone());
+        }
+        delta
+    }
+
+    /// Applies changes found using [`Chain::delta_since()`]. If applied to the checkpoint the
+    /// delta was created from, the result is the same as the chain it was created with.
+    ///
+    /// If every pair is removed, the chain will be empty and unable to generate anything.
+    pub fn apply_delta(&mut self, delta: &ChainDelta) {
+        self.clear_indexes();
+        for TokenPair(left, right) in &delta.removed {
+            if let Some(rights) = self.map.get_mut(left) {
+                rights.remove(right);
+                if rights.is_empty() {
+                    self.map.remove(left);
+                }
+            }
+        }
+
+        for (TokenPair(left, right), dist) in &delta.changed {
+            self.map
+                .entry_ref(left.as_str())
+                .or_default()
+                .insert(right.clone(), dist.clone());
+        }
+
+        if let Some(sentences) = &delta.sentences {
+            self.sentences = sentences.clone();
+        }
+    }
+
+    /// Saves only the changes made to this chain since `checkpoint` to a file at `path`. Load
+    /// the changes using [`ChainDelta::load()`] and apply them using [`Chain::apply_delta()`].
+    /// Requires the `save` feature.
+    #[cfg(feature = "save")]
+    pub fn save_delta(
+        &self,
+        path: impl AsRef<Path>,
+        checkpoint: &Chain,
+    ) -> Result<(), ChainFileError> {
+        self.delta_since(checkpoint).save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn delta_only_contains_changes() {
+        let cb = ChainBuilder::new().feed_str("a b c d").into_cb();
+        let old = cb.clone().build().unwrap();
+        let new = cb.feed_str("a b x y").into_cb().build().unwrap();
+
+        let delta = new.delta_since(&old);
+        // ("a", " "), (" ", "b") and ("b", " ") have been seen more, and (" ", "x") and
+        // ("x", " ") are new
+        assert_eq!(delta.changed.len(), 5);
+        assert!(delta.removed.is_empty());
+
+        let reverse = old.delta_since(&new);
+        assert_eq!(reverse.removed.len(), 2);
+
+        let mut applied = old.clone();
+        applied.apply_delta(&delta);
+        assert!(new.delta_since(&applied).is_empty());
+        assert!(applied.delta_since(&new).is_empty());
+    }
+
+    #[test]
+    fn delta_contains_sentence_bounds() {
+        let cb = ChainBuilder::new().feed_sentences("I am here.").into_cb();
+        let old = cb.clone().build().unwrap();
+        let cb = cb.feed_sentences("You are there.").into_cb();
+        let new = cb.clone().build().unwrap();
+
+        let delta = new.delta_since(&old);
+        assert_eq!(delta.sentences.as_ref(), Some(new.sentence_bounds()));
+        let mut applied = old.clone();
+        applied.apply_delta(&delta);
+        assert_eq!(applied.sentence_bounds(), new.sentence_bounds());
+
+        // Only pairs changed
+        let more = cb.feed_str("here").into_cb().build().unwrap();
+        assert!(more.delta_since(&new).sentences.is_none());
+    }
+
+    #[test]
+    fn diff_of_same_chain_is_empty() {
+        use super::CountChange;
+
+        let cb = ChainBuilder::new().feed_str("a b a c").into_cb();
+        let old = cb.clone().build().unwrap();
+        assert!(old.diff(&old.clone()).is_empty());
+
+        let new = cb.feed_str("a b").into_cb().build().unwrap();
+        let diff = old.diff(&new);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        // "a b" only has a single transition, which was already seen
+        assert_eq!(
+            diff.changed,
+            vec![CountChange {
+                pair: ("a", " "),
+                next: "b",
+                before: 1,
+                after: 2
+            }]
+        );
+    }
+
+    #[cfg(feature = "save")]
+    #[test]
+    fn delta_file_roundtrip() {
+        use super::ChainDelta;
+        use crate::Chain;
+
+        let path = std::env::temp_dir().join(format!("markovish-delta-{}", std::process::id()));
+        let old = Chain::from_text("a b c d").unwrap();
+        let new = Chain::from_text("a b c d e f").unwrap();
+        new.save_delta(&path, &old).unwrap();
+        let delta = ChainDelta::load(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(delta, new.delta_since(&old));
+    }
+}
@@ -0,0 +1,163 @@
+//! Ways of joining generated tokens back into text, the opposite of
+//! [`tokenize`](crate::tokenize). Which one to use depends on how the chain was fed: tokens from
+//! [`ChainBuilder::feed_str()`] include whitespace and are simply concatenated, while tokens
+//! split on whitespace need spaces put back between them.
+//!
+//! # Examples
+//!
+//! ```
+//! # use markovish::ChainBuilder;
+//! # use rand::thread_rng;
+//! use markovish::detokenize::SmartJoin;
+//! use markovish::IntoChainBuilder;
+//!
+//! let text = "I am a cat , and you are a dog .";
+//! let cb = ChainBuilder::new().feed_tokens(text.split_whitespace()).into_cb();
+//! let chain = cb.build().unwrap();
+//! let generated = chain.generate_string_with(&mut thread_rng(), 20, &SmartJoin).unwrap();
+//! assert!(!generated.contains(" ,"));
+//! ```
+//!
+//! [`ChainBuilder::feed_str()`]: crate::ChainBuilder::feed_str
+
+use rand::Rng;
+
+use crate::tokenize::{WordBounds, Words};
+use crate::Chain;
+
+/// Joins tokens into text.
+pub trait Detokenizer {
+    /// Returns the text made up of `tokens`, in order.
+    fn detokenize(&self, tokens: &[&str]) -> String;
+}
+
+impl<T: Detokenizer + ?Sized> Detokenizer for &T {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        (**self).detokenize(tokens)
+    }
+}
+
+impl<T: Detokenizer + ?Sized> Detokenizer for Box<T> {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        (**self).detokenize(tokens)
+    }
+}
+
+/// Joins tokens without anything between them. Use this for tokens that include whitespace, like
+/// the ones from [`ChainBuilder::feed_str()`](crate::ChainBuilder::feed_str).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Concat;
+
+impl Detokenizer for Concat {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        tokens.concat()
+    }
+}
+
+/// Joins tokens with a single space between them. Use this for tokens split on whitespace, like
+/// the ones from [`Words`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpaceJoin;
+
+impl Detokenizer for SpaceJoin {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        tokens.join(" ")
+    }
+}
+
+/// Joins tokens with a single space between them, except before closing punctuation (like `.`,
+/// `,` and `)`) and after opening brackets. No space is added next to tokens that are already
+/// whitespace, so this works for most ways of splitting text.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::detokenize::{Detokenizer, SmartJoin};
+///
+/// let tokens = ["Hello", ",", "world", "(", "again", ")", "!"];
+/// assert_eq!(SmartJoin.detokenize(&tokens), "Hello, world (again)!");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmartJoin;
+
+impl SmartJoin {
+    /// Characters that are never preceded by a space.
+    const CLOSING: [char; 11] = ['.', ',', ';', ':', '!', '?', ')', ']', '}', '%', '…'];
+    /// Characters that are never followed by a space.
+    const OPENING: [char; 3] = ['(', '[', '{'];
+
+    fn needs_space(prev: &str, next: &str) -> bool {
+        let is_whitespace = |t: &str| t.chars().all(char::is_whitespace);
+        !(is_whitespace(prev)
+            || is_whitespace(next)
+            || next.starts_with(Self::CLOSING)
+            || prev.ends_with(Self::OPENING))
+    }
+}
+
+impl Detokenizer for SmartJoin {
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        let mut text = String::with_capacity(tokens.iter().map(|t| t.len() + 1).sum());
+        let mut prev: Option<&str> = None;
+        for token in tokens {
+            if prev.is_some_and(|prev| Self::needs_space(prev, token)) {
+                text.push(' ');
+            }
+            text.push_str(token);
+            prev = Some(token);
+        }
+        text
+    }
+}
+
+impl Detokenizer for WordBounds {
+    /// Tokens from [`WordBounds`] include whitespace, so they are joined like [`Concat`].
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        Concat.detokenize(tokens)
+    }
+}
+
+impl Detokenizer for Words {
+    /// Whitespace is removed by [`Words`], so tokens are joined like [`SpaceJoin`].
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        SpaceJoin.detokenize(tokens)
+    }
+}
+
+impl Chain {
+    /// Like [`Chain::generate_string()`], but the tokens are joined using `detokenizer`. Use
+    /// this if the chain was not fed with [`ChainBuilder::feed_str()`], such as when splitting
+    /// text on whitespace.
+    ///
+    /// [`ChainBuilder::feed_str()`]: crate::ChainBuilder::feed_str
+    pub fn generate_string_with<R: Rng + ?Sized, D: Detokenizer + ?Sized>(
+        &self,
+        rng: &mut R,
+        n: usize,
+        detokenizer: &D,
+    ) -> Option<String> {
+        let tokens = self.generate_str(rng, n)?;
+        Some(detokenizer.detokenize(&tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Concat, Detokenizer, SmartJoin, SpaceJoin};
+    use crate::tokenize::{Tokenizer, WordBounds, Words};
+
+    #[test]
+    fn round_trips() {
+        let text = "Hello, world! (Bye.)";
+        let tokens = WordBounds.tokenize(text);
+        let tokens: Vec<&str> = tokens.iter().map(|t| t.as_ref()).collect();
+        assert_eq!(Concat.detokenize(&tokens), text);
+        assert_eq!(SmartJoin.detokenize(&tokens), text);
+
+        let tokens = Words.tokenize(text);
+        let tokens: Vec<&str> = tokens.iter().map(|t| t.as_ref()).collect();
+        assert_eq!(SpaceJoin.detokenize(&tokens), text);
+        assert_eq!(Words.detokenize(&tokens), text);
+        assert_eq!(SmartJoin.detokenize(&tokens), text);
+    }
+}
@@ -0,0 +1,514 @@
+//! A [`DiskChain`] keeps its token distributions in a [`redb`] database on disk instead of in
+//! memory, for chains that are too large to fit in RAM. Requires the `redb` feature.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use hashbrown::HashMap;
+use rand::Rng;
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+
+use crate::distribution::{TokenDistribution, TokenDistributionBuilder, Weight};
+use crate::store::TransitionStore;
+use crate::token::{Token, TokenPair, TokenPairRef};
+use crate::ChainBuilder;
+
+/// Maps an encoded [`TokenPair`] to its encoded distribution.
+const DISTRIBUTIONS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("distributions");
+/// Maps an index to an encoded [`TokenPair`], used to choose random pairs.
+const PAIRS: TableDefinition<u64, &[u8]> = TableDefinition::new("pairs");
+
+/// A second order Markov chain stored on disk.
+///
+/// Only the most recently used distributions are kept in memory, in a cache holding at most
+/// `cache_size` distributions. Generation is done using the [`TransitionStore`] trait. Texts too
+/// large to be counted in memory at once can be added in parts using [`DiskChain::feed()`].
+///
+/// # Panics
+///
+/// Since generation cannot fail for in-memory chains, the [`TransitionStore`] implementation will
+/// panic if the database cannot be read. Use [`DiskChain::try_distribution()`] to handle such
+/// errors yourself.
+///
+/// # Examples
+///
+/// ```no_run
+/// use markovish::{ChainBuilder, IntoChainBuilder};
+/// use markovish::disk::DiskChain;
+/// use markovish::store::TransitionStore;
+///
+/// let cb = ChainBuilder::new().feed_str("A very large text").into_cb();
+/// let mut chain = DiskChain::create("chain.redb", cb, 10_000).unwrap();
+/// let more = ChainBuilder::new().feed_str("and some more of it").into_cb();
+/// chain.feed(more).unwrap();
+/// let start = chain.random_pair(&mut rand::thread_rng()).unwrap();
+/// let tokens = chain.generate_tokens(&mut rand::thread_rng(), &start, 100);
+/// ```
+pub struct DiskChain {
+    db: Database,
+    pair_count: u64,
+    cache: Mutex<LruCache>,
+}
+
+impl DiskChain {
+    /// Creates a new database at `path` (replacing any existing one) containing all pairs in
+    /// `builder`.
+    ///
+    /// Only the pairs are stored, so the sentence bounds of `builder` (see
+    /// [`ChainBuilder::feed_sentences()`]) are lost.
+    pub fn create(
+        path: impl AsRef<Path>,
+        builder: ChainBuilder,
+        cache_size: usize,
+    ) -> Result<Self, DiskChainError> {
+        let db = Database::create(path)?;
+        let txn = db.begin_write()?;
+        txn.open_table(DISTRIBUTIONS)?;
+        txn.open_table(PAIRS)?;
+        txn.commit()?;
+
+        let mut chain = Self::from_database(db, cache_size)?;
+        chain.feed(builder)?;
+        Ok(chain)
+    }
+
+    /// Adds the counts of all pairs in `builder` to the database, so that a text too large to be
+    /// counted in memory can be fed in parts. Like with [`DiskChain::create()`], the sentence
+    /// bounds of `builder` are lost.
+    pub fn feed(&mut self, builder: ChainBuilder) -> Result<(), DiskChainError> {
+        let mut pair_count = self.pair_count;
+        let txn = self.db.begin_write()?;
+        {
+            let mut dists = txn.open_table(DISTRIBUTIONS)?;
+            let mut pairs = txn.open_table(PAIRS)?;
+            for (pair, mut counts) in builder.map {
+                let key = encode_pair(&pair.as_ref());
+                let old = match dists.get(key.as_slice())? {
+                    Some(value) => {
+                        Some(decode_counts(value.value()).ok_or(DiskChainError::Corrupt)?)
+                    }
+                    None => None,
+                };
+                if let Some(old) = old {
+                    for (token, n) in old.map {
+                        counts.add_token_n(&token, n);
+                    }
+                } else {
+                    pairs.insert(pair_count, key.as_slice())?;
+                    pair_count += 1;
+                }
+                dists.insert(key.as_slice(), encode_counts(&counts).as_slice())?;
+            }
+        }
+        txn.commit()?;
+
+        self.pair_count = pair_count;
+        // The cached distributions may be outdated
+        let cache = self.cache.get_mut().unwrap();
+        *cache = LruCache::new(cache.capacity);
+        Ok(())
+    }
+
+    /// Opens an existing database at `path`, created using [`DiskChain::create()`].
+    pub fn open(path: impl AsRef<Path>, cache_size: usize) -> Result<Self, DiskChainError> {
+        Self::from_database(Database::open(path)?, cache_size)
+    }
+
+    fn from_database(db: Database, cache_size: usize) -> Result<Self, DiskChainError> {
+        let pair_count = {
+            let txn = db.begin_read()?;
+            let pairs = txn.open_table(PAIRS)?;
+            pairs.len()?
+        };
+
+        Ok(Self {
+            db,
+            pair_count,
+            cache: Mutex::new(LruCache::new(cache_size)),
+        })
+    }
+
+    /// Returns the amount of pairs in the chain.
+    pub fn pair_count(&self) -> u64 {
+        self.pair_count
+    }
+
+    /// Finds the distribution of tokens that may follow `prev`, first looking in the cache and
+    /// then on disk.
+    pub fn try_distribution(
+        &self,
+        prev: &TokenPairRef<'_>,
+    ) -> Result<Option<Arc<TokenDistribution>>, DiskChainError> {
+        if let Some(dist) = self.cache.lock().unwrap().get(prev) {
+            return Ok(Some(dist));
+        }
+
+        let txn = self.db.begin_read()?;
+        let dists = txn.open_table(DISTRIBUTIONS)?;
+        let Some(value) = dists.get(encode_pair(prev).as_slice())? else {
+            return Ok(None);
+        };
+        let dist = Arc::new(decode_distribution(value.value()).ok_or(DiskChainError::Corrupt)?);
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(TokenPair::from(prev), Arc::clone(&dist));
+        Ok(Some(dist))
+    }
+
+    /// Randomly chooses a pair from the chain, or `None` if it is empty.
+    pub fn try_random_pair<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> Result<Option<TokenPair>, DiskChainError> {
+        if self.pair_count == 0 {
+            return Ok(None);
+        }
+
+        let txn = self.db.begin_read()?;
+        let pairs = txn.open_table(PAIRS)?;
+        let i = rng.gen_range(0..self.pair_count);
+        let value = pairs.get(i)?.ok_or(DiskChainError::Corrupt)?;
+        let (pair, _) = decode_pair(value.value()).ok_or(DiskChainError::Corrupt)?;
+        Ok(Some(pair))
+    }
+}
+
+impl TransitionStore for DiskChain {
+    type Dist<'a> = Arc<TokenDistribution>;
+    type Token<'a> = Token;
+
+    fn lookup(&self, prev: &TokenPairRef<'_>) -> Option<Self::Dist<'_>> {
+        self.try_distribution(prev)
+            .expect("failed to read distribution from disk")
+    }
+
+    fn random_pair<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(Token, Token)> {
+        self.try_random_pair(rng)
+            .expect("failed to read pair from disk")
+            .map(|TokenPair(left, right)| (left, right))
+    }
+
+    fn generate_next<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+    ) -> Option<Token> {
+        Some(self.lookup(prev)?.get_random_token(rng).clone())
+    }
+}
+
+impl fmt::Debug for DiskChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiskChain")
+            .field("pair_count", &self.pair_count)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Errors that can occur when using a [`DiskChain`].
+#[derive(Debug)]
+pub enum DiskChainError {
+    /// The underlying database failed.
+    Database(Box<redb::Error>),
+    /// The database contained data that could not be decoded.
+    Corrupt,
+}
+
+impl fmt::Display for DiskChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database(e) => write!(f, "database error: {e}"),
+            Self::Corrupt => write!(f, "database contains corrupt chain data"),
+        }
+    }
+}
+
+impl std::error::Error for DiskChainError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(e) => Some(e.as_ref()),
+            Self::Corrupt => None,
+        }
+    }
+}
+
+macro_rules! impl_from_redb_error {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for DiskChainError {
+                fn from(value: $t) -> Self {
+                    Self::Database(Box::new(value.into()))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_redb_error!(
+    redb::Error,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+/// Marks the lack of a previous or next entry in [`LruCache`].
+const NO_ENTRY: usize = usize::MAX;
+
+/// A least recently used cache of distributions. The entries form a doubly linked list from the
+/// most to the least recently used, stored as indices into a [`Vec`], so that both looking up and
+/// evicting an entry take constant time.
+struct LruCache {
+    capacity: usize,
+    /// Maps a pair to the index of its entry.
+    index: HashMap<TokenPair, usize>,
+    entries: Vec<LruEntry>,
+    /// The most recently used entry.
+    head: usize,
+    /// The least recently used entry, which is evicted first.
+    tail: usize,
+}
+
+struct LruEntry {
+    pair: TokenPair,
+    dist: Arc<TokenDistribution>,
+    /// The entry used right before this one.
+    prev: usize,
+    /// The entry used right after this one.
+    next: usize,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            index: HashMap::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            head: NO_ENTRY,
+            tail: NO_ENTRY,
+        }
+    }
+
+    fn get(&mut self, pair: &TokenPairRef<'_>) -> Option<Arc<TokenDistribution>> {
+        let i = *self.index.get(pair)?;
+        self.unlink(i);
+        self.push_front(i);
+        Some(Arc::clone(&self.entries[i].dist))
+    }
+
+    fn insert(&mut self, pair: TokenPair, dist: Arc<TokenDistribution>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        // Another thread may have read the same pair from disk at the same time
+        if let Some(&i) = self.index.get(&pair) {
+            self.entries[i].dist = dist;
+            self.unlink(i);
+            self.push_front(i);
+            return;
+        }
+
+        let i = if self.entries.len() < self.capacity {
+            self.entries.push(LruEntry {
+                pair: pair.clone(),
+                dist,
+                prev: NO_ENTRY,
+                next: NO_ENTRY,
+            });
+            self.entries.len() - 1
+        } else {
+            // Reuse the least recently used entry
+            let i = self.tail;
+            self.unlink(i);
+            let entry = &mut self.entries[i];
+            self.index.remove(&entry.pair);
+            entry.pair = pair.clone();
+            entry.dist = dist;
+            i
+        };
+        self.index.insert(pair, i);
+        self.push_front(i);
+    }
+
+    /// Removes entry `i` from the list, without removing it from the cache.
+    fn unlink(&mut self, i: usize) {
+        let LruEntry { prev, next, .. } = self.entries[i];
+        match prev {
+            NO_ENTRY => self.head = next,
+            prev => self.entries[prev].next = next,
+        }
+        match next {
+            NO_ENTRY => self.tail = prev,
+            next => self.entries[next].prev = prev,
+        }
+    }
+
+    /// Makes entry `i`, which must not be in the list, the most recently used.
+    fn push_front(&mut self, i: usize) {
+        self.entries[i].prev = NO_ENTRY;
+        self.entries[i].next = self.head;
+        match self.head {
+            NO_ENTRY => self.tail = i,
+            head => self.entries[head].prev = i,
+        }
+        self.head = i;
+    }
+}
+
+fn encode_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn decode_str(buf: &[u8]) -> Option<(&str, &[u8])> {
+    let (len, rest) = buf.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (s, rest) = rest.split_at(len);
+    Some((std::str::from_utf8(s).ok()?, rest))
+}
+
+fn encode_pair(pair: &TokenPairRef<'_>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(pair.0.len() + pair.1.len() + 8);
+    encode_str(&mut buf, pair.0);
+    encode_str(&mut buf, pair.1);
+    buf
+}
+
+fn decode_pair(buf: &[u8]) -> Option<(TokenPair, &[u8])> {
+    let (left, rest) = decode_str(buf)?;
+    let (right, rest) = decode_str(rest)?;
+    Some((TokenPair::new(left, right), rest))
+}
+
+fn encode_counts(counts: &TokenDistributionBuilder) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for (token, n) in &counts.map {
+        encode_str(&mut buf, token);
+        buf.extend_from_slice(&(*n as u64).to_le_bytes());
+    }
+    buf
+}
+
+fn decode_counts(mut buf: &[u8]) -> Option<TokenDistributionBuilder> {
+    let mut b = TokenDistributionBuilder::new();
+    while !buf.is_empty() {
+        let (token, rest) = decode_str(buf)?;
+        let (n, rest) = rest.split_first_chunk::<8>()?;
+        b.add_token_n(token, Weight::try_from(u64::from_le_bytes(*n)).ok()?);
+        buf = rest;
+    }
+
+    if b.map.is_empty() {
+        return None;
+    }
+    Some(b)
+}
+
+fn decode_distribution(buf: &[u8]) -> Option<TokenDistribution> {
+    decode_counts(buf).map(TokenDistributionBuilder::build)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::thread_rng;
+
+    use super::{DiskChain, LruCache};
+    use crate::distribution::TokenDistributionBuilder;
+    use crate::store::TransitionStore;
+    use crate::token::TokenPair;
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn generate_from_disk() {
+        let dir = std::env::temp_dir().join(format!("markovish-disk-{}", std::process::id()));
+        let cb = ChainBuilder::new().feed_str("I am-full!of?cats").into_cb();
+        // A tiny cache, to make sure eviction works
+        let chain = DiskChain::create(&dir, cb, 2).unwrap();
+        assert_eq!(chain.pair_count(), 7);
+
+        let tokens = chain
+            .generate_tokens(&mut thread_rng(), &("I".into(), " ".into()), 7)
+            .unwrap();
+        assert_eq!(tokens, vec!["am", "-", "full", "!", "of", "?", "cats"]);
+        assert_eq!(
+            chain
+                .generate_tokens(&mut thread_rng(), &("I".into(), " ".into()), 13)
+                .unwrap()
+                .len(),
+            13
+        );
+        assert!(chain.lookup(&("You", " ")).is_none());
+
+        drop(chain);
+        let reopened = DiskChain::open(&dir, 10).unwrap();
+        assert!(reopened.random_pair(&mut thread_rng()).is_some());
+        std::fs::remove_file(dir).unwrap();
+    }
+
+    #[test]
+    fn feed_adds_to_counts() {
+        let dir = std::env::temp_dir().join(format!("markovish-feed-{}", std::process::id()));
+        let cb = ChainBuilder::new().feed_str("I am a cat").into_cb();
+        let mut chain = DiskChain::create(&dir, cb, 10).unwrap();
+        assert_eq!(chain.pair_count(), 5);
+        // Cached before feeding, so the cache has to be updated
+        assert_eq!(chain.lookup(&("a", " ")).unwrap().count("cat"), 1);
+
+        let cb = ChainBuilder::new()
+            .feed_str("I am a dog, I think")
+            .into_cb();
+        chain.feed(cb).unwrap();
+        assert_eq!(chain.pair_count(), 9);
+        let dist = chain.lookup(&("a", " ")).unwrap();
+        assert_eq!((dist.count("cat"), dist.count("dog")), (1, 1));
+        assert_eq!(chain.lookup(&("I", " ")).unwrap().count("am"), 2);
+
+        drop(chain);
+        let reopened = DiskChain::open(&dir, 10).unwrap();
+        assert_eq!(reopened.pair_count(), 9);
+        assert!(reopened.lookup(&(" ", "think")).is_none());
+        assert_eq!(reopened.lookup(&("a", " ")).unwrap().count("dog"), 1);
+        std::fs::remove_file(dir).unwrap();
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let dist = |token: &str| {
+            let mut b = TokenDistributionBuilder::new();
+            b.add_token(token);
+            Arc::new(b.build())
+        };
+        let mut cache = LruCache::new(2);
+        cache.insert(TokenPair::new("a", " "), dist("1"));
+        cache.insert(TokenPair::new("b", " "), dist("2"));
+        assert!(cache.get(&("a", " ")).is_some());
+
+        // "b" was used last, so it is evicted
+        cache.insert(TokenPair::new("c", " "), dist("3"));
+        assert!(cache.get(&("b", " ")).is_none());
+        assert!(cache.get(&("a", " ")).is_some());
+        assert!(cache.get(&("c", " ")).is_some());
+
+        // Inserting a pair again replaces it, without evicting anything
+        cache.insert(TokenPair::new("c", " "), dist("4"));
+        assert_eq!(cache.get(&("c", " ")).unwrap().count("4"), 1);
+        assert!(cache.get(&("a", " ")).is_some());
+
+        cache.insert(TokenPair::new("d", " "), dist("5"));
+        assert!(cache.get(&("c", " ")).is_none());
+        assert_eq!(cache.entries.len(), 2);
+
+        let mut empty = LruCache::new(0);
+        empty.insert(TokenPair::new("a", " "), dist("1"));
+        assert!(empty.get(&("a", " ")).is_none());
+    }
+}
@@ -1,7 +1,10 @@
 //! [`TokenDistribution`] are representations of how common [`Token`]s are, and are paired up with
 //! a [`TokenPair`](crate::token::TokenPair) in a [`Chain`](crate::Chain).
 
-use hashbrown::HashMap;
+use std::fmt;
+use std::mem::size_of;
+
+use hashbrown::{HashMap, HashSet};
 use rand::Rng;
 use rand_distr::{weighted_alias::WeightedAliasIndex, Distribution};
 
@@ -10,14 +13,51 @@ use serde::{Deserialize, Serialize};
 
 use crate::token::Token;
 
+/// The type used to count how many times a token has been seen, and to weight the
+/// [`TokenDistribution`].
+///
+/// This is [`usize`] by default. If the `u32-weights` feature is enabled it is [`u32`] instead,
+/// which uses less memory but can only count up to [`u32::MAX`] occurances of a token pair.
+#[cfg(not(feature = "u32-weights"))]
+pub type Weight = usize;
+
+/// The type used to count how many times a token has been seen, and to weight the
+/// [`TokenDistribution`].
+///
+/// This is [`u32`] since the `u32-weights` feature is enabled, which uses less memory than
+/// [`usize`] but can only count up to [`u32::MAX`] occurances of a token pair.
+#[cfg(feature = "u32-weights")]
+pub type Weight = u32;
+
 /// A distribution of choices and their likelyhood.
+///
+/// Most token pairs in a normal text are only ever followed by a single token, so those are
+/// stored without a sampling table and never need to touch the random number generator.
+///
+/// The counts each distribution was built from are kept, so that they can be queried using
+/// [`TokenDistribution::count()`], and so that the sampling tables can be rebuilt (and validated)
+/// when a distribution is deserialized, instead of trusting whatever tables were stored.
 #[derive(Clone, Debug)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TokenDistribution {
-    /// Mappings of index in choices to their likelyhood.
-    dist: WeightedAliasIndex<usize>,
-    /// The actual choices
-    choices: Vec<Token>,
+    repr: Repr,
+}
+
+/// How a [`TokenDistribution`] is stored. Only created by [`TokenDistribution::new_unchecked()`],
+/// so that the sampling table is always built from the weights next to it.
+#[derive(Clone, Debug)]
+enum Repr {
+    /// Only one token has ever been seen, so it is always chosen. Also contains how many times
+    /// it was seen.
+    Single(Token, Weight),
+    /// Two or more tokens have been seen, and one is chosen depending on how common it is.
+    Weighted {
+        /// Mappings of index in choices to their likelyhood.
+        dist: WeightedAliasIndex<Weight>,
+        /// The actual choices
+        choices: Vec<Token>,
+        /// How many times each choice was seen.
+        weights: Vec<Weight>,
+    },
 }
 
 impl TokenDistribution {
@@ -25,18 +65,305 @@ impl TokenDistribution {
         TokenDistributionBuilder::new()
     }
 
-    pub fn get_random_token(&self, rng: &mut impl Rng) -> &Token {
-        &self.choices[self.dist.sample(rng)]
+    pub fn get_random_token<R: Rng + ?Sized>(&self, rng: &mut R) -> &Token {
+        match &self.repr {
+            Repr::Single(token, _) => token,
+            Repr::Weighted { dist, choices, .. } => &choices[dist.sample(rng)],
+        }
+    }
+
+    /// Returns an iterator of every token in this distribution, together with how many times it
+    /// has been seen. The order is unspecified.
+    pub fn iter(&self) -> impl Iterator<Item = (&Token, Weight)> {
+        let (choices, weights) = self.parts();
+        choices.iter().zip(weights.iter().copied())
+    }
+
+    /// Returns the amount of different tokens in this distribution.
+    pub fn len(&self) -> usize {
+        self.parts().0.len()
+    }
+
+    /// Always `false`, since a distribution can never be empty. Exists since
+    /// [`TokenDistribution::len()`] does.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns how many times `token` has been seen in this distribution, or zero if it never has.
+    pub fn count(&self, token: &str) -> Weight {
+        let (choices, weights) = self.parts();
+        choices
+            .iter()
+            .position(|c| c == token)
+            .map_or(0, |i| weights[i])
+    }
+
+    /// Returns the sum of the counts of all tokens in this distribution.
+    pub fn total(&self) -> u64 {
+        self.parts().1.iter().map(|w| *w as u64).sum()
+    }
+
+    /// Returns the probability that `token` is chosen from this distribution, which is zero if
+    /// it has never been seen.
+    pub fn probability(&self, token: &str) -> f64 {
+        self.count(token) as f64 / self.total() as f64
+    }
+
+    /// Returns the [Shannon entropy](https://en.wikipedia.org/wiki/Entropy_(information_theory))
+    /// of this distribution in bits. A distribution with a single token has an entropy of zero,
+    /// and the entropy grows the more tokens there are to choose from, and the more even their
+    /// probabilities are.
+    pub fn entropy(&self) -> f64 {
+        let total = self.total() as f64;
+        self.iter()
+            .map(|(_, n)| {
+                let p = n as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    /// Creates a distribution from tokens and their weights, for when a distribution is made by
+    /// hand instead of from a text. A token with twice the weight of another is twice as likely
+    /// to be chosen.
+    ///
+    /// Fails if there are no tokens, if a token is listed twice, or if a weight is zero or does
+    /// not fit in a [`Weight`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use markovish::distribution::{DistributionError, TokenDistribution};
+    ///
+    /// let dist = TokenDistribution::from_weights(vec![("yes".into(), 3), ("no".into(), 1)]);
+    /// assert_eq!(dist.unwrap().probability("yes"), 0.75);
+    ///
+    /// let dist = TokenDistribution::from_weights(vec![("yes".into(), 3), ("yes".into(), 1)]);
+    /// assert_eq!(dist, Err(DistributionError::DuplicateChoice("yes".into())));
+    /// ```
+    pub fn from_weights(weights: Vec<(Token, u64)>) -> Result<Self, DistributionError> {
+        let (choices, weights): (Vec<_>, Vec<_>) = weights.into_iter().unzip();
+        let weights = weights
+            .into_iter()
+            .map(Weight::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|_| DistributionError::InvalidWeights)?;
+        Self::try_from_parts(choices, weights)
+    }
+
+    /// Turns the distribution back into a builder with the same counts, so that more tokens can
+    /// be added to it.
+    pub fn into_builder(self) -> TokenDistributionBuilder {
+        let map = match self.repr {
+            Repr::Single(token, n) => HashMap::from([(token, n)]),
+            Repr::Weighted {
+                choices, weights, ..
+            } => choices.into_iter().zip(weights).collect(),
+        };
+        TokenDistributionBuilder { map }
+    }
+
+    /// Creates a distribution from choices and how many times each was seen, where the weight of
+    /// a choice is at the same index as the choice itself.
+    ///
+    /// Fails if there is not exactly one weight per choice, if there are no choices, if a choice
+    /// is listed twice, or if a weight is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use markovish::distribution::{DistributionError, TokenDistribution};
+    ///
+    /// let dist = TokenDistribution::try_from_parts(vec!["yes".into(), "no".into()], vec![3, 1]);
+    /// assert_eq!(dist.unwrap().count("yes"), 3);
+    ///
+    /// let dist = TokenDistribution::try_from_parts(vec!["yes".into(), "no".into()], vec![3, 0]);
+    /// assert_eq!(dist, Err(DistributionError::ZeroWeight));
+    /// ```
+    pub fn try_from_parts(
+        choices: Vec<Token>,
+        weights: Vec<Weight>,
+    ) -> Result<Self, DistributionError> {
+        if choices.len() > 1 {
+            let mut seen = HashSet::with_capacity(choices.len());
+            if let Some(token) = choices.iter().find(|token| !seen.insert(*token)) {
+                return Err(DistributionError::DuplicateChoice(token.clone()));
+            }
+        }
+        Self::new_unchecked(choices, weights)
+    }
+
+    /// Like [`TokenDistribution::try_from_parts()`], but without checking for duplicate choices,
+    /// for when the choices are already known to be unique. Everything else is still checked.
+    fn new_unchecked(
+        mut choices: Vec<Token>,
+        mut weights: Vec<Weight>,
+    ) -> Result<Self, DistributionError> {
+        if choices.len() != weights.len() {
+            return Err(DistributionError::LengthMismatch {
+                choices: choices.len(),
+                weights: weights.len(),
+            });
+        }
+        if choices.is_empty() {
+            return Err(DistributionError::Empty);
+        }
+        if weights.contains(&0) {
+            return Err(DistributionError::ZeroWeight);
+        }
+
+        if choices.len() == 1 {
+            // Unwrap is safe, we just checked the length
+            let repr = Repr::Single(choices.pop().unwrap(), weights.pop().unwrap());
+            return Ok(Self { repr });
+        }
+
+        let dist = WeightedAliasIndex::new(table_weights(&weights))
+            .map_err(|_| DistributionError::InvalidWeights)?;
+        let repr = Repr::Weighted {
+            dist,
+            choices,
+            weights,
+        };
+        Ok(Self { repr })
+    }
+
+    /// The choices of this distribution, and how many times each has been seen.
+    pub(crate) fn parts(&self) -> (&[Token], &[Weight]) {
+        match &self.repr {
+            Repr::Single(token, n) => (std::slice::from_ref(token), std::slice::from_ref(n)),
+            Repr::Weighted {
+                choices, weights, ..
+            } => (choices, weights),
+        }
+    }
+
+    /// Bytes on the heap owned by the distribution itself, not counting the heap memory of its
+    /// tokens.
+    pub(crate) fn heap_size(&self) -> usize {
+        match &self.repr {
+            Repr::Single(..) => 0,
+            Repr::Weighted {
+                choices, weights, ..
+            } => {
+                // The alias table has one alias (u32) and one odds (Weight) per choice
+                let alias_table = choices.len() * (size_of::<u32>() + size_of::<Weight>());
+                choices.capacity() * size_of::<Token>()
+                    + weights.capacity() * size_of::<Weight>()
+                    + alias_table
+            }
+        }
+    }
+}
+
+/// Two distributions are equal if they have the same choices, seen the same amount of times.
+impl PartialEq for TokenDistribution {
+    fn eq(&self, other: &Self) -> bool {
+        let (choices, weights) = self.parts();
+        let (other_choices, other_weights) = other.parts();
+        if choices.len() != other_choices.len() {
+            return false;
+        }
+
+        let mut counts: Vec<_> = choices.iter().zip(weights).collect();
+        let mut other_counts: Vec<_> = other_choices.iter().zip(other_weights).collect();
+        counts.sort_unstable();
+        other_counts.sort_unstable();
+        counts == other_counts
+    }
+}
+
+impl Eq for TokenDistribution {}
+
+/// Reasons a [`TokenDistribution`] could not be created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DistributionError {
+    /// There are no choices.
+    Empty,
+    /// There is not exactly one weight per choice.
+    LengthMismatch {
+        /// The amount of choices.
+        choices: usize,
+        /// The amount of weights.
+        weights: usize,
+    },
+    /// A choice has a weight of zero, so it could never be chosen.
+    ZeroWeight,
+    /// The same choice is listed more than once.
+    DuplicateChoice(Token),
+    /// The weights could not be used to create a distribution, for example because their sum
+    /// is too large.
+    InvalidWeights,
+}
+
+impl fmt::Display for DistributionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "distribution has no choices"),
+            Self::LengthMismatch { choices, weights } => write!(
+                f,
+                "distribution has {choices} choices but {weights} weights"
+            ),
+            Self::ZeroWeight => write!(f, "distribution contains a weight of zero"),
+            Self::DuplicateChoice(token) => {
+                write!(
+                    f,
+                    "distribution contains the choice {token:?} more than once"
+                )
+            }
+            Self::InvalidWeights => write!(f, "distribution weights are invalid"),
+        }
+    }
+}
+
+impl std::error::Error for DistributionError {}
+
+/// How a [`TokenDistribution`] is serialized. Only the counts are stored, sorted by their
+/// tokens; the sampling tables are rebuilt when deserializing.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+#[serde(rename = "TokenDistribution")]
+struct SerializedDistribution<'a> {
+    choices: Vec<&'a Token>,
+    weights: Vec<Weight>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "TokenDistribution")]
+struct DeserializedDistribution {
+    choices: Vec<Token>,
+    weights: Vec<Weight>,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for TokenDistribution {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (choices, weights) = self.parts();
+        let mut counts: Vec<_> = choices.iter().zip(weights.iter().copied()).collect();
+        counts.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        let (choices, weights) = counts.into_iter().unzip();
+        SerializedDistribution { choices, weights }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for TokenDistribution {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = DeserializedDistribution::deserialize(deserializer)?;
+        Self::try_from_parts(raw.choices, raw.weights).map_err(serde::de::Error::custom)
     }
 }
 
 /// Builder for [`TokenDistribution`]. Used when parsing a text to add a lot of words, and then to
 /// build a list of [`TokenDistribution`] using how many times they appeared.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TokenDistributionBuilder {
     /// Counts how many times a token is likely to appear.
-    map: HashMap<String, usize>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::sorted::map"))]
+    pub(crate) map: HashMap<Token, Weight>,
 }
 
 impl TokenDistributionBuilder {
@@ -46,7 +373,9 @@ impl TokenDistributionBuilder {
         }
     }
 
-    /// Creates a weighted distribution for the likelyhood of tokens to appear.
+    /// Creates a weighted distribution for the likelyhood of tokens to appear. If only a single
+    /// token has been added, no sampling table is needed and that token is always chosen. The
+    /// choices are sorted, so that the same counts always give the same distribution.
     ///
     /// # Panics
     ///
@@ -54,35 +383,229 @@ impl TokenDistributionBuilder {
     ///
     /// - There are no inserted tokens
     pub fn build(self) -> TokenDistribution {
-        let mut choices = Vec::with_capacity(self.map.len());
-        let mut occurances = Vec::with_capacity(self.map.len());
-        for (token, n) in self.map {
-            choices.push(token);
-            occurances.push(n);
+        let mut counts: Vec<_> = self.map.into_iter().collect();
+        counts.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let (choices, weights) = counts.into_iter().unzip();
+        // The keys of a map are always unique
+        TokenDistribution::new_unchecked(choices, weights)
+            .expect("failed to create token distribution")
+    }
+
+    /// Add an occurance of this token.
+    pub fn add_token(&mut self, token: &str) {
+        match self.map.get_mut(token) {
+            Some(n) => {
+                *n = n.saturating_add(1);
+            }
+            None => {
+                self.map.insert(Token::from(token), 1);
+            }
         }
+    }
 
-        TokenDistribution {
-            dist: WeightedAliasIndex::new(occurances)
-                .expect("failed to create weighted alias index"),
-            choices,
+    /// Rescales the counts of all tokens so that the most common token is counted `max_weight`
+    /// times, keeping the ratios between tokens as well as possible. Rare tokens are never
+    /// rounded down to zero, so no token is lost. If no token has been counted more than
+    /// `max_weight` times, nothing is changed.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `max_weight` is zero.
+    pub fn quantize(&mut self, max_weight: Weight) {
+        assert!(max_weight > 0, "cannot quantize to a max weight of zero");
+        let Some(&largest) = self.map.values().max() else {
+            return;
+        };
+        if largest <= max_weight {
+            return;
+        }
+
+        for n in self.map.values_mut() {
+            *n = scale(*n, largest, max_weight);
         }
     }
 
-    /// Add an occurance of this token.
-    pub fn add_token(&mut self, token: &str) {
+    /// Removes an occurance of `token`, forgetting it completely if it was only seen once.
+    /// Returns `false` if the token had never been seen.
+    pub fn remove_token(&mut self, token: &str) -> bool {
         match self.map.get_mut(token) {
+            Some(1) => {
+                self.map.remove(token);
+                true
+            }
             Some(n) => {
-                *n += 1;
+                *n -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Multiplies the count of every token with `factor`, removing tokens whose count reaches
+    /// zero. Counts are whole numbers, so they are rounded up or down at random in proportion to
+    /// how close they are, which makes the decay exact on average. Otherwise a token seen once
+    /// would either never be forgotten, or be forgotten right away.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `factor` is not between zero and one.
+    pub fn decay<R: Rng + ?Sized>(&mut self, rng: &mut R, factor: f64) {
+        assert!(
+            (0.0..=1.0).contains(&factor),
+            "decay factor must be between zero and one"
+        );
+        self.map.retain(|_, n| {
+            let scaled = *n as f64 * factor;
+            let mut decayed = scaled.floor();
+            if rng.gen_bool(scaled - decayed) {
+                decayed += 1.0;
+            }
+            *n = decayed as Weight;
+            *n > 0
+        });
+    }
+
+    /// Add `n` occurances of this token at once. Adding zero occurances does nothing.
+    pub fn add_token_n(&mut self, token: &str, n: Weight) {
+        if n == 0 {
+            return;
+        }
+
+        match self.map.get_mut(token) {
+            Some(c) => {
+                *c = c.saturating_add(n);
             }
             None => {
-                self.map.insert(token.to_string(), 1);
+                self.map.insert(Token::from(token), n);
             }
         }
     }
 }
 
+/// The weights to build a sampling table from. A table can only be built if no weight is larger
+/// than [`Weight::MAX`] divided by the amount of weights, so larger weights are scaled down while
+/// keeping their proportions. This also means that the sum of the weights fits in a [`Weight`].
+pub(crate) fn table_weights(weights: &[Weight]) -> Vec<Weight> {
+    let max_weight = Weight::MAX / Weight::try_from(weights.len()).unwrap_or(Weight::MAX);
+    match weights.iter().max() {
+        Some(&largest) if largest > max_weight => weights
+            .iter()
+            .map(|n| scale(*n, largest, max_weight))
+            .collect(),
+        _ => weights.to_vec(),
+    }
+}
+
+/// Scales `n` so that `largest` becomes `max_weight`, without rounding it down to zero.
+fn scale(n: Weight, largest: Weight, max_weight: Weight) -> Weight {
+    let scaled = (n as u128 * max_weight as u128 + largest as u128 / 2) / largest as u128;
+    (scaled as Weight).max(1)
+}
+
 impl Default for TokenDistributionBuilder {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::{DistributionError, Repr, TokenDistribution, TokenDistributionBuilder};
+
+    #[test]
+    fn single_token_is_single_variant() {
+        let mut b = TokenDistributionBuilder::new();
+        b.add_token("only");
+        b.add_token("only");
+        let dist = b.build();
+        assert!(matches!(dist.repr, Repr::Single(ref t, 2) if t == "only"));
+        assert_eq!(dist.get_random_token(&mut thread_rng()), "only");
+    }
+
+    #[test]
+    fn invalid_parts_are_rejected() {
+        assert_eq!(
+            TokenDistribution::try_from_parts(vec!["a".into(), "b".into()], vec![1]).unwrap_err(),
+            DistributionError::LengthMismatch {
+                choices: 2,
+                weights: 1
+            }
+        );
+        assert_eq!(
+            TokenDistribution::try_from_parts(Vec::new(), Vec::new()).unwrap_err(),
+            DistributionError::Empty
+        );
+        assert_eq!(
+            TokenDistribution::try_from_parts(vec!["a".into(), "b".into()], vec![1, 0])
+                .unwrap_err(),
+            DistributionError::ZeroWeight
+        );
+        assert_eq!(
+            TokenDistribution::try_from_parts(vec!["a".into(), "a".into()], vec![1, 1])
+                .unwrap_err(),
+            DistributionError::DuplicateChoice("a".into())
+        );
+    }
+
+    #[cfg(feature = "save")]
+    #[test]
+    fn deserialize_validates_and_rebuilds() {
+        use super::Weight;
+
+        // Structs are encoded as their fields in order by bincode
+        let corrupt = bincode::serialize(&(vec!["a", "b"], vec![1 as Weight])).unwrap();
+        let err = bincode::deserialize::<TokenDistribution>(&corrupt).unwrap_err();
+        assert!(err.to_string().contains("2 choices but 1 weights"));
+
+        let valid = bincode::serialize(&(vec!["a", "b"], vec![1 as Weight, 3])).unwrap();
+        let dist = bincode::deserialize::<TokenDistribution>(&valid).unwrap();
+        assert!(matches!(dist.repr, Repr::Weighted { ref weights, .. } if weights == &[1, 3]));
+    }
+
+    #[test]
+    fn quantize_keeps_ratios() {
+        let mut b = TokenDistributionBuilder::new();
+        b.add_token_n("common", 1000);
+        b.add_token_n("half", 500);
+        b.add_token_n("rare", 1);
+        b.quantize(10);
+        assert_eq!(b.map["common"], 10);
+        assert_eq!(b.map["half"], 5);
+        assert_eq!(b.map["rare"], 1, "rare tokens must not be dropped");
+
+        // Already small enough, so nothing should change
+        b.quantize(100);
+        assert_eq!(b.map["common"], 10);
+    }
+
+    #[test]
+    fn several_tokens_are_weighted() {
+        let mut b = TokenDistributionBuilder::new();
+        b.add_token("one");
+        b.add_token("two");
+        let dist = b.build();
+        assert!(matches!(dist.repr, Repr::Weighted { .. }));
+    }
+
+    #[test]
+    fn saturated_counts_can_be_built() {
+        use super::Weight;
+
+        let mut b = TokenDistributionBuilder::new();
+        b.map.insert("common".into(), Weight::MAX);
+        b.add_token("common");
+        b.add_token("rare");
+        let dist = b.build();
+        let (choices, weights) = dist.parts();
+        let count = |token| weights[choices.iter().position(|c| c == token).unwrap()];
+        assert_eq!(count("common"), Weight::MAX);
+        assert_eq!(count("rare"), 1);
+
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            assert_eq!(dist.get_random_token(&mut rng), "common");
+        }
+    }
+}
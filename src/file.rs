@@ -0,0 +1,373 @@
+//! Saving and loading of [`Chain`]s to and from files, using a small versioned header followed by
+//! the chain encoded with [`bincode`](https://crates.io/crates/bincode). Requires the `save`
+//! feature.
+//!
+//! The header makes sure that a file written by an incompatible version of this crate (or with
+//! incompatible features) is rejected with a [`ChainFileError`], instead of being decoded into
+//! garbage. It also contains the length and a CRC-32 checksum of the encoded chain, so that
+//! truncated or otherwise damaged files are detected (see [`ChainFileError::CorruptFile`]).
+//!
+//! The layout of a file is, with all integers in little endian:
+//!
+//! | Bytes | Content                                   |
+//! |-------|-------------------------------------------|
+//! | 8     | [`MAGIC`]                                 |
+//! | 2     | Format version, [`FORMAT_VERSION`]        |
+//! | 1     | Order of the chain, always `2`            |
+//! | 1     | Hasher identifier, always `0`             |
+//! | 4     | Flags, see the `FLAG_*` constants         |
+//! | 8     | Length of the encoded chain               |
+//! | 4     | CRC-32 checksum of the encoded chain      |
+//! | ...   | The chain, encoded with `bincode`         |
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::Chain;
+
+/// The first bytes of every chain file.
+pub const MAGIC: [u8; 8] = *b"MARKOVSH";
+
+/// The version of the file format. Files with any other version are rejected.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The order of the Markov chains stored by this version of the crate.
+const ORDER: u8 = 2;
+
+/// Identifies the hasher used for the maps in the chain.
+const HASHER: u8 = 0;
+
+/// Set if token weights are stored as [`u32`], see the `u32-weights` feature.
+pub const FLAG_U32_WEIGHTS: u32 = 1 << 0;
+
+/// The flags that the files written by this build of the crate have.
+pub(crate) fn current_flags() -> u32 {
+    let mut flags = 0;
+    if cfg!(feature = "u32-weights") {
+        flags |= FLAG_U32_WEIGHTS;
+    }
+    flags
+}
+
+/// Errors that can occur when saving or loading a [`Chain`].
+#[derive(Debug)]
+pub enum ChainFileError {
+    /// Failed to read or write the file.
+    Io(io::Error),
+    /// The file does not start with [`MAGIC`], so it is not a chain file.
+    NotAChainFile,
+    /// The file was written using another format version.
+    UnsupportedVersion(u16),
+    /// The file contains a chain of an order this crate does not support.
+    UnsupportedOrder(u8),
+    /// The file was written using a different hasher.
+    UnsupportedHasher(u8),
+    /// The file was written with different features enabled (see the `FLAG_*` constants).
+    IncompatibleFlags {
+        /// The flags found in the file.
+        found: u32,
+        /// The flags of this build.
+        expected: u32,
+    },
+    /// The chain itself could not be encoded or decoded.
+    Encoding(bincode::Error),
+    /// The file is truncated or damaged, since it does not match its checksum.
+    CorruptFile,
+}
+
+impl fmt::Display for ChainFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::NotAChainFile => write!(f, "not a markovish chain file"),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported chain file version {v}, only {FORMAT_VERSION} is supported"
+            ),
+            Self::UnsupportedOrder(o) => {
+                write!(f, "chain of order {o} is not supported, only order {ORDER}")
+            }
+            Self::UnsupportedHasher(h) => write!(f, "unsupported hasher {h}"),
+            Self::IncompatibleFlags { found, expected } => write!(
+                f,
+                "chain file has flags {found:#x}, but this build requires {expected:#x}"
+            ),
+            Self::Encoding(e) => write!(f, "failed to encode or decode chain: {e}"),
+            Self::CorruptFile => write!(f, "chain file is truncated or corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for ChainFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Encoding(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ChainFileError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<bincode::Error> for ChainFileError {
+    fn from(value: bincode::Error) -> Self {
+        Self::Encoding(value)
+    }
+}
+
+impl Chain {
+    /// Saves the chain to a file at `path`, creating or truncating it. It can be loaded again
+    /// using [`Chain::load()`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I will be saved").unwrap();
+    /// chain.save("chain.markovish").unwrap();
+    /// let loaded = Chain::load("chain.markovish").unwrap();
+    /// ```
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ChainFileError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a chain from a file saved using [`Chain::save()`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChainFileError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the chain in the same format as [`Chain::save()`] to any writer.
+    pub fn write_to(&self, mut writer: impl Write) -> Result<(), ChainFileError> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&[ORDER, HASHER])?;
+        writer.write_all(&current_flags().to_le_bytes())?;
+        write_payload(writer, self)
+    }
+
+    /// Saves the chain like [`Chain::save()`], but compressed using
+    /// [zstd](https://facebook.github.io/zstd/) at the given compression `level` (1-22, where `0`
+    /// means the zstd default). Requires the `zstd` feature.
+    ///
+    /// The file is simply the output of [`Chain::save()`] wrapped in a zstd frame, so it can be
+    /// decompressed using the `zstd` command line tool as well.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I will be saved, but smaller").unwrap();
+    /// chain.save_compressed("chain.markovish.zst", 19).unwrap();
+    /// let loaded = Chain::load_compressed("chain.markovish.zst").unwrap();
+    /// ```
+    #[cfg(feature = "zstd")]
+    pub fn save_compressed(
+        &self,
+        path: impl AsRef<Path>,
+        level: i32,
+    ) -> Result<(), ChainFileError> {
+        let mut encoder = zstd::Encoder::new(BufWriter::new(File::create(path)?), level)?;
+        self.write_to(&mut encoder)?;
+        encoder.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// Loads a chain from a file saved using [`Chain::save_compressed()`]. Requires the `zstd`
+    /// feature.
+    #[cfg(feature = "zstd")]
+    pub fn load_compressed(path: impl AsRef<Path>) -> Result<Self, ChainFileError> {
+        Self::read_from(zstd::Decoder::new(File::open(path)?)?)
+    }
+
+    /// Reads a chain written using [`Chain::write_to()`] or [`Chain::save()`].
+    pub fn read_from(mut reader: impl Read) -> Result<Self, ChainFileError> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => ChainFileError::NotAChainFile,
+            _ => ChainFileError::Io(e),
+        })?;
+        if magic != MAGIC {
+            return Err(ChainFileError::NotAChainFile);
+        }
+
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != FORMAT_VERSION {
+            return Err(ChainFileError::UnsupportedVersion(version));
+        }
+
+        let mut order_and_hasher = [0; 2];
+        reader.read_exact(&mut order_and_hasher)?;
+        let [order, hasher] = order_and_hasher;
+        if order != ORDER {
+            return Err(ChainFileError::UnsupportedOrder(order));
+        }
+        if hasher != HASHER {
+            return Err(ChainFileError::UnsupportedHasher(hasher));
+        }
+
+        let mut flags = [0; 4];
+        reader.read_exact(&mut flags)?;
+        check_flags(u32::from_le_bytes(flags))?;
+
+        read_payload(reader)
+    }
+}
+
+/// Checks that the flags read from a file are the same as the ones of this build.
+pub(crate) fn check_flags(flags: u32) -> Result<(), ChainFileError> {
+    if flags != current_flags() {
+        return Err(ChainFileError::IncompatibleFlags {
+            found: flags,
+            expected: current_flags(),
+        });
+    }
+    Ok(())
+}
+
+/// Writes `value` encoded with `bincode`, prefixed by its length and checksum.
+pub(crate) fn write_payload<T: Serialize>(
+    mut writer: impl Write,
+    value: &T,
+) -> Result<(), ChainFileError> {
+    let payload = bincode::serialize(value)?;
+    writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+    writer.write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    Ok(())
+}
+
+/// Reads a value written using [`write_payload()`], making sure it matches its checksum.
+pub(crate) fn read_payload<T: DeserializeOwned>(
+    mut reader: impl Read,
+) -> Result<T, ChainFileError> {
+    let mut len = [0; 8];
+    reader.read_exact(&mut len).map_err(eof_is_corrupt)?;
+    let len = u64::from_le_bytes(len);
+    let mut checksum = [0; 4];
+    reader.read_exact(&mut checksum).map_err(eof_is_corrupt)?;
+    let checksum = u32::from_le_bytes(checksum);
+
+    // Reading through `take` instead of allocating `len` bytes up front, since a corrupt
+    // length could be huge
+    let mut payload = Vec::new();
+    reader.take(len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != len || crc32fast::hash(&payload) != checksum {
+        return Err(ChainFileError::CorruptFile);
+    }
+
+    Ok(bincode::deserialize(&payload)?)
+}
+
+/// Files ending early are truncated, not unreadable.
+fn eof_is_corrupt(e: io::Error) -> ChainFileError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => ChainFileError::CorruptFile,
+        _ => ChainFileError::Io(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::{ChainFileError, FORMAT_VERSION, MAGIC};
+    use crate::{Chain, IntoChainBuilder};
+
+    #[test]
+    fn roundtrip() {
+        let chain = Chain::from_text("I am-full!of?cats").unwrap();
+        let mut buf = Vec::new();
+        chain.write_to(&mut buf).unwrap();
+        assert!(buf.starts_with(&MAGIC));
+
+        let loaded = Chain::read_from(buf.as_slice()).unwrap();
+        assert_eq!(
+            loaded
+                .generate_n_tokens(&mut thread_rng(), &("I", " "), 7)
+                .unwrap(),
+            vec!["am", "-", "full", "!", "of", "?", "cats"],
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_roundtrip() {
+        let path = std::env::temp_dir().join(format!("markovish-zstd-{}.zst", std::process::id()));
+        let chain = Chain::from_text("I am-full!of?cats").unwrap();
+        chain.save_compressed(&path, 3).unwrap();
+
+        assert!(matches!(
+            Chain::load(&path),
+            Err(ChainFileError::NotAChainFile)
+        ));
+        let loaded = Chain::load_compressed(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.pairs().count(), 7);
+    }
+
+    #[test]
+    fn detects_corruption() {
+        let chain = Chain::from_text("I am-full!of?cats").unwrap();
+        let mut buf = Vec::new();
+        chain.write_to(&mut buf).unwrap();
+
+        let mut flipped = buf.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            Chain::read_from(flipped.as_slice()),
+            Err(ChainFileError::CorruptFile)
+        ));
+
+        for len in [buf.len() - 1, MAGIC.len() + 10] {
+            assert!(matches!(
+                Chain::read_from(&buf[..len]),
+                Err(ChainFileError::CorruptFile)
+            ));
+        }
+    }
+
+    #[test]
+    fn keeps_sentence_bounds() {
+        let chain = crate::ChainBuilder::new()
+            .feed_sentences("I am-full!of?cats")
+            .into_cb()
+            .build()
+            .unwrap();
+        let mut buf = Vec::new();
+        chain.write_to(&mut buf).unwrap();
+        let loaded = Chain::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.sentence_bounds(), chain.sentence_bounds());
+    }
+
+    #[test]
+    fn rejects_garbage_and_newer_versions() {
+        assert!(matches!(
+            Chain::read_from(&b"hello"[..]),
+            Err(ChainFileError::NotAChainFile)
+        ));
+
+        let chain = Chain::from_text("I am-full!of?cats").unwrap();
+        let mut buf = Vec::new();
+        chain.write_to(&mut buf).unwrap();
+        buf[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Chain::read_from(buf.as_slice()),
+            Err(ChainFileError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1
+        ));
+    }
+}
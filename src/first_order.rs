@@ -0,0 +1,143 @@
+//! First order chains, where the next token only depends on the previous token. These are derived
+//! from a second order [`Chain`], and are useful when a [`Chain`] does not know a pair, such as
+//! when backing off, autocompleting a single word, or choosing how to continue a prompt.
+
+use hashbrown::HashMap;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::distribution::{TokenDistribution, TokenDistributionBuilder, Weight};
+use crate::store::Walk;
+use crate::token::{Token, TokenRef};
+use crate::Chain;
+
+/// A first order Markov chain, created using [`Chain::marginalize_to_first_order()`].
+///
+/// # Examples
+///
+/// ```
+/// # use markovish::Chain;
+/// # use rand::thread_rng;
+/// let chain = Chain::from_text("I am a cat. You are a dog.").unwrap();
+/// let first_order = chain.marginalize_to_first_order();
+///
+/// // "a" is followed by " " in both sentences
+/// assert_eq!(first_order.count("a", " "), Some(2));
+/// assert_eq!(first_order.generate_next_token(&mut thread_rng(), "a"), Some(" "));
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FirstOrderChain {
+    map: HashMap<Token, TokenDistribution>,
+}
+
+impl FirstOrderChain {
+    /// Returns an iterator of all tokens that have been followed by another token.
+    pub fn tokens(&self) -> impl Iterator<Item = TokenRef<'_>> {
+        self.map.keys().map(Token::as_str)
+    }
+
+    /// Returns the distribution of tokens following `prev`, if it has been seen.
+    pub fn distribution(&self, prev: &str) -> Option<&TokenDistribution> {
+        self.map.get(prev)
+    }
+
+    /// Returns how many times `next` has followed `prev`, like [`Chain::count()`].
+    pub fn count(&self, prev: &str, next: &str) -> Option<Weight> {
+        self.map.get(prev).map(|dist| dist.count(next))
+    }
+
+    /// Returns the probability that `next` follows `prev`, like [`Chain::probability()`].
+    pub fn probability(&self, prev: &str, next: &str) -> Option<f64> {
+        self.map.get(prev).map(|dist| dist.probability(next))
+    }
+
+    /// Randomly chooses a token to start generating from.
+    pub fn start_token<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenRef<'_>> {
+        self.tokens().choose(rng)
+    }
+
+    /// Generates a token that could follow `prev`, or `None` if `prev` has never been followed
+    /// by anything.
+    pub fn generate_next_token<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &str,
+    ) -> Option<TokenRef<'_>> {
+        self.map
+            .get(prev)
+            .map(|dist| dist.get_random_token(rng).as_str())
+    }
+
+    /// Generates `n` tokens like [`Chain::generate_str()`], starting over from a random token
+    /// whenever the last token has never been followed by anything.
+    pub fn generate_str<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<&str>> {
+        if self.map.is_empty() {
+            return None;
+        }
+
+        // Starting over gives a pair, so the token after the random one is chosen right away
+        let start = |rng: &mut R| {
+            let token = self.start_token(rng)?;
+            Some((token, self.generate_next_token(rng, token)?))
+        };
+        let mut walk = Walk::new(None);
+        let mut res = Vec::with_capacity(n);
+        while res.len() < n {
+            let next = walk.step(
+                rng,
+                |rng, (_, prev)| self.generate_next_token(rng, prev),
+                start,
+            )?;
+            res.push(next);
+        }
+        Some(res)
+    }
+}
+
+impl Chain {
+    /// Creates a [`FirstOrderChain`] by summing the counts of all pairs that end with the same
+    /// token, so that the next token only depends on the previous one.
+    pub fn marginalize_to_first_order(&self) -> FirstOrderChain {
+        let mut builders: HashMap<Token, TokenDistributionBuilder> = HashMap::new();
+        for rights in self.map.values() {
+            for (right, dist) in rights {
+                let builder = match builders.get_mut(right) {
+                    Some(b) => b,
+                    None => builders.entry(right.clone()).or_default(),
+                };
+                for (token, n) in dist.iter() {
+                    builder.add_token_n(token, n);
+                }
+            }
+        }
+
+        let map = builders.into_iter().map(|(t, b)| (t, b.build())).collect();
+        FirstOrderChain { map }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use crate::Chain;
+
+    #[test]
+    fn sums_over_first_token() {
+        let chain = Chain::from_text("a x b. c x d. e x b").unwrap();
+        let first_order = chain.marginalize_to_first_order();
+
+        assert_eq!(first_order.count(" ", "b"), Some(2));
+        assert_eq!(first_order.count(" ", "d"), Some(1));
+        assert_eq!(first_order.probability("x", " "), Some(1.0));
+        assert_eq!(first_order.count("b", "."), Some(1));
+        assert_eq!(first_order.count("missing", "b"), None);
+
+        let text = first_order.generate_str(&mut thread_rng(), 7).unwrap();
+        assert_eq!(text.len(), 7);
+    }
+}
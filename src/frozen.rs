@@ -0,0 +1,629 @@
+//! A [`FrozenChain`] is an immutable version of a [`Chain`], optimized for being read from many
+//! times (and by many threads) once it has been created.
+
+use std::fmt;
+
+use rand::Rng;
+
+use crate::chain::Chain;
+use crate::distribution::{self, Weight};
+use crate::store::Walk;
+use crate::token::{Token, TokenPairRef, TokenRef};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// An immutable second order Markov chain, stored as sorted and flattened arrays instead of a
+/// hash map.
+///
+/// Every token is stored exactly once in a sorted vocabulary, and both pairs and the tokens that
+/// may follow them are stored as [`u32`] indices into that vocabulary. The following tokens of all
+/// pairs are stored in a single array, together with a running total of their weights, so that
+/// looking up a pair and choosing the next token are both done using binary search. This makes a
+/// [`FrozenChain`] smaller than a [`Chain`] and faster to serialize, while generation works the
+/// same way. Since there is no interior mutability, it can be shared between threads without any
+/// locking.
+///
+/// Unlike a [`Chain`], everything in a [`FrozenChain`] is sorted, including the choices of every
+/// distribution. The order of [`FrozenChain::pairs()`], the serialized output and the text
+/// generated using a seeded random number generator are therefore the same every time the same
+/// text is frozen, which is useful for snapshot tests and for chains baked into a program.
+///
+/// # Examples
+///
+/// ```
+/// # use markovish::{Chain, FrozenChain};
+/// # use rand::thread_rng;
+/// let chain = Chain::from_text("I am frozen").unwrap();
+/// let frozen = FrozenChain::from(chain);
+/// assert_eq!(
+///     frozen.generate_next_token(&mut thread_rng(), &("I", " ")),
+///     Some("am")
+/// );
+/// ```
+///
+/// A deserialized [`FrozenChain`] is checked to be sorted and to only refer to tokens that exist,
+/// see [`FrozenChainError`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FrozenChain {
+    /// Every token that is part of a pair, sorted and without duplicates.
+    tokens: Box<[Token]>,
+    /// Pairs as indices into `tokens`. Since `tokens` is sorted, so is this.
+    pairs: Box<[(u32, u32)]>,
+    /// Where the choices of each pair start in `choices`, followed by the amount of choices. The
+    /// choices of the pair with index `i` are `choices[offsets[i]..offsets[i + 1]]`.
+    offsets: Box<[u32]>,
+    /// The tokens that may follow each pair as indices into `tokens`, sorted within every pair.
+    choices: Box<[u32]>,
+    /// The running total of the weights of the choices of each pair, starting over for every
+    /// pair.
+    weights: Box<[Weight]>,
+    /// The first two tokens of every sentence recorded using
+    /// [`ChainBuilder::feed_sentences()`](crate::ChainBuilder::feed_sentences), as sorted
+    /// indices into `tokens`.
+    sentence_starts: Box<[(u32, u32)]>,
+    /// The last two tokens of every recorded sentence, like `sentence_starts`.
+    sentence_ends: Box<[(u32, u32)]>,
+}
+
+impl FrozenChain {
+    /// Returns an iterator of all pairs in the chain, in sorted order.
+    pub fn pairs(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        (0..self.pairs.len()).map(|i| self.pair_at(i))
+    }
+
+    /// Returns an iterator of all pairs that have `first` as their left token, in sorted order.
+    /// See [`Chain::pairs_with_first()`].
+    ///
+    /// Since pairs are sorted, the matching pairs are found using binary search instead of
+    /// looking at every pair in the chain.
+    pub fn pairs_with_first<'a>(&'a self, first: &str) -> impl Iterator<Item = TokenPairRef<'a>> {
+        let range = match self.index_of(first) {
+            Some(i) => {
+                let start = self.pairs.partition_point(|&(left, _)| left < i);
+                let end = self.pairs.partition_point(|&(left, _)| left <= i);
+                start..end
+            }
+            None => 0..0,
+        };
+        range.map(|i| self.pair_at(i))
+    }
+
+    /// Randomly chooses two tokens that are known to be able to generate a new token. If no
+    /// start tokens exist, `None` is returned.
+    ///
+    /// Like [`Chain::start_tokens()`], this runs in constant time, but without creating an index.
+    pub fn start_tokens<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenPairRef<'_>> {
+        if self.pairs.is_empty() {
+            return None;
+        }
+        Some(self.pair_at(rng.gen_range(0..self.pairs.len())))
+    }
+
+    /// Randomly chooses the first two tokens of a sentence in the source text, see
+    /// [`Chain::sentence_start_tokens()`].
+    ///
+    /// If no sentences were recorded in the frozen chain, `None` is returned.
+    pub fn sentence_start_tokens<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenPairRef<'_>> {
+        if self.sentence_starts.is_empty() {
+            return None;
+        }
+        let i = rng.gen_range(0..self.sentence_starts.len());
+        Some(self.token_pair(self.sentence_starts[i]))
+    }
+
+    /// Generates a single sentence of at most `max_tokens` tokens (including the start tokens),
+    /// see [`Chain::generate_sentence()`].
+    ///
+    /// If no sentences were recorded in the frozen chain, `None` is returned.
+    pub fn generate_sentence<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        max_tokens: usize,
+    ) -> Option<Vec<&str>> {
+        let (mut left, mut right) = self.sentence_start_tokens(rng)?;
+        let mut res: Vec<_> = [left, right].into_iter().take(max_tokens).collect();
+
+        while res.len() < max_tokens && !self.is_sentence_end(&(left, right)) {
+            let Some(next) = self.generate_next_token(rng, &(left, right)) else {
+                break;
+            };
+            res.push(next);
+            left = right;
+            right = next;
+        }
+
+        Some(res)
+    }
+
+    /// Generates a random new token using the previous tokens.
+    ///
+    /// If the chain has never seen the `prev` tokens together, `None` is returned.
+    pub fn generate_next_token<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+    ) -> Option<TokenRef<'_>> {
+        let i = self.find_pair(prev)?;
+        let (start, end) = (self.offsets[i] as usize, self.offsets[i + 1] as usize);
+        let weights = &self.weights[start..end];
+        let chosen = match weights {
+            [_] => 0,
+            [.., total] => {
+                let n = rng.gen_range(0..*total);
+                weights.partition_point(|&w| w <= n)
+            }
+            [] => unreachable!("every pair has at least one choice"),
+        };
+        Some(&self.tokens[self.choices[start + chosen] as usize])
+    }
+
+    /// Generates `n` tokens, using previously used tokens to generate new ones. See
+    /// [`Chain::generate_n_tokens()`].
+    ///
+    /// If the chain has never seen the `prev` tokens together, `None` is returned.
+    pub fn generate_n_tokens<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+        n: usize,
+    ) -> Option<Vec<TokenRef<'_>>> {
+        if n < 1 {
+            return Some(Vec::new());
+        }
+
+        let i = self.find_pair(prev)?;
+        let mut walk = Walk::new(Some(self.pair_at(i)));
+        let mut res = Vec::with_capacity(n);
+        while res.len() < n {
+            let next = walk.step(
+                rng,
+                |rng, prev| self.generate_next_token(rng, prev),
+                |rng| self.start_tokens(rng),
+            );
+            let Some(next) = next else {
+                break;
+            };
+            res.push(next);
+        }
+        Some(res)
+    }
+
+    /// Generates a string with `n` tokens, randomly choosing a starting point.
+    pub fn generate_str<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<&str>> {
+        let start = self.start_tokens(rng)?;
+        self.generate_n_tokens(rng, &start, n)
+    }
+
+    fn pair_at(&self, i: usize) -> TokenPairRef<'_> {
+        self.token_pair(self.pairs[i])
+    }
+
+    fn token_pair(&self, (left, right): (u32, u32)) -> TokenPairRef<'_> {
+        (&self.tokens[left as usize], &self.tokens[right as usize])
+    }
+
+    fn index_of(&self, token: &str) -> Option<u32> {
+        self.tokens
+            .binary_search_by(|t| t.as_str().cmp(token))
+            .ok()
+            .map(|i| i as u32)
+    }
+
+    /// Returns the index of `pair`, if the chain has it.
+    fn find_pair(&self, pair: &TokenPairRef<'_>) -> Option<usize> {
+        let key = (self.index_of(pair.0)?, self.index_of(pair.1)?);
+        self.pairs.binary_search(&key).ok()
+    }
+
+    fn is_sentence_end(&self, pair: &TokenPairRef<'_>) -> bool {
+        let (Some(left), Some(right)) = (self.index_of(pair.0), self.index_of(pair.1)) else {
+            return false;
+        };
+        self.sentence_ends.binary_search(&(left, right)).is_ok()
+    }
+}
+
+impl From<Chain> for FrozenChain {
+    /// Freezes a [`Chain`].
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the chain contains more than [`u32::MAX`] unique tokens, or if its pairs
+    /// can be followed by more than [`u32::MAX`] tokens in total.
+    fn from(chain: Chain) -> Self {
+        let bounds = &chain.sentences;
+        let mut tokens: Vec<Token> = chain
+            .map
+            .iter()
+            .flat_map(|(left, rights)| {
+                rights.iter().flat_map(move |(right, dist)| {
+                    [left, right].into_iter().chain(dist.parts().0).cloned()
+                })
+            })
+            .chain(
+                bounds
+                    .starts()
+                    .chain(bounds.ends())
+                    .flat_map(|(left, right)| [Token::from(left), Token::from(right)]),
+            )
+            .collect();
+        tokens.sort_unstable();
+        tokens.dedup();
+        assert!(
+            u32::try_from(tokens.len()).is_ok(),
+            "too many tokens to freeze chain"
+        );
+
+        let index =
+            |token: &str| tokens.binary_search_by(|t| t.as_str().cmp(token)).unwrap() as u32;
+        let mut entries: Vec<_> = chain
+            .map
+            .iter()
+            .flat_map(|(left, rights)| {
+                rights
+                    .iter()
+                    .map(move |(right, dist)| (left.as_str(), right.as_str(), dist))
+            })
+            .map(|(left, right, dist)| ((index(left), index(right)), dist))
+            .collect();
+        entries.sort_unstable_by_key(|(pair, _)| *pair);
+
+        let mut pairs = Vec::with_capacity(entries.len());
+        let mut offsets = Vec::with_capacity(entries.len() + 1);
+        let mut choices = Vec::new();
+        let mut weights = Vec::new();
+        offsets.push(0);
+        for (pair, dist) in entries {
+            let (dist_choices, dist_weights) = dist.parts();
+            let mut counts: Vec<(u32, Weight)> = dist_choices
+                .iter()
+                .map(|token| index(token))
+                .zip(distribution::table_weights(dist_weights))
+                .collect();
+            counts.sort_unstable_by_key(|(choice, _)| *choice);
+
+            let mut total: Weight = 0;
+            for (choice, weight) in counts {
+                total += weight;
+                choices.push(choice);
+                weights.push(total);
+            }
+            pairs.push(pair);
+            offsets.push(u32::try_from(choices.len()).expect("too many choices to freeze chain"));
+        }
+
+        let sentence_pairs = |pairs: &mut dyn Iterator<Item = TokenPairRef<'_>>| {
+            let mut pairs: Vec<_> = pairs.map(|(l, r)| (index(l), index(r))).collect();
+            pairs.sort_unstable();
+            pairs.into_boxed_slice()
+        };
+        let sentence_starts = sentence_pairs(&mut bounds.starts());
+        let sentence_ends = sentence_pairs(&mut bounds.ends());
+
+        Self {
+            tokens: tokens.into_boxed_slice(),
+            pairs: pairs.into_boxed_slice(),
+            offsets: offsets.into_boxed_slice(),
+            choices: choices.into_boxed_slice(),
+            weights: weights.into_boxed_slice(),
+            sentence_starts,
+            sentence_ends,
+        }
+    }
+}
+
+/// Reasons a deserialized [`FrozenChain`] was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrozenChainError {
+    /// The tokens are not sorted, or the same token is listed more than once.
+    UnsortedTokens,
+    /// There are more tokens than can be referred to by a [`u32`].
+    TooManyTokens,
+    /// A pair refers to a token that does not exist.
+    TokenOutOfRange(u32),
+    /// The pairs or sentence bounds are not sorted, or the same pair is listed more than once.
+    UnsortedPairs,
+    /// The offsets do not split the choices into one range of at least one choice per pair.
+    InvalidOffsets,
+    /// There is not exactly one weight per choice.
+    LengthMismatch {
+        /// The amount of choices.
+        choices: usize,
+        /// The amount of weights.
+        weights: usize,
+    },
+    /// The choices of a pair are not sorted, or the same token is listed more than once.
+    UnsortedChoices,
+    /// The weights of a pair are not a running total of weights above zero.
+    InvalidWeights,
+}
+
+impl fmt::Display for FrozenChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsortedTokens => write!(f, "frozen chain tokens are not sorted and unique"),
+            Self::TooManyTokens => write!(f, "frozen chain has more than {} tokens", u32::MAX),
+            Self::TokenOutOfRange(i) => write!(f, "frozen chain refers to missing token {i}"),
+            Self::UnsortedPairs => write!(f, "frozen chain pairs are not sorted and unique"),
+            Self::InvalidOffsets => write!(f, "frozen chain offsets do not match its pairs"),
+            Self::LengthMismatch { choices, weights } => write!(
+                f,
+                "frozen chain has {choices} choices but {weights} weights"
+            ),
+            Self::UnsortedChoices => write!(f, "frozen chain choices are not sorted and unique"),
+            Self::InvalidWeights => write!(f, "frozen chain weights are not increasing"),
+        }
+    }
+}
+
+impl std::error::Error for FrozenChainError {}
+
+/// How a [`FrozenChain`] is deserialized, before it has been checked.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "FrozenChain")]
+struct DeserializedFrozenChain {
+    tokens: Box<[Token]>,
+    pairs: Box<[(u32, u32)]>,
+    offsets: Box<[u32]>,
+    choices: Box<[u32]>,
+    weights: Box<[Weight]>,
+    sentence_starts: Box<[(u32, u32)]>,
+    sentence_ends: Box<[(u32, u32)]>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<DeserializedFrozenChain> for FrozenChain {
+    type Error = FrozenChainError;
+
+    fn try_from(raw: DeserializedFrozenChain) -> Result<Self, Self::Error> {
+        let DeserializedFrozenChain {
+            tokens,
+            pairs,
+            offsets,
+            choices,
+            weights,
+            sentence_starts,
+            sentence_ends,
+        } = raw;
+
+        // Lookups use binary search, so everything must be strictly sorted
+        if !tokens.windows(2).all(|w| w[0].as_str() < w[1].as_str()) {
+            return Err(FrozenChainError::UnsortedTokens);
+        }
+        if u32::try_from(tokens.len()).is_err() {
+            return Err(FrozenChainError::TooManyTokens);
+        }
+        let all_pairs = [&pairs, &sentence_starts, &sentence_ends];
+        if let Some(&i) = all_pairs
+            .iter()
+            .flat_map(|pairs| pairs.iter())
+            .flat_map(|(left, right)| [left, right])
+            .chain(choices.iter())
+            .find(|&&i| i as usize >= tokens.len())
+        {
+            return Err(FrozenChainError::TokenOutOfRange(i));
+        }
+        if !all_pairs
+            .iter()
+            .all(|pairs| pairs.windows(2).all(|w| w[0] < w[1]))
+        {
+            return Err(FrozenChainError::UnsortedPairs);
+        }
+        if offsets.len() != pairs.len() + 1
+            || offsets.first() != Some(&0)
+            || offsets.last().map(|&o| o as usize) != Some(choices.len())
+            || !offsets.windows(2).all(|w| w[0] < w[1])
+        {
+            return Err(FrozenChainError::InvalidOffsets);
+        }
+        if choices.len() != weights.len() {
+            return Err(FrozenChainError::LengthMismatch {
+                choices: choices.len(),
+                weights: weights.len(),
+            });
+        }
+        for w in offsets.windows(2) {
+            let range = w[0] as usize..w[1] as usize;
+            if !choices[range.clone()].windows(2).all(|c| c[0] < c[1]) {
+                return Err(FrozenChainError::UnsortedChoices);
+            }
+            let weights = &weights[range];
+            if weights[0] == 0 || !weights.windows(2).all(|w| w[0] < w[1]) {
+                return Err(FrozenChainError::InvalidWeights);
+            }
+        }
+
+        Ok(Self {
+            tokens,
+            pairs,
+            offsets,
+            choices,
+            weights,
+            sentence_starts,
+            sentence_ends,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for FrozenChain {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = DeserializedFrozenChain::deserialize(deserializer)?;
+        Self::try_from(raw).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{thread_rng, SeedableRng};
+
+    use super::FrozenChain;
+    use crate::{Chain, ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn frozen_pairs_with_first() {
+        let chain = Chain::from_text("I am here. I have been here, I think.").unwrap();
+        let frozen = FrozenChain::from(chain.clone());
+        for first in ["I", " ", "here", "nothing"] {
+            let mut expected: Vec<_> = chain.pairs_with_first(first).collect();
+            expected.sort();
+            assert_eq!(frozen.pairs_with_first(first).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn frozen_has_same_pairs() {
+        let chain = Chain::from_text("I am-full!of?cats and I am full of dogs").unwrap();
+        let mut pairs: Vec<_> = chain
+            .pairs()
+            .map(|(l, r)| (l.to_string(), r.to_string()))
+            .collect();
+        pairs.sort();
+
+        let frozen = FrozenChain::from(chain);
+        let frozen_pairs: Vec<_> = frozen
+            .pairs()
+            .map(|(l, r)| (l.to_string(), r.to_string()))
+            .collect();
+        assert_eq!(pairs, frozen_pairs);
+    }
+
+    #[test]
+    fn frozen_is_deterministic() {
+        let texts = [
+            "I am a cat. I am a dog. I am a bird.",
+            "I am a cow. I am an ant.",
+        ];
+        let freeze = |texts: &[&str]| {
+            let mut cb = ChainBuilder::new();
+            for text in texts {
+                cb = cb.feed_str(text).into_cb();
+            }
+            FrozenChain::from(cb.build().unwrap())
+        };
+        let a = freeze(&texts);
+        let b = freeze(&[texts[1], texts[0]]);
+
+        assert!(a.pairs().eq(b.pairs()));
+        for seed in 0..10 {
+            assert_eq!(
+                a.generate_str(&mut StdRng::seed_from_u64(seed), 30),
+                b.generate_str(&mut StdRng::seed_from_u64(seed), 30),
+            );
+        }
+    }
+
+    #[test]
+    fn frozen_generates_like_chain() {
+        let frozen = FrozenChain::from(Chain::from_text("I am-full!of?cats").unwrap());
+        assert_eq!(
+            frozen
+                .generate_n_tokens(&mut thread_rng(), &("I", " "), 7)
+                .unwrap(),
+            vec!["am", "-", "full", "!", "of", "?", "cats"],
+        );
+        assert_eq!(
+            frozen
+                .generate_n_tokens(&mut thread_rng(), &("I", " "), 13)
+                .unwrap()
+                .len(),
+            13
+        );
+        assert!(frozen
+            .generate_next_token(&mut thread_rng(), &("You", " "))
+            .is_none());
+    }
+
+    #[test]
+    fn frozen_keeps_sentences() {
+        let cb = ChainBuilder::new()
+            .feed_sentences("I am a cat. I am a dog.")
+            .into_cb();
+        let frozen = FrozenChain::from(cb.build().unwrap());
+        let mut rng = thread_rng();
+        assert_eq!(frozen.sentence_start_tokens(&mut rng), Some(("I", " ")));
+        let sentence = frozen.generate_sentence(&mut rng, 100).unwrap().concat();
+        assert!(sentence == "I am a cat." || sentence == "I am a dog.");
+
+        let frozen = FrozenChain::from(Chain::from_text("I am a cat.").unwrap());
+        assert!(frozen.generate_sentence(&mut rng, 100).is_none());
+    }
+
+    #[test]
+    fn frozen_chooses_by_weight() {
+        // "x" " " is followed by "a" once and by "b" three times
+        let frozen = FrozenChain::from(Chain::from_text("x a x b x b x b").unwrap());
+        let mut rng = thread_rng();
+        let mut counts = [0; 2];
+        for _ in 0..1000 {
+            match frozen.generate_next_token(&mut rng, &("x", " ")) {
+                Some("a") => counts[0] += 1,
+                Some("b") => counts[1] += 1,
+                other => panic!("unexpected token {other:?}"),
+            }
+        }
+        assert!(counts[0] > 0 && counts[1] > 2 * counts[0]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn deserialize_rejects_corrupt_chains() {
+        use super::FrozenChainError;
+
+        let frozen = FrozenChain::from(Chain::from_text("I am frozen").unwrap());
+        let json = serde_json::to_value(&frozen).unwrap();
+        let roundtrip: FrozenChain = serde_json::from_value(json.clone()).unwrap();
+        assert!(roundtrip.pairs().eq(frozen.pairs()));
+
+        let corrupt = |f: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            f(&mut json);
+            serde_json::from_value::<FrozenChain>(json)
+                .unwrap_err()
+                .to_string()
+        };
+        let expect = |e: FrozenChainError| e.to_string();
+
+        // Tokens are " ", "I", "am", "frozen", pairs are (0, 2), (1, 0), (2, 0) and they are
+        // followed by 0, 2 and 3 respectively
+        assert_eq!(
+            corrupt(&|j| j["tokens"].as_array_mut().unwrap().swap(0, 1)),
+            expect(FrozenChainError::UnsortedTokens)
+        );
+        assert_eq!(
+            corrupt(&|j| j["pairs"][0][1] = 9.into()),
+            expect(FrozenChainError::TokenOutOfRange(9))
+        );
+        assert_eq!(
+            corrupt(&|j| j["pairs"].as_array_mut().unwrap().swap(0, 1)),
+            expect(FrozenChainError::UnsortedPairs)
+        );
+        assert_eq!(
+            corrupt(&|j| j["choices"][2] = 9.into()),
+            expect(FrozenChainError::TokenOutOfRange(9))
+        );
+        assert_eq!(
+            corrupt(&|j| {
+                j["offsets"].as_array_mut().unwrap().pop();
+            }),
+            expect(FrozenChainError::InvalidOffsets)
+        );
+        assert_eq!(
+            corrupt(&|j| {
+                j["weights"].as_array_mut().unwrap().pop();
+            }),
+            expect(FrozenChainError::LengthMismatch {
+                choices: 3,
+                weights: 2
+            })
+        );
+        assert_eq!(
+            corrupt(&|j| j["weights"][1] = 0.into()),
+            expect(FrozenChainError::InvalidWeights)
+        );
+    }
+}
@@ -0,0 +1,868 @@
+//! A configurable [`Generator`], for when the `generate_*` methods of [`Chain`] are not enough.
+//! Generated tokens can be cleaned up using [`OutputFilter`]s before they are returned.
+//!
+//! # Examples
+//!
+//! ```
+//! # use markovish::Chain;
+//! # use rand::thread_rng;
+//! use markovish::generate::{EnsureFinalPeriod, Generator, TrimLeadingWhitespace};
+//!
+//! let chain = Chain::from_text("I am a cat. I am a dog").unwrap();
+//! let generator = Generator::new(&chain)
+//!     .filter(TrimLeadingWhitespace)
+//!     .filter(EnsureFinalPeriod);
+//! let text = generator.generate_string(&mut thread_rng(), 20).unwrap();
+//! assert!(text.ends_with('.'));
+//! assert!(!text.starts_with(' '));
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+
+use hashbrown::HashMap;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::detokenize::{Concat, Detokenizer};
+use crate::store::Walk;
+use crate::token::{TokenPairRef, TokenRef};
+use crate::Chain;
+
+/// Changes generated tokens before they are returned by a [`Generator`], such as by removing or
+/// adding tokens.
+pub trait OutputFilter {
+    /// Changes `tokens` in place.
+    fn apply(&self, tokens: &mut Vec<Cow<'_, str>>);
+}
+
+impl<F: Fn(&mut Vec<Cow<'_, str>>)> OutputFilter for F {
+    fn apply(&self, tokens: &mut Vec<Cow<'_, str>>) {
+        self(tokens)
+    }
+}
+
+/// Returns `true` if `token` only contains whitespace.
+fn is_whitespace(token: &str) -> bool {
+    token.chars().all(char::is_whitespace)
+}
+
+/// Returns `true` if `token` only contains punctuation, and is not empty.
+fn is_punctuation(token: &str) -> bool {
+    !token.is_empty() && token.chars().all(|c| c.is_ascii_punctuation() || c == '…')
+}
+
+/// Removes whitespace at the start of the output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrimLeadingWhitespace;
+
+impl OutputFilter for TrimLeadingWhitespace {
+    fn apply(&self, tokens: &mut Vec<Cow<'_, str>>) {
+        let blank = tokens.iter().take_while(|t| is_whitespace(t)).count();
+        tokens.drain(..blank);
+        if let Some(first) = tokens.first_mut() {
+            match first {
+                Cow::Borrowed(s) => *s = s.trim_start(),
+                Cow::Owned(s) => *s = s.trim_start().to_string(),
+            }
+        }
+    }
+}
+
+/// Collapses punctuation that is repeated, so that `"!!!"` or `"." "."` becomes a single `"!"`
+/// or `"."`. Only punctuation directly following other punctuation is removed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CollapsePunctuation;
+
+impl OutputFilter for CollapsePunctuation {
+    fn apply(&self, tokens: &mut Vec<Cow<'_, str>>) {
+        let mut prev_punctuation = false;
+        tokens.retain_mut(|token| {
+            if !is_punctuation(token) {
+                prev_punctuation = false;
+                return true;
+            }
+            if prev_punctuation {
+                return false;
+            }
+            prev_punctuation = true;
+
+            // Repeated characters within a single token, like "!!!"
+            let mut chars = token.chars();
+            if let Some(first) = chars.next() {
+                if chars.all(|c| c == first) && token.len() > first.len_utf8() {
+                    *token = Cow::Owned(first.to_string());
+                }
+            }
+            true
+        });
+    }
+}
+
+/// Makes sure that the output ends with a sentence terminator (`.`, `!` or `?`), so that it
+/// does not stop mid-sentence. Trailing whitespace is removed, and other punctuation at the end
+/// (like a comma) is replaced with a period.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnsureFinalPeriod;
+
+impl OutputFilter for EnsureFinalPeriod {
+    fn apply(&self, tokens: &mut Vec<Cow<'_, str>>) {
+        while tokens.last().is_some_and(|t| is_whitespace(t)) {
+            tokens.pop();
+        }
+
+        let Some(last) = tokens.last_mut() else {
+            return;
+        };
+        let trimmed = last.trim_end();
+        if trimmed.ends_with(['.', '!', '?']) {
+            return;
+        }
+
+        if is_punctuation(trimmed) {
+            *last = Cow::Borrowed(".");
+        } else {
+            let mut with_period = trimmed.to_string();
+            with_period.push('.');
+            *last = Cow::Owned(with_period);
+        }
+    }
+}
+
+/// Uppercases the first letter of the output, and the first letter after every sentence
+/// terminator (`.`, `!` or `?`). Chains often start generating in the middle of a sentence, which
+/// otherwise makes the output start in lowercase.
+///
+/// # Examples
+///
+/// ```
+/// use std::borrow::Cow;
+/// use markovish::generate::{Capitalize, OutputFilter};
+///
+/// let mut tokens: Vec<_> = ["and", " ", "so", ".", " ", "éclair", "!"]
+///     .into_iter()
+///     .map(Cow::Borrowed)
+///     .collect();
+/// Capitalize.apply(&mut tokens);
+/// assert_eq!(tokens.concat(), "And so. Éclair!");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Capitalize;
+
+impl OutputFilter for Capitalize {
+    fn apply(&self, tokens: &mut Vec<Cow<'_, str>>) {
+        let mut capitalize_next = true;
+        for token in tokens.iter_mut() {
+            if capitalize_next {
+                if let Some((i, c)) = token.char_indices().find(|(_, c)| c.is_alphabetic()) {
+                    if c.is_lowercase() {
+                        let mut capitalized = String::with_capacity(token.len() + 2);
+                        capitalized.push_str(&token[..i]);
+                        capitalized.extend(c.to_uppercase());
+                        capitalized.push_str(&token[i + c.len_utf8()..]);
+                        *token = Cow::Owned(capitalized);
+                    }
+                    capitalize_next = false;
+                }
+            }
+
+            if token.trim_end().ends_with(['.', '!', '?']) {
+                capitalize_next = true;
+            }
+        }
+    }
+}
+
+/// Returns how many times `token` was repeated at the end of `tokens`. Whitespace between the
+/// repeats is skipped, unless `token` is whitespace itself.
+fn repeats(tokens: &[TokenRef<'_>], token: &str) -> usize {
+    let whitespace = is_whitespace(token);
+    tokens
+        .iter()
+        .rev()
+        .filter(|t| whitespace || !is_whitespace(t))
+        .take_while(|t| **t == token)
+        .count()
+}
+
+/// Raises the weights to the power of `1 / temperature`, relative to the largest weight so that
+/// they never overflow. A temperature of zero or less only keeps the largest weights.
+fn apply_temperature(weights: &mut [(TokenRef<'_>, f64)], temperature: f64) {
+    let max = weights.iter().map(|(_, w)| *w).fold(0.0, f64::max);
+    for (_, w) in weights.iter_mut() {
+        *w = if temperature > 0.0 {
+            ((*w / max).ln() / temperature).exp()
+        } else if *w == max {
+            1.0
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Generates text from a [`Chain`], with options that the `generate_*` methods of [`Chain`] do
+/// not have. Options are set using builder methods.
+pub struct Generator<'a> {
+    chain: &'a Chain,
+    filters: Vec<Box<dyn OutputFilter + 'a>>,
+    detokenizer: Box<dyn Detokenizer + 'a>,
+    hooks: Hooks<'a>,
+    max_repeats: Option<usize>,
+    max_trigram_uses: Option<usize>,
+    temperature: Option<Schedule<'a>>,
+    surprising: Option<f64>,
+    without_replacement: Option<f64>,
+}
+
+/// A value that depends on how many tokens have been generated.
+type Schedule<'a> = Box<dyn Fn(usize) -> f64 + 'a>;
+
+/// How many times each continuation of a pair has been chosen during one generation.
+type TrigramUses<'a> = HashMap<(TokenRef<'a>, TokenRef<'a>, TokenRef<'a>), usize>;
+
+/// A callback called with a token.
+type TokenHook<'a> = Box<dyn Fn(&str) + 'a>;
+/// A callback called with a pair of tokens.
+type PairHook<'a> = Box<dyn Fn(TokenPairRef<'_>) + 'a>;
+
+/// Callbacks for events during generation, see [`Generator::on_token()`].
+#[derive(Default)]
+struct Hooks<'a> {
+    on_token: Option<TokenHook<'a>>,
+    on_dead_end: Option<PairHook<'a>>,
+    on_restart: Option<PairHook<'a>>,
+}
+
+impl<'a> Generator<'a> {
+    /// Creates a generator without any filters, that generates text like
+    /// [`Chain::generate_str()`].
+    pub fn new(chain: &'a Chain) -> Self {
+        Self {
+            chain,
+            filters: Vec::new(),
+            detokenizer: Box::new(Concat),
+            hooks: Hooks::default(),
+            max_repeats: None,
+            max_trigram_uses: None,
+            temperature: None,
+            surprising: None,
+            without_replacement: None,
+        }
+    }
+
+    /// Adds a filter, which is applied to the generated tokens after all previously added
+    /// filters.
+    pub fn filter(mut self, filter: impl OutputFilter + 'a) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Sets how tokens are joined by [`Generator::generate_string()`]. The default is
+    /// [`Concat`], which fits chains fed using
+    /// [`ChainBuilder::feed_str()`](crate::ChainBuilder::feed_str).
+    pub fn detokenizer(mut self, detokenizer: impl Detokenizer + 'a) -> Self {
+        self.detokenizer = Box::new(detokenizer);
+        self
+    }
+
+    /// Calls `f` with every generated token, before any filters are applied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use std::cell::Cell;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// let dead_ends = Cell::new(0);
+    /// let tokens = Cell::new(0);
+    /// let generator = Generator::new(&chain)
+    ///     .on_token(|_| tokens.set(tokens.get() + 1))
+    ///     .on_dead_end(|_| dead_ends.set(dead_ends.get() + 1));
+    /// generator.generate(&mut thread_rng(), 20);
+    /// assert_eq!(tokens.get(), 20);
+    /// assert!(dead_ends.get() > 0);
+    /// ```
+    pub fn on_token(mut self, f: impl Fn(&str) + 'a) -> Self {
+        self.hooks.on_token = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the previous two tokens whenever there is no possible next token, so that
+    /// generation has to be restarted. Useful to find out how often a chain runs into dead ends.
+    pub fn on_dead_end(mut self, f: impl Fn(TokenPairRef<'_>) + 'a) -> Self {
+        self.hooks.on_dead_end = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the new start tokens whenever generation is restarted after a dead end.
+    pub fn on_restart(mut self, f: impl Fn(TokenPairRef<'_>) + 'a) -> Self {
+        self.hooks.on_restart = Some(Box::new(f));
+        self
+    }
+
+    /// Limits how many times the same token may be generated in a row to `n`. Whitespace between
+    /// the repeated tokens is ignored, so `"ha ha ha"` counts as three repeats of `"ha"`. Once the
+    /// limit is reached, the next token is chosen among the other possible tokens, and if there
+    /// are none it is treated like a dead end.
+    ///
+    /// Useful for texts with ASCII art or long lines of dashes, which may otherwise make the
+    /// chain generate the same token over and over.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("Hmm!!!!!!!!!!!!!!!! Okay.").unwrap();
+    /// let generator = Generator::new(&chain).max_repeats(3);
+    /// let text = generator.generate_string(&mut thread_rng(), 50).unwrap();
+    /// assert!(!text.contains("!!!!"));
+    /// ```
+    pub fn max_repeats(mut self, n: usize) -> Self {
+        assert!(n > 0, "the same token must be allowed at least once");
+        self.max_repeats = Some(n);
+        self
+    }
+
+    /// Limits how many times the same three tokens from the source text may be generated during
+    /// one generation to `k`. Once a pair has been followed by the same token `k` times, that
+    /// token is no longer chosen after the pair, and if there are no other tokens it is treated
+    /// like a dead end.
+    ///
+    /// This keeps long outputs from getting stuck repeating the most common phrase of the source
+    /// text.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat. I am a dog. I am a cat. I am a cat.").unwrap();
+    /// let generator = Generator::new(&chain).max_trigram_uses(2);
+    /// let text = generator.generate_string(&mut thread_rng(), 100).unwrap();
+    /// assert!(!text.is_empty());
+    /// ```
+    pub fn max_trigram_uses(mut self, k: usize) -> Self {
+        assert!(k > 0, "trigrams must be allowed at least once");
+        self.max_trigram_uses = Some(k);
+        self
+    }
+
+    /// Sets the temperature used when choosing tokens. A temperature of `1.0` chooses tokens
+    /// like [`Chain::generate_str()`], lower temperatures make common tokens even more likely,
+    /// and higher temperatures make all tokens more equally likely. A temperature of zero or
+    /// less always chooses the most common token.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat. I am a dog. I am a cat.").unwrap();
+    /// let generator = Generator::new(&chain).temperature(0.0);
+    /// let text = generator.generate_string(&mut thread_rng(), 50).unwrap();
+    /// assert!(!text.contains("dog"));
+    /// ```
+    pub fn temperature(self, temperature: f64) -> Self {
+        self.temperature_schedule(move |_| temperature)
+    }
+
+    /// Like [`Generator::temperature()`], but the temperature is a function of how many tokens
+    /// have been generated so far. This way the output can start out close to the source text,
+    /// and get wilder the longer it gets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat. I am a dog. I am a bird.").unwrap();
+    /// let generator = Generator::new(&chain).temperature_schedule(|i| 0.5 + i as f64 / 100.0);
+    /// assert!(generator.generate_string(&mut thread_rng(), 200).is_some());
+    /// ```
+    pub fn temperature_schedule(mut self, f: impl Fn(usize) -> f64 + 'a) -> Self {
+        self.temperature = Some(Box::new(f));
+        self
+    }
+
+    /// Prefers rare tokens over common ones, by choosing tokens with a weight of one over their
+    /// probability instead of the probability itself. Probabilities lower than `floor` are
+    /// treated as `floor`, so that a token seen once among thousands does not always win.
+    ///
+    /// Useful to generate deliberately weird text, or to stress test something that reads the
+    /// output. Can be combined with [`Generator::temperature()`], which is applied afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `floor` is not larger than zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat. I am a cat. I am a dog. I am a cat.").unwrap();
+    /// let generator = Generator::new(&chain).surprising(0.01);
+    /// assert!(generator.generate_string(&mut thread_rng(), 50).is_some());
+    /// ```
+    pub fn surprising(mut self, floor: f64) -> Self {
+        assert!(floor > 0.0, "the floor must be larger than zero");
+        self.surprising = Some(floor);
+        self
+    }
+
+    /// Makes tokens less likely to be chosen after a pair if they have already been chosen after
+    /// that pair during the same generation. The weight of a token is multiplied by `factor` for
+    /// every earlier time it was chosen, so a `factor` of `0.0` never chooses the same token after
+    /// the same pair twice, unless there is nothing else to choose.
+    ///
+    /// This gives more varied output when generating long texts from a small source text.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not between `0.0` and `1.0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat. I am a dog. I am a cat.").unwrap();
+    /// let generator = Generator::new(&chain).without_replacement(0.1);
+    /// assert!(generator.generate_string(&mut thread_rng(), 50).is_some());
+    /// ```
+    pub fn without_replacement(mut self, factor: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&factor),
+            "the factor must be between 0.0 and 1.0"
+        );
+        self.without_replacement = Some(factor);
+        self
+    }
+
+    /// Returns the chain tokens are generated from.
+    pub fn chain(&self) -> &'a Chain {
+        self.chain
+    }
+
+    /// Generates `n` tokens like [`Chain::generate_str()`], and then applies the filters. Since
+    /// filters may add or remove tokens, the result may not have exactly `n` tokens.
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<Cow<'a, str>>> {
+        let mut tokens: Vec<_> = self
+            .generate_tokens(rng, n)?
+            .into_iter()
+            .map(Cow::Borrowed)
+            .collect();
+        for filter in &self.filters {
+            filter.apply(&mut tokens);
+        }
+        Some(tokens)
+    }
+
+    /// Generates `n` tokens like [`Chain::generate_str()`], calling the hooks along the way.
+    fn generate_tokens<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<TokenRef<'a>>> {
+        let mut walk = Walk::new(Some(self.chain.start_tokens(rng)?));
+        let mut res = Vec::with_capacity(n);
+        let mut uses = TrigramUses::new();
+        while res.len() < n {
+            let next = walk.step(
+                rng,
+                |rng, &(left, right)| {
+                    let Some(next) = self.next_token(rng, &(left, right), &res, &uses) else {
+                        if let Some(f) = &self.hooks.on_dead_end {
+                            f((left, right));
+                        }
+                        return None;
+                    };
+                    if self.max_trigram_uses.is_some() || self.without_replacement.is_some() {
+                        *uses.entry((left, right, next)).or_default() += 1;
+                    }
+                    Some(next)
+                },
+                |rng| {
+                    let start = self.chain.start_tokens(rng)?;
+                    if let Some(f) = &self.hooks.on_restart {
+                        f(start);
+                    }
+                    Some(start)
+                },
+            );
+            let Some(next) = next else {
+                break;
+            };
+            if let Some(f) = &self.hooks.on_token {
+                f(next);
+            }
+            res.push(next);
+        }
+        Some(res)
+    }
+
+    /// Chooses the token following `prev`, taking the options of the generator and the tokens
+    /// generated so far into account. Returns `None` if no token is allowed.
+    fn next_token<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'a>,
+        res: &[TokenRef<'a>],
+        uses: &TrigramUses<'a>,
+    ) -> Option<TokenRef<'a>> {
+        if self.max_repeats.is_none()
+            && self.max_trigram_uses.is_none()
+            && self.temperature.is_none()
+            && self.surprising.is_none()
+            && self.without_replacement.is_none()
+        {
+            return self.chain.generate_next_token(rng, prev);
+        }
+
+        let allowed = |token: TokenRef<'a>| {
+            self.max_repeats.is_none_or(|max| repeats(res, token) < max)
+                && self.max_trigram_uses.is_none_or(|max| {
+                    uses.get(&(prev.0, prev.1, token)).copied().unwrap_or(0) < max
+                })
+        };
+        let dist = self.chain.get_distribution(prev)?;
+        let mut candidates: Vec<(TokenRef<'a>, f64)> = dist
+            .iter()
+            .map(|(token, n)| (token.as_str(), n as f64))
+            .filter(|(token, _)| allowed(token))
+            .collect();
+        if let Some(floor) = self.surprising {
+            let total = dist.total() as f64;
+            for (_, w) in candidates.iter_mut() {
+                *w = 1.0 / (*w / total).max(floor);
+            }
+        }
+        if let Some(factor) = self.without_replacement {
+            let reduced: Vec<f64> = candidates
+                .iter()
+                .map(|(token, w)| {
+                    let used = uses.get(&(prev.0, prev.1, *token)).copied().unwrap_or(0);
+                    w * factor.powi(used.try_into().unwrap_or(i32::MAX))
+                })
+                .collect();
+            // If every token has already been used, they are chosen as if they had not
+            if reduced.iter().any(|w| *w > 0.0) {
+                for ((_, w), reduced) in candidates.iter_mut().zip(reduced) {
+                    *w = reduced;
+                }
+            }
+        }
+        if let Some(schedule) = &self.temperature {
+            apply_temperature(&mut candidates, schedule(res.len()));
+        }
+        candidates
+            .choose_weighted(rng, |(_, w)| *w)
+            .ok()
+            .map(|(token, _)| *token)
+    }
+
+    /// Like [`Generator::generate()`], but uses a [`StdRng`] seeded with `seed`, so that the same
+    /// output can be generated again later. The seed is part of the returned report.
+    ///
+    /// The same seed only gives the same output with the same options and version of
+    /// `markovish`, and from the same chain. A chain that is loaded again, even from the same
+    /// file, may store its tokens in another order and generate something else.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat. I am a dog. I am a bird.").unwrap();
+    /// let generator = Generator::new(&chain);
+    /// let report = generator.generate_seeded(42, 20).unwrap();
+    /// assert_eq!(report.seed, 42);
+    /// assert_eq!(generator.generate_seeded(42, 20), Some(report));
+    /// ```
+    pub fn generate_seeded(&self, seed: u64, n: usize) -> Option<GenerationReport<'a>> {
+        let tokens = self.generate(&mut StdRng::seed_from_u64(seed), n)?;
+        Some(GenerationReport { tokens, seed })
+    }
+
+    /// Like [`Generator::generate_seeded()`], but the seed is chosen using `rng`. Use this to
+    /// generate random outputs that can still be reproduced, by passing the seed of the report
+    /// to [`Generator::generate_seeded()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// use markovish::generate::Generator;
+    ///
+    /// let chain = Chain::from_text("I am a cat. I am a dog. I am a bird.").unwrap();
+    /// let generator = Generator::new(&chain);
+    /// let report = generator.generate_report(&mut thread_rng(), 20).unwrap();
+    ///
+    /// // The same output, but twice as long
+    /// let longer = generator.generate_seeded(report.seed, 40).unwrap();
+    /// assert!(longer.tokens.starts_with(&report.tokens));
+    /// ```
+    pub fn generate_report<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        n: usize,
+    ) -> Option<GenerationReport<'a>> {
+        self.generate_seeded(rng.gen(), n)
+    }
+
+    /// Like [`Generator::generate()`], but the tokens are joined using the detokenizer.
+    pub fn generate_string<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<String> {
+        let tokens = self.generate(rng, n)?;
+        let tokens: Vec<&str> = tokens.iter().map(|t| t.as_ref()).collect();
+        Some(self.detokenizer.detokenize(&tokens))
+    }
+}
+
+/// Generated tokens together with the seed they were generated from, returned by
+/// [`Generator::generate_seeded()`] and [`Generator::generate_report()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenerationReport<'a> {
+    /// The generated tokens, after the filters have been applied.
+    pub tokens: Vec<Cow<'a, str>>,
+    /// The seed that generates the same tokens when passed to
+    /// [`Generator::generate_seeded()`].
+    pub seed: u64,
+}
+
+impl fmt::Debug for Generator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Generator")
+            .field("chain", &self.chain)
+            .field("filters", &self.filters.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use std::cell::{Cell, RefCell};
+
+    use hashbrown::{HashMap, HashSet};
+
+    use rand::thread_rng;
+
+    use super::{
+        apply_temperature, repeats, Capitalize, CollapsePunctuation, EnsureFinalPeriod, Generator,
+        OutputFilter, TrimLeadingWhitespace,
+    };
+    use crate::{Chain, ChainBuilder, IntoChainBuilder};
+
+    fn apply(filter: impl OutputFilter, tokens: &[&str]) -> Vec<String> {
+        let mut tokens: Vec<_> = tokens.iter().map(|t| Cow::Borrowed(*t)).collect();
+        filter.apply(&mut tokens);
+        tokens.into_iter().map(Cow::into_owned).collect()
+    }
+
+    #[test]
+    fn built_in_filters() {
+        assert_eq!(
+            apply(TrimLeadingWhitespace, &[" ", "\n", "  Hi", " "]),
+            vec!["Hi", " "]
+        );
+        assert_eq!(
+            apply(CollapsePunctuation, &["Hi", "!!!", "!", " ", ".", "?"]),
+            vec!["Hi", "!", " ", "."]
+        );
+        assert_eq!(
+            apply(EnsureFinalPeriod, &["Hi", " ", "there", "\n"]),
+            vec!["Hi", " ", "there."]
+        );
+        assert_eq!(apply(EnsureFinalPeriod, &["Hi", ","]), vec!["Hi", "."]);
+        assert_eq!(apply(EnsureFinalPeriod, &["Hi", "?"]), vec!["Hi", "?"]);
+        assert!(apply(EnsureFinalPeriod, &[" "]).is_empty());
+
+        assert_eq!(
+            apply(Capitalize, &["3", " ", "cats", "?", " ", "\"", "no", "\""]),
+            vec!["3", " ", "Cats", "?", " ", "\"", "No", "\""]
+        );
+
+        // Closures are filters too
+        let first_only = |tokens: &mut Vec<Cow<'_, str>>| tokens.truncate(1);
+        assert_eq!(apply(first_only, &["a", "b"]), vec!["a"]);
+    }
+
+    #[test]
+    fn repeats_are_limited() {
+        assert_eq!(repeats(&["a", "b", " ", "b", "\n", "b"], "b"), 3);
+        assert_eq!(repeats(&["a", " ", " "], " "), 2);
+        assert_eq!(repeats(&["a", "b"], "a"), 0);
+
+        // After "a" "a" there is always another "a", which is not allowed
+        let cb = ChainBuilder::new()
+            .feed_tokens(["a", "a", "a", "a", "b"].into_iter())
+            .into_cb();
+        let chain = cb.build().unwrap();
+        let generator = Generator::new(&chain).max_repeats(2);
+        let mut rng = thread_rng();
+        for _ in 0..20 {
+            let tokens = generator.generate(&mut rng, 30).unwrap();
+            assert!(!tokens.windows(3).any(|w| w.iter().all(|t| t == "a")));
+        }
+    }
+
+    #[test]
+    fn trigram_uses_are_limited() {
+        // Without a limit, "a" " " "b" would be chosen three times as often as "a" " " "c"
+        let chain = Chain::from_text("a b a b a b a c").unwrap();
+        let tokens = RefCell::new(Vec::new());
+        let restart_tokens = Cell::new(0_usize);
+        let generator = Generator::new(&chain)
+            .max_trigram_uses(2)
+            .on_restart(|_| restart_tokens.set(2))
+            .on_token(|t| {
+                let restarted = restart_tokens.get() > 0;
+                restart_tokens.set(restart_tokens.get().saturating_sub(1));
+                tokens.borrow_mut().push((t.to_string(), restarted));
+            });
+        generator.generate(&mut thread_rng(), 40).unwrap();
+
+        // Every token that was not a restart follows the two tokens before it, except for the
+        // first two, which follow the start tokens
+        let tokens = tokens.borrow();
+        let mut uses = HashMap::new();
+        for w in tokens.windows(3).filter(|w| !w[2].1) {
+            *uses.entry((&w[0].0, &w[1].0, &w[2].0)).or_insert(0) += 1;
+        }
+        assert!(!uses.is_empty());
+        assert!(uses.values().all(|n| *n <= 2));
+    }
+
+    #[test]
+    fn temperature_reshapes_weights() {
+        let weights = |temperature| {
+            let mut weights = vec![("a", 4.0), ("b", 2.0), ("c", 1.0)];
+            apply_temperature(&mut weights, temperature);
+            weights.into_iter().map(|(_, w)| w).collect::<Vec<f64>>()
+        };
+        assert_eq!(weights(1.0), vec![1.0, 0.5, 0.25]);
+        assert_eq!(weights(f64::INFINITY), vec![1.0, 1.0, 1.0]);
+        assert_eq!(weights(0.0), vec![1.0, 0.0, 0.0]);
+        assert_eq!(weights(-1.0), vec![1.0, 0.0, 0.0]);
+        assert!(weights(0.5)[1] < 0.5);
+
+        // The schedule is asked with the number of tokens generated so far
+        let chain = Chain::from_text("a b c d").unwrap();
+        let positions = RefCell::new(Vec::new());
+        let generator = Generator::new(&chain).temperature_schedule(|i| {
+            positions.borrow_mut().push(i);
+            1.0
+        });
+        generator.generate(&mut thread_rng(), 3).unwrap();
+        assert!(positions.borrow().windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn surprising_prefers_rare_tokens() {
+        let text = format!("{}b", "a ".repeat(10));
+        let chain = Chain::from_text(&text).unwrap();
+        // After "a" " ", "a" has been seen nine times and "b" once
+        let generator = Generator::new(&chain).surprising(0.01).temperature(0.0);
+        let mut rng = thread_rng();
+        for _ in 0..10 {
+            let tokens = generator.generate(&mut rng, 10).unwrap();
+            assert!(!tokens.windows(3).any(|w| w == ["a", " ", "a"]));
+        }
+    }
+
+    #[test]
+    fn without_replacement_varies_continuations() {
+        // "x" " " is followed by "a", "b" and "c" equally often, and there are no dead ends
+        let chain = Chain::from_text("x a x b x c x").unwrap();
+        let generator = Generator::new(&chain).without_replacement(0.0);
+        let mut rng = thread_rng();
+        for _ in 0..10 {
+            let tokens = generator.generate(&mut rng, 30).unwrap();
+            // The letters only follow "x" " ", so they are every continuation of that pair,
+            // including one that may have followed the start tokens
+            let letters: Vec<_> = tokens
+                .iter()
+                .filter(|t| ["a", "b", "c"].contains(&t.as_ref()))
+                .collect();
+            assert!(letters.len() >= 3);
+            // Every letter is used once before any is used again
+            let first: HashSet<_> = letters.iter().take(3).collect();
+            assert_eq!(first.len(), 3);
+        }
+    }
+
+    #[test]
+    fn seeds_reproduce_output() {
+        let text = "I am a cat. I am a dog. You are a bird, and I am not a bird.";
+        let chain = Chain::from_text(text).unwrap();
+        let generator = Generator::new(&chain).without_replacement(0.5);
+        let report = generator.generate_report(&mut thread_rng(), 50).unwrap();
+
+        // The tokens of a chain built again are stored in another order, like they would be in
+        // another process
+        let rebuilt = Chain::from_text(text).unwrap();
+        let again = Generator::new(&rebuilt)
+            .without_replacement(0.5)
+            .generate_seeded(report.seed, 50)
+            .unwrap();
+        assert_eq!(again.tokens, report.tokens);
+
+        #[cfg(feature = "save")]
+        {
+            let mut file = Vec::new();
+            chain.write_to(&mut file).unwrap();
+            let loaded = Chain::read_from(file.as_slice()).unwrap();
+            let again = Generator::new(&loaded)
+                .without_replacement(0.5)
+                .generate_seeded(report.seed, 50)
+                .unwrap();
+            assert_eq!(again.tokens, report.tokens);
+        }
+    }
+
+    #[test]
+    fn hooks_see_dead_ends_and_restarts() {
+        // "a" " " is the only pair, and "b" is always a dead end
+        let chain = Chain::from_text("a b").unwrap();
+        let tokens = RefCell::new(Vec::new());
+        let dead_ends = Cell::new(0);
+        let restarts = RefCell::new(Vec::new());
+        let generator = Generator::new(&chain)
+            .on_token(|t| tokens.borrow_mut().push(t.to_string()))
+            .on_dead_end(|pair| {
+                assert_eq!(pair, (" ", "b"));
+                dead_ends.set(dead_ends.get() + 1);
+            })
+            .on_restart(|pair| restarts.borrow_mut().push(pair == ("a", " ")));
+
+        let generated = generator.generate_string(&mut thread_rng(), 5).unwrap();
+        assert_eq!(generated, "ba ba");
+        assert_eq!(tokens.borrow().concat(), "ba ba");
+        assert_eq!(dead_ends.get(), 2);
+        assert_eq!(*restarts.borrow(), vec![true, true]);
+    }
+}
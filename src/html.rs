@@ -0,0 +1,136 @@
+//! Feeding [`ChainBuilder`]s with the visible text of HTML documents, so that tags and entities
+//! do not end up as tokens. Requires the `html` feature.
+//!
+//! HTML is parsed using [`html5gum`](https://crates.io/crates/html5gum), which handles broken
+//! HTML the same way browsers do.
+
+use std::borrow::Cow;
+
+use html5gum::{DefaultEmitter, Token};
+
+use crate::chain::FeedResult;
+use crate::tokenize::{Tokenizer, WordBounds};
+use crate::ChainBuilder;
+
+/// Elements whose content is never shown as text.
+const HIDDEN_ELEMENTS: [&[u8]; 5] = [b"script", b"style", b"noscript", b"template", b"head"];
+
+/// Elements that are part of a line of text. All other elements separate their text from the
+/// text around them.
+const INLINE_ELEMENTS: [&[u8]; 17] = [
+    b"a", b"abbr", b"b", b"bdi", b"bdo", b"cite", b"code", b"em", b"i", b"kbd", b"mark", b"q",
+    b"s", b"small", b"span", b"strong", b"u",
+];
+
+/// Returns the text of `html` that a browser would show, with tags removed and entities (such as
+/// `&amp;`) decoded. Elements that are not inline (such as paragraphs) are separated by a newline.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::html::visible_text;
+///
+/// let html = "<p>Fish &amp; <b>chips</b></p><script>alert(1)</script><p>Yum</p>";
+/// assert_eq!(visible_text(html), "Fish & chips\nYum");
+/// ```
+pub fn visible_text(html: &str) -> String {
+    let mut emitter = DefaultEmitter::default();
+    // Makes sure that the content of `<script>` and such is read as text, and not as tags
+    emitter.naively_switch_states(true);
+
+    let mut text = String::new();
+    // The hidden element we are inside of, if any
+    let mut hidden: Option<Vec<u8>> = None;
+
+    for token in html5gum::Tokenizer::new_with_emitter(html, emitter).flatten() {
+        match token {
+            Token::StartTag(tag) if hidden.is_none() => {
+                if HIDDEN_ELEMENTS.contains(&tag.name.as_slice()) && !tag.self_closing {
+                    hidden = Some(tag.name.to_vec());
+                } else if !INLINE_ELEMENTS.contains(&tag.name.as_slice()) {
+                    separate(&mut text);
+                }
+            }
+            Token::EndTag(tag) => match &hidden {
+                Some(name) if *name == *tag.name => hidden = None,
+                Some(_) => {}
+                None if !INLINE_ELEMENTS.contains(&tag.name.as_slice()) => separate(&mut text),
+                None => {}
+            },
+            Token::String(s) if hidden.is_none() => text.push_str(&String::from_utf8_lossy(&s)),
+            _ => {}
+        }
+    }
+
+    text.truncate(text.trim_end().len());
+    text
+}
+
+/// Adds a newline, unless `text` is empty or already ends with whitespace.
+fn separate(text: &mut String) {
+    if text.chars().next_back().is_some_and(|c| !c.is_whitespace()) {
+        text.push('\n');
+    }
+}
+
+/// Wraps another tokenizer, which is given the [`visible_text()`] of HTML documents instead of
+/// their source. Requires the `html` feature.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Html<T> {
+    inner: T,
+}
+
+impl<T> Html<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for Html<T> {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        // Tokens from the decoded text cannot borrow from `text`, so they must be owned
+        self.inner
+            .tokenize(&visible_text(text))
+            .into_iter()
+            .map(|t| Cow::Owned(t.into_owned()))
+            .collect()
+    }
+}
+
+impl ChainBuilder {
+    /// Feeds the builder with the [`visible_text()`] of the HTML document `content`, split like
+    /// in [`ChainBuilder::feed_str()`]. Useful for text scraped from the web. Requires the `html`
+    /// feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// # use rand::thread_rng;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let html = r#"<div class="post"><p>I &lt;3 cats</p></div>"#;
+    /// let chain = ChainBuilder::new().feed_html(html).into_cb().build().unwrap();
+    /// assert_eq!(
+    ///     chain.generate_next_token(&mut thread_rng(), &("I", " ")),
+    ///     Some("<")
+    /// );
+    /// ```
+    pub fn feed_html(self, content: &str) -> FeedResult {
+        self.feed_with(&Html::new(WordBounds), content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::visible_text;
+
+    #[test]
+    fn hidden_and_block_elements() {
+        let html = "<!DOCTYPE html><html><head><title>T</title></head><body>\
+                    <h1>Hi</h1><style>p { color: red; }</style>\
+                    <ul><li>a<br>b</li><li><a href='x'>c</a> d</li></ul><!-- no --></body></html>";
+        assert_eq!(visible_text(html), "Hi\na\nb\nc d");
+        assert_eq!(visible_text("<script>if (a < b) {}</script>"), "");
+    }
+}
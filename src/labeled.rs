@@ -0,0 +1,293 @@
+//! Chains where every transition is labeled with where it came from, such as which persona or
+//! author wrote the text. All labels share the same pairs, so a pair seen by several labels is
+//! only stored once instead of once per [`Chain`](crate::Chain). Every label still keeps its own
+//! distribution of the tokens following a pair. Text can be generated for a single label or for a
+//! blend of several.
+
+use hashbrown::HashMap;
+use itertools::Itertools;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::distribution::{TokenDistribution, TokenDistributionBuilder};
+use crate::store::Walk;
+use crate::token::{Token, TokenPair, TokenPairRef, TokenRef};
+
+/// Builds a [`LabeledChain`] by being fed labeled texts.
+#[derive(Clone, Debug, Default)]
+pub struct LabeledChainBuilder {
+    labels: Vec<Token>,
+    map: HashMap<TokenPair, Vec<(usize, TokenDistributionBuilder)>>,
+}
+
+impl LabeledChainBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the index of `label`, adding it if it is new.
+    fn label_index(&mut self, label: &str) -> usize {
+        match self.labels.iter().position(|l| l == label) {
+            Some(i) => i,
+            None => {
+                self.labels.push(Token::from(label));
+                self.labels.len() - 1
+            }
+        }
+    }
+
+    /// Feeds the builder with `content` written by `label`, split like in
+    /// [`ChainBuilder::feed_str()`](crate::ChainBuilder::feed_str).
+    ///
+    /// Returns how many transitions were added, which is zero if the text was too short.
+    pub fn feed_str_labeled(&mut self, label: &str, content: &str) -> usize {
+        self.feed_tokens_labeled(label, content.split_word_bounds())
+    }
+
+    /// Feeds the builder with pre-split tokens written by `label`, like
+    /// [`LabeledChainBuilder::feed_str_labeled()`].
+    pub fn feed_tokens_labeled<'a>(
+        &mut self,
+        label: &str,
+        tokens: impl Iterator<Item = TokenRef<'a>>,
+    ) -> usize {
+        let mut windows = tokens.tuple_windows().peekable();
+        if windows.peek().is_none() {
+            return 0;
+        }
+
+        let label = self.label_index(label);
+        let mut added = 0;
+        for (left, right, next) in windows {
+            let dists = match self.map.get_mut(&(left, right)) {
+                Some(dists) => dists,
+                None => self.map.entry(TokenPair::new(left, right)).or_default(),
+            };
+            let builder = match dists.iter().position(|(l, _)| *l == label) {
+                Some(i) => &mut dists[i].1,
+                None => {
+                    dists.push((label, TokenDistributionBuilder::new()));
+                    // Unwrap is safe, since we just pushed
+                    &mut dists.last_mut().unwrap().1
+                }
+            };
+            builder.add_token(next);
+            added += 1;
+        }
+        added
+    }
+
+    /// Uses up the builder and creates a new chain. Will return an error if the builder has not
+    /// been fed any texts.
+    pub fn build(self) -> Result<LabeledChain, LabeledChainBuilder> {
+        if self.map.is_empty() {
+            return Err(self);
+        }
+
+        let map = self
+            .map
+            .into_iter()
+            .map(|(pair, dists)| {
+                let dists = dists.into_iter().map(|(l, b)| (l, b.build())).collect();
+                (pair, dists)
+            })
+            .collect();
+        Ok(LabeledChain {
+            labels: self.labels,
+            map,
+        })
+    }
+}
+
+/// A second order Markov chain where every transition is labeled. Created using a
+/// [`LabeledChainBuilder`].
+///
+/// # Examples
+///
+/// ```
+/// # use rand::thread_rng;
+/// use markovish::labeled::LabeledChainBuilder;
+///
+/// let mut cb = LabeledChainBuilder::new();
+/// cb.feed_str_labeled("alice", "I like cats. I like tea.");
+/// cb.feed_str_labeled("bob", "I like dogs.");
+/// let chain = cb.build().unwrap();
+///
+/// let mut rng = thread_rng();
+/// let alice = chain.generate_str_for(&mut rng, "alice", 20).unwrap().concat();
+/// assert!(!alice.contains("dogs"));
+///
+/// // Bob is three times as likely to be chosen
+/// let blend = chain.generate_str_blended(&mut rng, &[("alice", 1.0), ("bob", 3.0)], 20);
+/// assert!(blend.is_some());
+/// ```
+#[derive(Clone, Debug)]
+pub struct LabeledChain {
+    labels: Vec<Token>,
+    /// The distribution of each label that has seen the pair.
+    map: HashMap<TokenPair, Vec<(usize, TokenDistribution)>>,
+}
+
+impl LabeledChain {
+    /// Returns an iterator of all labels, in the order they were first fed.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.labels.iter().map(Token::as_str)
+    }
+
+    /// Returns the distribution of `label` for `prev`, if it has seen the pair.
+    pub fn distribution_for(
+        &self,
+        label: &str,
+        prev: &TokenPairRef<'_>,
+    ) -> Option<&TokenDistribution> {
+        let label = self.labels.iter().position(|l| l == label)?;
+        self.map
+            .get(prev)?
+            .iter()
+            .find(|(l, _)| *l == label)
+            .map(|(_, dist)| dist)
+    }
+
+    /// Generates `n` tokens using only what `label` has been fed, randomly choosing a starting
+    /// point like [`Chain::generate_str()`](crate::Chain::generate_str).
+    ///
+    /// If the label has never been fed, `None` is returned.
+    pub fn generate_str_for<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        label: &str,
+        n: usize,
+    ) -> Option<Vec<&str>> {
+        self.generate_str_blended(rng, &[(label, 1.0)], n)
+    }
+
+    /// Generates `n` tokens using a blend of several labels. Every next token is chosen from
+    /// the distribution of one of the labels that have seen the previous tokens, where a label
+    /// with twice the weight is twice as likely to be chosen. Labels that do not exist, or have
+    /// a weight that is not a finite number above zero, are ignored.
+    ///
+    /// If none of the labels have been fed, `None` is returned.
+    pub fn generate_str_blended<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        weights: &[(&str, f64)],
+        n: usize,
+    ) -> Option<Vec<&str>> {
+        let mut weights: Vec<(usize, f64)> = weights
+            .iter()
+            .filter(|(_, w)| w.is_finite() && *w > 0.0)
+            .filter_map(|(label, w)| Some((self.labels.iter().position(|l| l == label)?, *w)))
+            .collect();
+        // Scaled so that the sum of the weights can never overflow
+        let max = weights.iter().map(|(_, w)| *w).fold(0.0, f64::max);
+        for (_, w) in weights.iter_mut() {
+            *w /= max;
+        }
+
+        let start = |rng: &mut R| {
+            self.map
+                .iter()
+                .filter(|(_, dists)| dists.iter().any(|(l, _)| weight_of(&weights, *l).is_some()))
+                .map(|(pair, _)| pair.as_ref())
+                .choose(rng)
+        };
+
+        let mut walk = Walk::new(Some(start(rng)?));
+        let mut res = Vec::with_capacity(n);
+        while res.len() < n {
+            let next = walk.step(
+                rng,
+                |rng, prev| self.blended_next(rng, &weights, prev),
+                start,
+            );
+            let Some(next) = next else {
+                break;
+            };
+            res.push(next);
+        }
+
+        Some(res)
+    }
+
+    /// Chooses one of the labels that have seen `prev` using their weights, and then a token
+    /// from the distribution of that label.
+    fn blended_next<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        weights: &[(usize, f64)],
+        prev: &TokenPairRef<'_>,
+    ) -> Option<TokenRef<'_>> {
+        let candidates: Vec<(f64, &TokenDistribution)> = self
+            .map
+            .get(prev)?
+            .iter()
+            .filter_map(|(l, dist)| Some((weight_of(weights, *l)?, dist)))
+            .collect();
+
+        let total: f64 = candidates.iter().map(|(w, _)| w).sum();
+        let mut target = rng.gen_range(0.0..total.max(f64::MIN_POSITIVE));
+        for (w, dist) in &candidates {
+            if target < *w {
+                return Some(dist.get_random_token(rng).as_str());
+            }
+            target -= w;
+        }
+        // Rounding errors may leave us here, so the last label is chosen
+        candidates
+            .last()
+            .map(|(_, dist)| dist.get_random_token(rng).as_str())
+    }
+}
+
+/// Returns the weight of the label with index `label`, if it has one.
+fn weight_of(weights: &[(usize, f64)], label: usize) -> Option<f64> {
+    weights.iter().find(|(l, _)| *l == label).map(|(_, w)| *w)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::LabeledChainBuilder;
+
+    #[test]
+    fn labels_share_pairs() {
+        let mut cb = LabeledChainBuilder::new();
+        assert_eq!(cb.feed_str_labeled("alice", "Hi"), 0);
+        assert_eq!(cb.feed_str_labeled("alice", "I am Alice"), 3);
+        assert_eq!(cb.feed_str_labeled("bob", "I am Bob"), 3);
+        let chain = cb.build().unwrap();
+
+        assert_eq!(chain.labels().collect::<Vec<_>>(), vec!["alice", "bob"]);
+        let dist = chain.distribution_for("bob", &("am", " ")).unwrap();
+        assert_eq!(dist.count("Bob"), 1);
+        assert_eq!(dist.count("Alice"), 0);
+
+        let mut rng = thread_rng();
+        for _ in 0..20 {
+            let text = chain.generate_str_for(&mut rng, "alice", 10).unwrap();
+            assert!(!text.contains(&"Bob"));
+            assert_eq!(text.len(), 10);
+        }
+        assert_eq!(chain.generate_str_for(&mut rng, "carol", 10), None);
+        assert_eq!(
+            chain.generate_str_blended(&mut rng, &[("alice", 0.0), ("bob", -1.0)], 10),
+            None
+        );
+        assert_eq!(
+            chain.generate_str_blended(
+                &mut rng,
+                &[("alice", f64::INFINITY), ("bob", f64::NAN)],
+                10
+            ),
+            None
+        );
+        for _ in 0..20 {
+            let text = chain
+                .generate_str_blended(&mut rng, &[("alice", f64::MAX), ("bob", f64::MAX)], 10)
+                .unwrap();
+            assert_eq!(text.len(), 10);
+        }
+    }
+}
@@ -26,20 +26,93 @@
 //! The examples in this crate use [`rand::thread_rng()`], but if you want things to go fast you
 //! could try using [`rand::rngs::SmallRng`](https://docs.rs/rand/latest/rand/rngs/struct.SmallRng.html),
 //! which is generally faster but not as safe (but you should NOT use this crate to generate passwords
-//! anyway).
+//! anyway). All methods taking a generator also accept a `&mut dyn RngCore`, in case you store
+//! your generator as a trait object.
 //!
 //! # Features
 //!
 //! `markovish` comes with some feature(s) that you can disable (or enable) at will. They are:
 //!
+//! - `compact_str`: Stores each [`token::Token`] as a
+//!   [`CompactString`](https://docs.rs/compact_str), so that tokens shorter than 24 bytes do not
+//!   need to be allocated on the heap. Since most tokens are short, this saves both memory and time.
+//! - `html`: Enables `ChainBuilder::feed_html()`, which only feeds the text of HTML documents
+//!   that a browser would show (see the `html` module).
 //! - `inline-more`: Enables the [`hashbrown`](https://crates.io/crates/hashbrown) `inline-more`
-//! feature, improving performance at the cost of compilation time. Enabled by default.
+//!   feature, improving performance at the cost of compilation time. Enabled by default.
+//! - `json`: Enables `Chain::to_portable_json()` and `Chain::from_portable_json()`, which use a
+//!   stable JSON format that can be read by other tools (see the `portable` module). Also enables
+//!   importing models from the Python library `markovify` (see the `markovify` module). Implies
+//!   `serde`.
+//! - `postcard`: Enables `Chain::to_postcard()` and `Chain::from_postcard()`, a compact binary
+//!   format suitable for embedding chains in firmware images. Implies `serde`.
+//! - `rand09`: Enables `rand09::Rand09`, which allows random number generators from `rand` 0.9
+//!   to be used for generation, during the migration away from `rand` 0.8.
+//! - `rayon`: Builds the distributions of a [`Chain`] in parallel when calling
+//!   [`ChainBuilder::build()`], which speeds up building chains from large texts. Also enables
+//!   `Chain::score_batch_par()`, which scores many texts in parallel.
+//! - `redb`: Enables `disk::DiskChain`, which stores its distributions in a
+//!   [`redb`](https://crates.io/crates/redb) database on disk, for chains larger than your memory.
+//! - `save`: Enables `Chain::save()` and `Chain::load()`, which store chains in files with a
+//!   versioned header (see the `file` module). Implies `serde`.
 //! - `serde`: Allows for serializing and deserializing some of the data structures in this library,
-//! so they can be stored and reused once created. Especially serializing [`Chain`] and [`ChainBuilder`]
-//! is useful, since the same chain can be recreated without having to parse the text again.
+//!   so they can be stored and reused once created. Especially serializing [`Chain`] and [`ChainBuilder`]
+//!   is useful, since the same chain can be recreated without having to parse the text again.
+//! - `tokenizers`: Enables `subword::HfTokenizer`, which splits text into subword tokens using a
+//!   vocabulary from the Hugging Face [`tokenizers`](https://crates.io/crates/tokenizers) library.
+//! - `u32-weights`: Counts token occurances using [`u32`] instead of [`usize`] (see
+//!   [`distribution::Weight`]), which makes chains smaller. Only use this if no token pair is
+//!   seen more than [`u32::MAX`] times in your texts.
+//! - `wasm`: Enables `wasm::JsChain`, a wrapper of [`Chain`] that can be used from JavaScript,
+//!   and makes random number generation work when building for `wasm32-unknown-unknown`. Note
+//!   that [`distribution::Weight`] is 32 bits on 32-bit targets, like with `u32-weights`.
+//! - `zstd`: Enables `Chain::save_compressed()` and `Chain::load_compressed()`, which compress
+//!   saved chains using [zstd](https://facebook.github.io/zstd/). Implies `save`.
 
+pub mod analysis;
+pub mod approx;
+pub mod arpa;
 pub mod chain;
+pub mod concurrent;
+pub mod constrained;
+pub mod delta;
+pub mod detokenize;
+#[cfg(feature = "redb")]
+pub mod disk;
 pub mod distribution;
+#[cfg(feature = "save")]
+pub mod file;
+pub mod first_order;
+pub mod frozen;
+pub mod generate;
+#[cfg(feature = "html")]
+pub mod html;
+pub mod labeled;
+pub mod limit;
+#[cfg(feature = "json")]
+pub mod markovify;
+pub mod metrics;
+pub mod overlay;
+#[cfg(feature = "json")]
+pub mod portable;
+pub mod prior;
+pub mod prompt;
+#[cfg(feature = "rand09")]
+pub mod rand09;
+pub mod sentence;
+#[cfg(feature = "serde")]
+mod sorted;
+pub mod store;
+pub mod stream;
+#[cfg(feature = "tokenizers")]
+pub mod subword;
 pub mod token;
+pub mod tokenize;
+pub mod unk;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod window;
 
 pub use chain::{Chain, ChainBuilder, IntoChainBuilder};
+pub use frozen::FrozenChain;
@@ -0,0 +1,295 @@
+//! A builder with a memory limit, for feeding large amounts of text without supervision, where
+//! running out of memory is worse than forgetting the rarest parts of the text.
+
+use std::mem::size_of;
+
+use hashbrown::HashSet;
+use itertools::Itertools;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::analysis::builder_pair_size;
+use crate::chain::AddedPair;
+use crate::distribution::{TokenDistributionBuilder, Weight};
+use crate::sentence::SentenceBounds;
+use crate::token::{Token, TokenPair, TokenRef};
+use crate::{Chain, ChainBuilder};
+
+/// Builds chains like a [`ChainBuilder`], but keeps it from using much more than a set amount of
+/// memory. Created using [`ChainBuilder::with_memory_limit()`].
+///
+/// Once the estimated memory use (see [`ChainBuilder::memory_estimate()`]) goes above the limit,
+/// the pairs seen the fewest times are removed until less than 90% of the limit is used. Those
+/// pairs are forgotten, as if they had never been fed, together with the sentence bounds (see
+/// [`ChainBuilder::feed_sentences()`]) that can no longer be reached without them. Pairs are only
+/// chosen by how often they were seen, so a common pair followed by many different tokens is kept
+/// even if it is large.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::ChainBuilder;
+///
+/// let mut cb = ChainBuilder::new().with_memory_limit(16 * 1024);
+/// for i in 0..1000 {
+///     cb.feed_str(&format!("{i} said hello."));
+/// }
+/// assert!(cb.builder().memory_estimate() <= 16 * 1024);
+/// assert!(cb.pruned_pairs() > 0);
+///
+/// // The most common pairs are kept
+/// let chain = cb.build().unwrap();
+/// assert_eq!(chain.count(&("said", " "), "hello"), Some(1000));
+/// ```
+#[derive(Clone, Debug)]
+pub struct MemoryLimitedChainBuilder {
+    limit: usize,
+    /// Updated cheaply when pairs or tokens are added, and recalculated when it goes above the
+    /// limit.
+    estimate: usize,
+    pruned_pairs: usize,
+    cb: ChainBuilder,
+}
+
+impl ChainBuilder {
+    /// Limits how much memory the builder may use to roughly `bytes`, see
+    /// [`MemoryLimitedChainBuilder`]. If the builder already uses more, the least common pairs
+    /// are removed right away.
+    pub fn with_memory_limit(self, bytes: usize) -> MemoryLimitedChainBuilder {
+        let mut cb = MemoryLimitedChainBuilder {
+            limit: bytes,
+            estimate: 0,
+            pruned_pairs: 0,
+            cb: self,
+        };
+        cb.enforce_limit();
+        cb
+    }
+}
+
+impl MemoryLimitedChainBuilder {
+    /// Feeds the builder with more text, split like in [`ChainBuilder::feed_str()`].
+    ///
+    /// Returns `false` if the text was too short to be used.
+    pub fn feed_str(&mut self, content: &str) -> bool {
+        self.feed_tokens(content.split_word_bounds())
+    }
+
+    /// Feeds the builder with more text, and records where each sentence starts and ends, like
+    /// [`ChainBuilder::feed_sentences()`]. The sentence bounds count towards the limit, and are
+    /// removed together with the pairs they need.
+    ///
+    /// Returns `false` if the text was too short to be used.
+    pub fn feed_sentences(&mut self, content: &str) -> bool {
+        if !self.feed_str(content) {
+            return false;
+        }
+
+        let buckets = |cb: &ChainBuilder| cb.sentences.sets().map(HashSet::capacity);
+        let before = buckets(&self.cb);
+        let bytes = self.cb.sentences.record_sentences(content);
+        let after = buckets(&self.cb);
+        self.estimate += bytes + (after[0] - before[0] + after[1] - before[1]) * SENTENCE_BUCKET;
+        // The pairs of the sentences may have been removed while feeding them. Their ends are
+        // removed the next time the limit is enforced, since finding them is more expensive.
+        let map = &self.cb.map;
+        self.cb
+            .sentences
+            .retain_starts(|pair| map.contains_key(pair));
+        if self.estimate > self.limit {
+            self.enforce_limit();
+        }
+        true
+    }
+
+    /// Feeds the builder with pre-split tokens, like [`ChainBuilder::feed_tokens()`].
+    ///
+    /// Returns `false` if there were too few tokens to be used.
+    pub fn feed_tokens<'a>(&mut self, tokens: impl Iterator<Item = TokenRef<'a>>) -> bool {
+        let mut fed = false;
+        for (left, right, next) in tokens.tuple_windows() {
+            fed = true;
+            let pairs = self.cb.map.capacity();
+            let (tokens, next_buckets) = self
+                .cb
+                .map
+                .get(&(left, right))
+                .map_or((0, 0), |b| (b.map.len(), b.map.capacity()));
+
+            let added = self.cb.add_occurance(&(left, right), next);
+            let b = &self.cb.map[&(left, right)];
+            // Growing a map allocates room for many entries at once
+            let mut bytes = (self.cb.map.capacity() - pairs) * PAIR_BUCKET
+                + (b.map.capacity() - next_buckets) * TOKEN_BUCKET;
+            if matches!(added, AddedPair::New) {
+                bytes += left.len() + right.len();
+            }
+            if b.map.len() > tokens {
+                bytes += next.len();
+            }
+
+            self.estimate += bytes;
+            if self.estimate > self.limit {
+                self.enforce_limit();
+            }
+        }
+        fed
+    }
+
+    /// Removes the pairs seen the fewest times until the builder uses less than 90% of its
+    /// limit.
+    fn enforce_limit(&mut self) {
+        let mut estimate = self.cb.memory_estimate();
+        if estimate <= self.limit {
+            self.estimate = estimate;
+            return;
+        }
+
+        let target = self.limit / 10 * 9;
+        // Removing pairs does not always make the map itself smaller, so this may need a few
+        // rounds
+        while estimate > target && !self.cb.map.is_empty() {
+            let mut by_count: Vec<(u64, usize, &TokenPair)> = self
+                .cb
+                .map
+                .iter()
+                .map(|(pair, b)| {
+                    let count = b.map.values().map(|&n| n as u64).sum();
+                    (count, builder_pair_size(pair, b) + PAIR_BUCKET, pair)
+                })
+                .collect();
+            by_count.sort_unstable_by_key(|(count, ..)| *count);
+
+            let mut removed: HashSet<TokenPair> = HashSet::new();
+            for (_, size, pair) in by_count {
+                if estimate <= target && !removed.is_empty() {
+                    break;
+                }
+                estimate = estimate.saturating_sub(size);
+                removed.insert(pair.clone());
+            }
+
+            self.pruned_pairs += removed.len();
+            self.cb.map.retain(|pair, _| !removed.contains(pair));
+            self.cb.map.shrink_to_fit();
+            prune_sentences(&mut self.cb);
+            estimate = self.cb.memory_estimate();
+        }
+        if self.cb.map.is_empty() {
+            // Without any pairs, no sentence can be generated
+            self.cb.sentences = SentenceBounds::default();
+            estimate = self.cb.memory_estimate();
+        }
+
+        self.estimate = estimate;
+    }
+
+    /// Returns the limit in bytes.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns how many pairs have been removed to stay below the limit.
+    pub fn pruned_pairs(&self) -> usize {
+        self.pruned_pairs
+    }
+
+    /// Returns the builder with everything that has been kept.
+    pub fn builder(&self) -> &ChainBuilder {
+        &self.cb
+    }
+
+    /// Uses up the builder and returns the [`ChainBuilder`] without a limit.
+    pub fn into_builder(self) -> ChainBuilder {
+        self.cb
+    }
+
+    /// Uses up the builder and creates a new chain, like [`ChainBuilder::build()`]. Will return
+    /// an error if the builder has not been fed any texts.
+    pub fn build(self) -> Result<Chain, ChainBuilder> {
+        self.cb.build()
+    }
+}
+
+/// Removes the sentence starts that are no longer pairs of `cb`, and the sentence ends that can
+/// no longer be generated.
+fn prune_sentences(cb: &mut ChainBuilder) {
+    let map = &cb.map;
+    let reachable: HashSet<(&str, &str)> = map
+        .iter()
+        .flat_map(|(TokenPair(_, right), b)| {
+            b.map
+                .keys()
+                .map(move |next| (right.as_str(), next.as_str()))
+        })
+        .collect();
+    cb.sentences.retain_starts(|pair| map.contains_key(pair));
+    // Pairs of the builder can be generated as start tokens
+    cb.sentences.retain_ends(|pair| {
+        map.contains_key(pair) || reachable.contains(&(pair.0.as_str(), pair.1.as_str()))
+    });
+    cb.sentences.shrink_to_fit();
+}
+
+/// Bytes used by every bucket in the map of pairs of a builder.
+const PAIR_BUCKET: usize = size_of::<(TokenPair, TokenDistributionBuilder)>() + 1;
+/// Bytes used by every bucket in the map of a distribution builder.
+const TOKEN_BUCKET: usize = size_of::<(Token, Weight)>() + 1;
+/// Bytes used by every bucket in the sets of sentence bounds.
+const SENTENCE_BUCKET: usize = size_of::<TokenPair>() + 1;
+
+#[cfg(test)]
+mod tests {
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn prunes_least_common_pairs() {
+        let cb = ChainBuilder::new().feed_str("a b a b a b c d").into_cb();
+        let full = cb.memory_estimate();
+
+        // Enough room for everything
+        let limited = cb.clone().with_memory_limit(full * 2);
+        assert_eq!(limited.pruned_pairs(), 0);
+
+        // "a" " " is the most common pair, and must be kept
+        let limited = cb.with_memory_limit(full - 1);
+        assert!(limited.pruned_pairs() > 0);
+        assert!(limited.builder().memory_estimate() <= (full - 1) / 10 * 9);
+        let chain = limited.build().unwrap();
+        assert_eq!(chain.count(&("a", " "), "b"), Some(3));
+        assert_eq!(chain.count(&("c", " "), "d"), None);
+
+        let mut limited = ChainBuilder::new().with_memory_limit(1 << 20);
+        assert!(!limited.feed_sentences("Hi"));
+        assert!(limited.feed_sentences("I am a cat. You are a dog."));
+        let chain = limited.build().unwrap();
+        assert_eq!(chain.sentence_bounds().starts().count(), 2);
+
+        // Sentence bounds count towards the limit, and are removed with their pairs
+        let text = "I am a cat. You are a dog. We are birds.";
+        let cb = ChainBuilder::new().feed_str(text).into_cb();
+        let with_sentences = ChainBuilder::new().feed_sentences(text).into_cb();
+        assert!(with_sentences.memory_estimate() > cb.memory_estimate());
+        let mut limited = ChainBuilder::new().with_memory_limit(cb.memory_estimate());
+        assert!(limited.feed_sentences(text));
+        assert!(limited.pruned_pairs() > 0);
+        let cb = limited.into_builder();
+        for (left, right) in cb.sentences.starts() {
+            assert!(cb.map.contains_key(&(left, right)));
+        }
+        for (left, right) in cb.sentences.ends() {
+            let reachable = cb
+                .map
+                .iter()
+                .any(|(pair, b)| pair.1 == left && b.map.contains_key(right));
+            assert!(reachable || cb.map.contains_key(&(left, right)));
+        }
+
+        let mut limited = ChainBuilder::new().with_memory_limit(1);
+        // Fed, but every pair is removed right away
+        assert!(limited.feed_sentences("I am a cat."));
+        assert_eq!(limited.builder().sentences, Default::default());
+        assert!(!limited.feed_str("Hi"));
+        assert!(limited.feed_str("I am tiny"));
+        assert!(limited.into_builder().build().is_err());
+    }
+}
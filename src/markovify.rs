@@ -0,0 +1,193 @@
+//! Importing models trained using the Python library
+//! [`markovify`](https://github.com/jsvine/markovify). Requires the `json` feature.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::distribution::Weight;
+use crate::token::TokenPair;
+use crate::ChainBuilder;
+
+/// The token `markovify` uses to pad the start of every sentence.
+pub const MARKOVIFY_BEGIN: &str = "___BEGIN__";
+
+/// The token `markovify` uses to mark the end of every sentence.
+pub const MARKOVIFY_END: &str = "___END__";
+
+/// Errors that can occur when importing a `markovify` model.
+#[derive(Debug)]
+pub enum MarkovifyError {
+    /// The input is not valid JSON.
+    Json(serde_json::Error),
+    /// The model does not have a state size of 2, so it cannot be used as a second order chain.
+    UnsupportedStateSize(usize),
+    /// The JSON is valid, but does not look like a `markovify` model.
+    InvalidFormat(&'static str),
+}
+
+impl fmt::Display for MarkovifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid markovify JSON: {e}"),
+            Self::UnsupportedStateSize(n) => {
+                write!(f, "markovify model has state size {n}, only 2 is supported")
+            }
+            Self::InvalidFormat(reason) => write!(f, "invalid markovify model: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MarkovifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for MarkovifyError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl ChainBuilder {
+    /// Creates a builder from a model exported using `to_json()` in `markovify`, either of a
+    /// `markovify.Text` or of a `markovify.Chain`. Only models with a `state_size` of 2 are
+    /// supported.
+    ///
+    /// `markovify` splits text on whitespace, so the tokens will be words without any whitespace
+    /// between them. Its sentence start and end markers ([`MARKOVIFY_BEGIN`] and
+    /// [`MARKOVIFY_END`]) are not imported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// let json = r#"[[["___BEGIN__", "___BEGIN__"], {"I": 1}],
+    ///                [["___BEGIN__", "I"], {"am": 1}],
+    ///                [["I", "am"], {"markovify.": 1}],
+    ///                [["am", "markovify."], {"___END__": 1}]]"#;
+    /// let chain = ChainBuilder::from_markovify_json(json).unwrap().build().unwrap();
+    /// assert_eq!(chain.pairs().count(), 1);
+    /// ```
+    pub fn from_markovify_json(json: &str) -> Result<Self, MarkovifyError> {
+        let value: Value = serde_json::from_str(json)?;
+
+        let model = match value {
+            Value::Object(mut text) => {
+                if let Some(size) = text.get("state_size") {
+                    let size = size
+                        .as_u64()
+                        .ok_or(MarkovifyError::InvalidFormat("state_size is not a number"))?;
+                    if size != 2 {
+                        return Err(MarkovifyError::UnsupportedStateSize(size as usize));
+                    }
+                }
+                // `markovify.Text` stores its chain as a JSON string inside the JSON
+                match text.remove("chain") {
+                    Some(Value::String(chain)) => serde_json::from_str(&chain)?,
+                    Some(chain @ Value::Array(_)) => chain,
+                    _ => return Err(MarkovifyError::InvalidFormat("missing chain")),
+                }
+            }
+            chain @ Value::Array(_) => chain,
+            _ => {
+                return Err(MarkovifyError::InvalidFormat(
+                    "expected an object or a list",
+                ))
+            }
+        };
+
+        let Value::Array(entries) = model else {
+            return Err(MarkovifyError::InvalidFormat("chain is not a list"));
+        };
+
+        let mut cb = ChainBuilder::new();
+        for entry in entries {
+            let (state, nexts) = match entry {
+                Value::Array(mut pair) if pair.len() == 2 => {
+                    let nexts = pair.pop().unwrap();
+                    (pair.pop().unwrap(), nexts)
+                }
+                _ => return Err(MarkovifyError::InvalidFormat("invalid chain entry")),
+            };
+
+            let state: Vec<&str> = state
+                .as_array()
+                .ok_or(MarkovifyError::InvalidFormat("state is not a list"))?
+                .iter()
+                .map(|t| t.as_str())
+                .collect::<Option<_>>()
+                .ok_or(MarkovifyError::InvalidFormat("state contains a non-string"))?;
+            let [left, right] = state[..] else {
+                return Err(MarkovifyError::UnsupportedStateSize(state.len()));
+            };
+            let nexts = nexts.as_object().ok_or(MarkovifyError::InvalidFormat(
+                "transitions are not an object",
+            ))?;
+
+            if left == MARKOVIFY_BEGIN || right == MARKOVIFY_BEGIN {
+                continue;
+            }
+
+            for (next, n) in nexts {
+                if next == MARKOVIFY_END {
+                    continue;
+                }
+                let n = n
+                    .as_u64()
+                    .and_then(|n| Weight::try_from(n).ok())
+                    .ok_or(MarkovifyError::InvalidFormat("invalid transition count"))?;
+                cb.map
+                    .entry(TokenPair::new(left, right))
+                    .or_default()
+                    .add_token_n(next, n);
+            }
+        }
+
+        // Pairs where every transition was skipped have no tokens
+        cb.map.retain(|_, b| !b.map.is_empty());
+        Ok(cb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::MarkovifyError;
+    use crate::ChainBuilder;
+
+    #[test]
+    fn import_markovify_text() {
+        let chain = r#"[[["___BEGIN__", "___BEGIN__"], {"I": 2}], [["___BEGIN__", "I"], {"am": 2}], [["I", "am"], {"a": 1, "the": 1}], [["am", "a"], {"cat.": 1}], [["am", "the"], {"cat.": 1}], [["a", "cat."], {"___END__": 1}], [["the", "cat."], {"___END__": 1}]]"#;
+        let text = serde_json::json!({
+            "state_size": 2,
+            "chain": chain,
+            "parsed_sentences": null,
+        })
+        .to_string();
+
+        let chain = ChainBuilder::from_markovify_json(&text)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(chain.pairs().count(), 3);
+        assert_eq!(
+            chain.generate_next_token(&mut thread_rng(), &("am", "a")),
+            Some("cat.")
+        );
+    }
+
+    #[test]
+    fn reject_other_state_sizes() {
+        let text = r#"{"state_size": 3, "chain": "[]"}"#;
+        assert!(matches!(
+            ChainBuilder::from_markovify_json(text),
+            Err(MarkovifyError::UnsupportedStateSize(3))
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+//! Metrics for judging text generated by a [`Chain`], for example to compare different corpora
+//! or settings.
+
+use std::hash::Hash;
+
+use hashbrown::HashSet;
+use rand::Rng;
+
+use crate::Chain;
+
+/// Returns the share of the `n`-grams (runs of `n` tokens) in `tokens` that are unique, which is a
+/// number between zero and one. A text that keeps repeating itself gets a low ratio, and a text
+/// that never repeats any `n` tokens gets one.
+///
+/// If there are fewer than `n` tokens, or `n` is zero, there is nothing that can be repeated and
+/// one is returned.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::metrics::distinct_ngram_ratio;
+///
+/// assert_eq!(distinct_ngram_ratio(&["a", "b", "c", "d"], 2), 1.0);
+/// // "a b" is found three times, and "b a" twice
+/// assert_eq!(distinct_ngram_ratio(&["a", "b", "a", "b", "a", "b"], 2), 0.4);
+/// ```
+pub fn distinct_ngram_ratio<T: Eq + Hash>(tokens: &[T], n: usize) -> f64 {
+    if n == 0 || tokens.len() < n {
+        return 1.0;
+    }
+
+    let ngrams = tokens.windows(n);
+    let total = ngrams.len();
+    let distinct = ngrams.collect::<HashSet<_>>().len();
+    distinct as f64 / total as f64
+}
+
+/// How repetitive text generated by a chain is, see [`Chain::sample_quality()`]. Every value is
+/// the average [`distinct_ngram_ratio()`] of the samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampleQuality {
+    /// The share of unique tokens.
+    pub distinct_unigrams: f64,
+    /// The share of unique runs of two tokens.
+    pub distinct_bigrams: f64,
+    /// The share of unique runs of three tokens. Since the chain looks at two tokens to choose
+    /// the third, this is usually the most telling value.
+    pub distinct_trigrams: f64,
+}
+
+impl Chain {
+    /// Generates `samples` texts of `len` tokens each, and measures how repetitive they are. This
+    /// makes it possible to compare corpora (or ways of building chains) without reading through
+    /// a lot of generated text.
+    ///
+    /// If `samples` is zero, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let quality = chain.sample_quality(&mut thread_rng(), 10, 50).unwrap();
+    /// assert!(quality.distinct_trigrams <= 1.0);
+    /// ```
+    pub fn sample_quality<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        samples: usize,
+        len: usize,
+    ) -> Option<SampleQuality> {
+        if samples == 0 {
+            return None;
+        }
+
+        let mut quality = SampleQuality {
+            distinct_unigrams: 0.0,
+            distinct_bigrams: 0.0,
+            distinct_trigrams: 0.0,
+        };
+        for _ in 0..samples {
+            let tokens = self.generate_str(rng, len)?;
+            quality.distinct_unigrams += distinct_ngram_ratio(&tokens, 1);
+            quality.distinct_bigrams += distinct_ngram_ratio(&tokens, 2);
+            quality.distinct_trigrams += distinct_ngram_ratio(&tokens, 3);
+        }
+
+        let samples = samples as f64;
+        quality.distinct_unigrams /= samples;
+        quality.distinct_bigrams /= samples;
+        quality.distinct_trigrams /= samples;
+        Some(quality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::distinct_ngram_ratio;
+    use crate::Chain;
+
+    #[test]
+    fn too_few_tokens_are_distinct() {
+        assert_eq!(distinct_ngram_ratio::<&str>(&[], 1), 1.0);
+        assert_eq!(distinct_ngram_ratio(&["a", "a"], 3), 1.0);
+        assert_eq!(distinct_ngram_ratio(&["a", "a"], 0), 1.0);
+        assert_eq!(distinct_ngram_ratio(&["a", "a"], 1), 0.5);
+    }
+
+    #[test]
+    fn looping_chain_is_repetitive() {
+        let chain = Chain::from_text("la la la la la la").unwrap();
+        let quality = chain.sample_quality(&mut thread_rng(), 3, 20).unwrap();
+        assert!(quality.distinct_trigrams < 0.2);
+        assert_eq!(chain.sample_quality(&mut thread_rng(), 0, 20), None);
+    }
+}
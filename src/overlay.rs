@@ -0,0 +1,304 @@
+//! Copy-on-write layers on top of a shared [`Chain`]. An [`OverlayChain`] only stores the pairs
+//! that have been changed, so a large chain can be slightly personalized for many users (or
+//! tenants) without copying it.
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+use itertools::Itertools;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::distribution::{TokenDistribution, TokenDistributionBuilder, Weight};
+use crate::store::TransitionStore;
+use crate::token::{TokenPair, TokenPairRef, TokenRef};
+use crate::Chain;
+
+/// A [`Chain`] shared with others, with some of its pairs overridden. Lookups first check the
+/// overrides, and then the base chain, which is never changed.
+///
+/// The base can be anything that dereferences to a [`Chain`], such as an [`Arc<Chain>`] shared
+/// between threads or a plain reference.
+///
+/// # Examples
+///
+/// ```
+/// # use markovish::Chain;
+/// # use rand::thread_rng;
+/// use std::sync::Arc;
+/// use markovish::overlay::OverlayChain;
+///
+/// let base = Arc::new(Chain::from_text("I am a cat.").unwrap());
+/// let mut overlay = OverlayChain::new(Arc::clone(&base));
+/// overlay.feed_str("I am a dog.");
+///
+/// // The overlay has learned new pairs, but the base is left untouched
+/// assert_eq!(overlay.count(&("a", " "), "dog"), Some(1));
+/// assert_eq!(overlay.count(&("a", " "), "cat"), Some(1));
+/// assert_eq!(base.count(&("a", " "), "dog"), Some(0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct OverlayChain<B = Arc<Chain>> {
+    base: B,
+    /// The amount of pairs in `base`, which never changes.
+    base_pairs: usize,
+    /// The amount of overridden pairs that are not in `base`.
+    added_pairs: usize,
+    overrides: HashMap<TokenPair, TokenDistribution>,
+}
+
+impl<B: Deref<Target = Chain>> OverlayChain<B> {
+    /// Creates an overlay without any overrides, which behaves exactly like `base`.
+    pub fn new(base: B) -> Self {
+        let base_pairs = base.map.values().map(HashMap::len).sum();
+        Self {
+            base,
+            base_pairs,
+            added_pairs: 0,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Returns the shared chain below the overrides.
+    pub fn base(&self) -> &Chain {
+        &self.base
+    }
+
+    /// Returns an iterator of the pairs that have been overridden, in no particular order.
+    pub fn overridden(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        self.overrides.keys().map(TokenPair::as_ref)
+    }
+
+    /// Returns `true` if `pair` has been overridden.
+    pub fn is_overridden(&self, pair: &TokenPairRef<'_>) -> bool {
+        self.overrides.contains_key(pair)
+    }
+
+    /// Replaces the distribution of the tokens that may follow `pair`, no matter what is in the
+    /// base chain. The pair does not have to exist in the base chain. Returns the previous
+    /// override, if any.
+    pub fn set_distribution(
+        &mut self,
+        pair: &TokenPairRef<'_>,
+        dist: TokenDistribution,
+    ) -> Option<TokenDistribution> {
+        self.insert_override(TokenPair::from(pair), dist)
+    }
+
+    /// Removes the override of `pair`, so that it behaves like in the base chain again. Returns
+    /// the removed override, if any.
+    pub fn reset(&mut self, pair: &TokenPairRef<'_>) -> Option<TokenDistribution> {
+        let removed = self.overrides.remove(pair)?;
+        if self.base.get_distribution(pair).is_none() {
+            self.added_pairs -= 1;
+        }
+        Some(removed)
+    }
+
+    /// Removes all overrides.
+    pub fn clear(&mut self) {
+        self.overrides.clear();
+        self.added_pairs = 0;
+    }
+
+    /// Overrides `pair`, keeping track of how many pairs are not in the base chain.
+    fn insert_override(
+        &mut self,
+        pair: TokenPair,
+        dist: TokenDistribution,
+    ) -> Option<TokenDistribution> {
+        let added = self.base.get_distribution(&pair.as_ref()).is_none();
+        let previous = self.overrides.insert(pair, dist);
+        if added && previous.is_none() {
+            self.added_pairs += 1;
+        }
+        previous
+    }
+
+    /// Feeds the overlay with `content`, split like in [`ChainBuilder::feed_str()`]. Pairs that
+    /// are seen are copied from the base chain (or the overrides) and then updated, so the new
+    /// occurances are added to the ones in the base chain.
+    ///
+    /// Returns the number of pairs that were changed, which is zero if the text was too short.
+    ///
+    /// [`ChainBuilder::feed_str()`]: crate::ChainBuilder::feed_str
+    pub fn feed_str(&mut self, content: &str) -> usize {
+        self.feed_tokens(content.split_word_bounds())
+    }
+
+    /// Feeds the overlay with pre-split tokens, like [`OverlayChain::feed_str()`].
+    pub fn feed_tokens<'a>(&mut self, tokens: impl Iterator<Item = TokenRef<'a>>) -> usize {
+        let mut builders: HashMap<TokenPair, TokenDistributionBuilder> = HashMap::new();
+        for (left, right, next) in tokens.tuple_windows() {
+            let pair = (left, right);
+            if !builders.contains_key(&pair) {
+                let mut builder = TokenDistributionBuilder::new();
+                for (token, n) in self.lookup(&pair).into_iter().flat_map(|d| d.iter()) {
+                    builder.add_token_n(token, n);
+                }
+                builders.insert(TokenPair::from(&pair), builder);
+            }
+            // Unwrap is safe, since we just made sure it exists
+            builders.get_mut(&pair).unwrap().add_token(next);
+        }
+
+        let changed = builders.len();
+        for (pair, builder) in builders {
+            self.insert_override(pair, builder.build());
+        }
+        changed
+    }
+
+    /// Returns an iterator of all pairs in either the base chain or the overrides.
+    pub fn pairs(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        self.base.pairs().chain(self.added())
+    }
+
+    /// Returns an iterator of the overridden pairs that are not in the base chain.
+    fn added(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        self.overridden()
+            .filter(|pair| self.base.get_distribution(pair).is_none())
+    }
+
+    /// Like [`Chain::count()`], but using the overrides.
+    pub fn count(&self, prev: &TokenPairRef<'_>, next: &str) -> Option<Weight> {
+        Some(self.lookup(prev)?.count(next))
+    }
+
+    /// Randomly chooses two tokens that are known to be able to generate a new token, from
+    /// either the base chain or the overrides. Every pair is as likely to be chosen.
+    ///
+    /// Only the overrides are looked through, and only when one of the pairs not in the base
+    /// chain is chosen. Otherwise the base chain chooses among its own pairs using
+    /// [`Chain::start_tokens()`].
+    pub fn start_tokens<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenPairRef<'_>> {
+        let added = self.added_pairs;
+        if added > 0 && rng.gen_range(0..self.base_pairs + added) < added {
+            self.added().choose(rng)
+        } else {
+            self.base.start_tokens(rng)
+        }
+    }
+
+    /// Like [`Chain::generate_next_token()`], but using the overrides.
+    pub fn generate_next_token<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+    ) -> Option<TokenRef<'_>> {
+        Some(self.lookup(prev)?.get_random_token(rng))
+    }
+
+    /// Like [`Chain::generate_str()`], but using the overrides.
+    pub fn generate_str<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<&str>> {
+        let start = self.start_tokens(rng)?;
+        self.generate_tokens(rng, &start, n)
+    }
+}
+
+impl<B: Deref<Target = Chain>> TransitionStore for OverlayChain<B> {
+    type Dist<'a>
+        = &'a TokenDistribution
+    where
+        Self: 'a;
+    type Token<'a>
+        = TokenRef<'a>
+    where
+        Self: 'a;
+
+    fn lookup(&self, prev: &TokenPairRef<'_>) -> Option<Self::Dist<'_>> {
+        self.overrides
+            .get(prev)
+            .or_else(|| self.base.get_distribution(prev))
+    }
+
+    fn random_pair<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenPairRef<'_>> {
+        self.start_tokens(rng)
+    }
+
+    fn generate_next<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+    ) -> Option<TokenRef<'_>> {
+        self.generate_next_token(rng, prev)
+    }
+}
+
+impl<B: Deref<Target = Chain>> From<B> for OverlayChain<B> {
+    fn from(base: B) -> Self {
+        Self::new(base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashSet;
+    use rand::thread_rng;
+
+    use super::OverlayChain;
+    use crate::distribution::TokenDistribution;
+    use crate::token::TokenPair;
+    use crate::Chain;
+
+    #[test]
+    fn overrides_and_resets() {
+        let base = Chain::from_text("I am a cat.").unwrap();
+        let mut overlay = OverlayChain::new(&base);
+        let dist = TokenDistribution::try_from_parts(vec!["was".into()], vec![1]).unwrap();
+
+        assert!(overlay.set_distribution(&("I", " "), dist).is_none());
+        assert!(overlay.is_overridden(&("I", " ")));
+        assert_eq!(
+            overlay.generate_next_token(&mut thread_rng(), &("I", " ")),
+            Some("was")
+        );
+        assert_eq!(overlay.pairs().count(), base.pairs().count());
+
+        assert!(overlay.reset(&("I", " ")).is_some());
+        assert_eq!(
+            overlay.generate_next_token(&mut thread_rng(), &("I", " ")),
+            Some("am")
+        );
+    }
+
+    #[test]
+    fn feeding_adds_new_pairs() {
+        let base = Chain::from_text("I am a cat.").unwrap();
+        let mut overlay = OverlayChain::new(&base);
+        assert_eq!(overlay.feed_str("a"), 0);
+        assert_eq!(overlay.feed_str("a cat! Hi"), 4);
+
+        assert_eq!(overlay.count(&(" ", "cat"), "."), Some(1));
+        assert_eq!(overlay.count(&(" ", "cat"), "!"), Some(1));
+        assert_eq!(overlay.pairs().count(), base.pairs().count() + 2);
+        assert_eq!(
+            overlay.generate_str(&mut thread_rng(), 10).unwrap().len(),
+            10
+        );
+    }
+
+    #[test]
+    fn start_tokens_include_added_pairs() {
+        let base = Chain::from_text("I am").unwrap();
+        let mut overlay = OverlayChain::new(&base);
+        let mut rng = thread_rng();
+        assert_eq!(overlay.start_tokens(&mut rng), Some(("I", " ")));
+
+        overlay.feed_str("You are here");
+        let starts: HashSet<_> = (0..100)
+            .map(|_| TokenPair::from(&overlay.start_tokens(&mut rng).unwrap()))
+            .collect();
+        assert_eq!(starts.len(), 4);
+        assert!(overlay.pairs().all(|pair| starts.contains(&pair)));
+
+        overlay.reset(&("You", " "));
+        for _ in 0..100 {
+            assert_ne!(overlay.start_tokens(&mut rng), Some(("You", " ")));
+        }
+        overlay.clear();
+        assert_eq!(overlay.start_tokens(&mut rng), Some(("I", " ")));
+    }
+}
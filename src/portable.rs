@@ -0,0 +1,291 @@
+//! A documented and stable JSON representation of a [`Chain`], for exchanging chains with tools
+//! not written in Rust. Requires the `json` feature.
+//!
+//! Unlike serializing a [`Chain`] with `serde` directly, this format does not depend on how the
+//! chain is laid out internally, and will stay the same between versions of this crate. It looks
+//! like this:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "order": 2,
+//!   "vocab": [" ", "I", "am"],
+//!   "transitions": {
+//!     "1": { "0": { "2": 1 } }
+//!   },
+//!   "sentences": { "starts": [[1, 0]], "ends": [[0, 2]] }
+//! }
+//! ```
+//!
+//! - `version` is the version of this format, currently [`PORTABLE_VERSION`].
+//! - `order` is the order of the chain, always `2`.
+//! - `vocab` is every token in the chain, sorted and without duplicates.
+//! - `transitions` maps the index in `vocab` of the left token of a pair, to the index of the
+//!   right token, to the index of each token that may follow the pair, to how many times it has
+//!   been seen. Since JSON only allows strings as keys, the indices are written as strings.
+//! - `sentences` is the first and last two tokens of every sentence, recorded using
+//!   [`ChainBuilder::feed_sentences()`](crate::ChainBuilder::feed_sentences), as pairs of
+//!   indices in `vocab`. It is left out if no sentences have been recorded.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::distribution::{DistributionError, TokenDistribution, Weight};
+use crate::sentence::SentenceBounds;
+use crate::token::{Token, TokenPair, TokenPairRef};
+use crate::Chain;
+
+/// The current version of the portable format.
+pub const PORTABLE_VERSION: u32 = 1;
+
+/// Left token index to right token index to next token index to count.
+type Transitions = BTreeMap<usize, BTreeMap<usize, BTreeMap<usize, Weight>>>;
+
+#[derive(Serialize, Deserialize)]
+struct PortableChain {
+    version: u32,
+    order: u8,
+    vocab: Vec<Token>,
+    transitions: Transitions,
+    #[serde(default, skip_serializing_if = "PortableSentences::is_empty")]
+    sentences: PortableSentences,
+}
+
+/// Sentence bounds as sorted pairs of indices in the vocabulary.
+#[derive(Default, Serialize, Deserialize)]
+struct PortableSentences {
+    starts: Vec<(usize, usize)>,
+    ends: Vec<(usize, usize)>,
+}
+
+impl PortableSentences {
+    fn is_empty(&self) -> bool {
+        self.starts.is_empty() && self.ends.is_empty()
+    }
+}
+
+/// Errors that can occur when reading a chain in the portable JSON format.
+#[derive(Debug)]
+pub enum PortableJsonError {
+    /// The input is not valid JSON, or does not have the right fields.
+    Json(serde_json::Error),
+    /// The input uses an unknown version of the format.
+    UnsupportedVersion(u32),
+    /// The input contains a chain of an order this crate does not support.
+    UnsupportedOrder(u8),
+    /// A transition refers to an index that is not in the vocabulary.
+    UnknownToken(usize),
+    /// The counts of a pair could not be turned into a distribution.
+    Distribution(DistributionError),
+    /// There are no transitions, and a [`Chain`] cannot be empty.
+    Empty,
+}
+
+impl fmt::Display for PortableJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid portable chain JSON: {e}"),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported portable chain version {v}, latest supported is {PORTABLE_VERSION}"
+            ),
+            Self::UnsupportedOrder(o) => write!(f, "chain of order {o} is not supported"),
+            Self::UnknownToken(i) => write!(f, "token index {i} is not in the vocabulary"),
+            Self::Distribution(e) => write!(f, "invalid transition counts: {e}"),
+            Self::Empty => write!(f, "chain has no transitions"),
+        }
+    }
+}
+
+impl std::error::Error for PortableJsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            Self::Distribution(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for PortableJsonError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl Chain {
+    /// Writes the chain in the portable JSON format described in the [`portable`](self) module.
+    /// The output is the same every time for the same chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am portable").unwrap();
+    /// let json = chain.to_portable_json();
+    /// let same = Chain::from_portable_json(&json).unwrap();
+    /// assert_eq!(json, same.to_portable_json());
+    /// ```
+    pub fn to_portable_json(&self) -> String {
+        let mut vocab: Vec<&str> = Vec::new();
+        for (left, rights) in &self.map {
+            vocab.push(left);
+            for (right, dist) in rights {
+                vocab.push(right);
+                vocab.extend(dist.parts().0.iter().map(Token::as_str));
+            }
+        }
+        let bounds = &self.sentences;
+        for (left, right) in bounds.starts().chain(bounds.ends()) {
+            vocab.extend([left, right]);
+        }
+        vocab.sort_unstable();
+        vocab.dedup();
+
+        let index = |token: &str| vocab.binary_search(&token).unwrap();
+        let mut transitions = Transitions::new();
+        for (left, rights) in &self.map {
+            let by_right = transitions.entry(index(left)).or_default();
+            for (right, dist) in rights {
+                let (choices, weights) = dist.parts();
+                by_right.insert(
+                    index(right),
+                    choices
+                        .iter()
+                        .zip(weights)
+                        .map(|(c, w)| (index(c), *w))
+                        .collect(),
+                );
+            }
+        }
+
+        let indices = |pairs: &mut dyn Iterator<Item = TokenPairRef<'_>>| {
+            let mut indices: Vec<_> = pairs.map(|(l, r)| (index(l), index(r))).collect();
+            indices.sort_unstable();
+            indices
+        };
+        let sentences = PortableSentences {
+            starts: indices(&mut bounds.starts()),
+            ends: indices(&mut bounds.ends()),
+        };
+
+        let portable = PortableChain {
+            version: PORTABLE_VERSION,
+            order: 2,
+            vocab: vocab.into_iter().map(Token::from).collect(),
+            transitions,
+            sentences,
+        };
+        // Cannot fail, since all keys are integers and all values are valid
+        serde_json::to_string(&portable).unwrap()
+    }
+
+    /// Reads a chain in the portable JSON format described in the [`portable`](self) module.
+    pub fn from_portable_json(json: &str) -> Result<Self, PortableJsonError> {
+        let portable: PortableChain = serde_json::from_str(json)?;
+        if portable.version != PORTABLE_VERSION {
+            return Err(PortableJsonError::UnsupportedVersion(portable.version));
+        }
+        if portable.order != 2 {
+            return Err(PortableJsonError::UnsupportedOrder(portable.order));
+        }
+
+        let vocab = &portable.vocab;
+        let token = |i: usize| vocab.get(i).ok_or(PortableJsonError::UnknownToken(i));
+
+        let mut map: HashMap<Token, HashMap<Token, TokenDistribution>> = HashMap::new();
+        for (left, rights) in portable.transitions {
+            let left = token(left)?;
+            for (right, nexts) in rights {
+                let right = token(right)?;
+                let mut choices = Vec::with_capacity(nexts.len());
+                let mut weights = Vec::with_capacity(nexts.len());
+                for (next, n) in nexts {
+                    choices.push(token(next)?.clone());
+                    weights.push(n);
+                }
+                let dist = TokenDistribution::try_from_parts(choices, weights)
+                    .map_err(PortableJsonError::Distribution)?;
+                map.entry(left.clone())
+                    .or_default()
+                    .insert(right.clone(), dist);
+            }
+        }
+
+        if map.is_empty() {
+            return Err(PortableJsonError::Empty);
+        }
+
+        let pairs = |indices: Vec<(usize, usize)>| {
+            indices
+                .into_iter()
+                .map(|(l, r)| Ok(TokenPair(token(l)?.clone(), token(r)?.clone())))
+                .collect::<Result<Vec<_>, PortableJsonError>>()
+        };
+        let sentences = SentenceBounds::from_pairs(
+            pairs(portable.sentences.starts)?,
+            pairs(portable.sentences.ends)?,
+        );
+        Ok(Chain::from_parts(map, sentences))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::PortableJsonError;
+    use crate::Chain;
+
+    #[test]
+    fn portable_roundtrip() {
+        let chain = Chain::from_text("I am-full!of?cats").unwrap();
+        let json = chain.to_portable_json();
+        let loaded = Chain::from_portable_json(&json).unwrap();
+        assert_eq!(
+            loaded
+                .generate_n_tokens(&mut thread_rng(), &("I", " "), 7)
+                .unwrap(),
+            vec!["am", "-", "full", "!", "of", "?", "cats"],
+        );
+    }
+
+    #[test]
+    fn portable_from_hand_written() {
+        let json = r#"{
+            "version": 1,
+            "order": 2,
+            "vocab": [" ", "I", "am"],
+            "transitions": { "1": { "0": { "2": 1 } } }
+        }"#;
+        let chain = Chain::from_portable_json(json).unwrap();
+        assert_eq!(
+            chain.generate_next_token(&mut thread_rng(), &("I", " ")),
+            Some("am")
+        );
+
+        let with_sentences = json.replace(
+            r#"{ "2": 1 } } }"#,
+            r#"{ "2": 1 } } }, "sentences": { "starts": [[1, 0]], "ends": [[0, 2]] }"#,
+        );
+        let chain = Chain::from_portable_json(&with_sentences).unwrap();
+        assert_eq!(
+            chain.sentence_start_tokens(&mut thread_rng()),
+            Some(("I", " "))
+        );
+        assert!(chain.sentence_bounds().is_end(&(" ", "am")));
+        let exported = chain.to_portable_json();
+        assert!(exported.contains(r#""sentences":{"starts":[[1,0]],"ends":[[0,2]]}"#));
+        let loaded = Chain::from_portable_json(&exported).unwrap();
+        assert_eq!(loaded.sentence_bounds(), chain.sentence_bounds());
+
+        let bad_index = json.replace(r#"{ "2": 1 }"#, r#"{ "7": 1 }"#);
+        assert!(matches!(
+            Chain::from_portable_json(&bad_index),
+            Err(PortableJsonError::UnknownToken(7))
+        ));
+    }
+}
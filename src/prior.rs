@@ -0,0 +1,121 @@
+//! Prior pseudo-counts that are mixed into the distributions of a chain when it is built, to bias
+//! generation towards some tokens without adding made up text to the corpus.
+
+use hashbrown::HashMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::distribution::Weight;
+use crate::token::{Token, TokenPair, TokenPairRef};
+use crate::{Chain, ChainBuilder};
+
+/// Pseudo-counts added to distributions by [`ChainBuilder::build_with_prior()`], as if the tokens
+/// had been seen that many more times. A global prior is added to every distribution, and a
+/// per-pair prior only to the distribution of that pair.
+///
+/// Priors never create new pairs, since the chain would not know how to reach them.
+///
+/// # Examples
+///
+/// ```
+/// # use markovish::ChainBuilder;
+/// use markovish::prior::Prior;
+/// use markovish::IntoChainBuilder;
+///
+/// let cb = ChainBuilder::new().feed_str("I am a cat.").into_cb();
+/// let prior = Prior::new()
+///     .with_global("meow", 1)
+///     .with_pair(&("a", " "), "dog", 2);
+/// let chain = cb.build_with_prior(&prior).unwrap();
+///
+/// assert_eq!(chain.probability(&("I", " "), "meow"), Some(0.5));
+/// assert_eq!(chain.probability(&("a", " "), "dog"), Some(0.5));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Prior {
+    global: HashMap<Token, Weight>,
+    pairs: HashMap<TokenPair, HashMap<Token, Weight>>,
+}
+
+impl Prior {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `n` pseudo-counts of `token` to every distribution.
+    pub fn with_global(mut self, token: &str, n: Weight) -> Self {
+        add(&mut self.global, token, n);
+        self
+    }
+
+    /// Adds `n` pseudo-counts of `token` to the distribution of `pair`, if the chain has it.
+    pub fn with_pair(mut self, pair: &TokenPairRef<'_>, token: &str, n: Weight) -> Self {
+        let counts = match self.pairs.get_mut(pair) {
+            Some(counts) => counts,
+            None => self.pairs.entry(TokenPair::from(pair)).or_default(),
+        };
+        add(counts, token, n);
+        self
+    }
+
+    /// Returns `true` if the prior does not add anything.
+    pub fn is_empty(&self) -> bool {
+        self.global.is_empty() && self.pairs.is_empty()
+    }
+}
+
+/// Adds `n` to the count of `token`, unless `n` is zero.
+fn add(counts: &mut HashMap<Token, Weight>, token: &str, n: Weight) {
+    if n == 0 {
+        return;
+    }
+    match counts.get_mut(token) {
+        Some(c) => *c = c.saturating_add(n),
+        None => {
+            counts.insert(Token::from(token), n);
+        }
+    }
+}
+
+impl ChainBuilder {
+    /// Builds the chain like [`ChainBuilder::build()`], but first adds the pseudo-counts of
+    /// `prior` to every distribution.
+    pub fn build_with_prior(mut self, prior: &Prior) -> Result<Chain, ChainBuilder> {
+        if self.map.is_empty() || prior.is_empty() {
+            return self.build();
+        }
+
+        for (pair, b) in self.map.iter_mut() {
+            for (token, n) in &prior.global {
+                b.add_token_n(token, *n);
+            }
+            for (token, n) in prior.pairs.get(pair).into_iter().flatten() {
+                b.add_token_n(token, *n);
+            }
+        }
+        self.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Prior;
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn priors_only_touch_existing_pairs() {
+        let cb = ChainBuilder::new().feed_str("a b").into_cb();
+        let prior = Prior::new()
+            .with_pair(&("x", "y"), "z", 5)
+            .with_pair(&("a", " "), "c", 1)
+            .with_pair(&("a", " "), "c", 2)
+            .with_global("b", 0);
+        let chain = cb.build_with_prior(&prior).unwrap();
+
+        assert_eq!(chain.pairs().count(), 1);
+        assert_eq!(chain.count(&("a", " "), "b"), Some(1));
+        assert_eq!(chain.count(&("a", " "), "c"), Some(3));
+    }
+}
@@ -0,0 +1,281 @@
+//! Working with text provided by a user, such as suggesting how to continue it.
+
+use hashbrown::HashMap;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::token::{TokenPairRef, TokenRef};
+use crate::Chain;
+
+impl Chain {
+    /// Returns the `k` most likely tokens to follow `text`, together with their probabilities,
+    /// with the most likely first. `text` is split like in
+    /// [`ChainBuilder::feed_str()`](crate::ChainBuilder::feed_str).
+    ///
+    /// The last two tokens of `text` are used if the chain knows them. Otherwise, the
+    /// suggestions are based on all pairs ending with the last token. If the chain does not know
+    /// the last token either, nothing is suggested.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I like cats. I like cats. I like dogs.").unwrap();
+    /// let suggestions = chain.suggest_next("Do you like ", 2);
+    /// assert_eq!(suggestions[0].0, "cats");
+    /// assert!((suggestions[0].1 - 2.0 / 3.0).abs() < 1e-9);
+    /// assert_eq!(suggestions[1].0, "dogs");
+    ///
+    /// // Only the last token is known
+    /// assert_eq!(chain.suggest_next("we like", 1)[0].0, " ");
+    ///
+    /// // No token is known
+    /// assert_eq!(chain.suggest_next("I hate", 1), vec![]);
+    /// ```
+    pub fn suggest_next(&self, text: &str, k: usize) -> Vec<(TokenRef<'_>, f64)> {
+        let mut tokens = text.split_word_bounds().rev();
+        let Some(last) = tokens.next() else {
+            return Vec::new();
+        };
+
+        let mut counts: HashMap<TokenRef<'_>, u64> = HashMap::new();
+        let exact = tokens
+            .next()
+            .and_then(|second_last| self.get_distribution(&(second_last, last)));
+        match exact {
+            Some(dist) => {
+                counts.extend(dist.iter().map(|(t, n)| (t.as_str(), n as u64)));
+            }
+            None => {
+                // Every pair ending with the last token
+                let pairs = self.pairs_with_second(last);
+                for dist in pairs.filter_map(|pair| self.get_distribution(&pair)) {
+                    for (t, n) in dist.iter() {
+                        *counts.entry(t.as_str()).or_default() += n as u64;
+                    }
+                }
+            }
+        }
+
+        let total: u64 = counts.values().sum();
+        let mut suggestions: Vec<_> = counts
+            .into_iter()
+            .map(|(t, n)| (t, n as f64 / total as f64))
+            .collect();
+        suggestions.sort_unstable_by(|(a, a_p), (b, b_p)| b_p.total_cmp(a_p).then(a.cmp(b)));
+        suggestions.truncate(k);
+        suggestions
+    }
+
+    /// Returns `prompt` followed by `n` generated tokens, joined like
+    /// [`Chain::generate_string()`]. `prompt` is split like in
+    /// [`ChainBuilder::feed_str()`](crate::ChainBuilder::feed_str).
+    ///
+    /// Generation continues from the last two tokens of `prompt` if the chain knows them, or
+    /// from a random pair ending with the last token if it does not. If the chain does not know
+    /// the last token either, the generated text starts from a random pair like
+    /// [`Chain::generate_str()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// let text = chain.continue_str(&mut thread_rng(), "You know, I am", 4);
+    /// assert_eq!(text, "You know, I am a cat");
+    /// ```
+    pub fn continue_str<R: Rng + ?Sized>(&self, rng: &mut R, prompt: &str, n: usize) -> String {
+        self.continue_prompt(rng, prompt, n, false)
+    }
+
+    /// Like [`Chain::continue_str()`], but if the chain does not know the last two tokens of
+    /// `prompt`, the closest pair it knows is used instead, see [`Chain::closest_pair()`]. This
+    /// way prompts with typos or different casing are still continued.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat").unwrap();
+    /// let text = chain.continue_str_fuzzy(&mut thread_rng(), "I AM", 4);
+    /// assert_eq!(text, "I AM a cat");
+    /// ```
+    pub fn continue_str_fuzzy<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prompt: &str,
+        n: usize,
+    ) -> String {
+        self.continue_prompt(rng, prompt, n, true)
+    }
+
+    /// Continues `prompt`, see [`Chain::continue_str()`] and [`Chain::continue_str_fuzzy()`].
+    fn continue_prompt<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prompt: &str,
+        n: usize,
+        fuzzy: bool,
+    ) -> String {
+        let mut text = String::from(prompt);
+        let Some(start) = self.prompt_pair(rng, prompt, fuzzy) else {
+            return text;
+        };
+        for token in self.generate_n_tokens(rng, &start, n).unwrap_or_default() {
+            text.push_str(token);
+        }
+        text
+    }
+
+    /// Returns the pair of the chain that is closest to `pair`, for when the chain does not know
+    /// `pair` exactly, such as when it contains a typo. Pairs are tried in this order:
+    ///
+    /// 1. `pair` itself.
+    /// 2. A pair that only differs in upper and lower case.
+    /// 3. A pair with the same second token, where the first token is as close to the first
+    ///    token of `pair` as possible.
+    /// 4. The pair with the smallest total
+    ///    [edit distance](https://en.wikipedia.org/wiki/Levenshtein_distance) to `pair`.
+    ///
+    /// Ties are broken by choosing the smallest pair. `None` is only returned if the chain is
+    /// empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("The cat sat").unwrap();
+    /// assert_eq!(chain.closest_pair(&("the", " ")), Some(("The", " ")));
+    /// assert_eq!(chain.closest_pair(&("bat", " ")), Some(("cat", " ")));
+    /// assert_eq!(chain.closest_pair(&(" ", "cta")), Some((" ", "cat")));
+    /// ```
+    pub fn closest_pair(&self, pair: &TokenPairRef<'_>) -> Option<TokenPairRef<'_>> {
+        if let Some(found) = self.known_pair(pair) {
+            return Some(found);
+        }
+
+        let eq_ignore_case = |a: &str, b: &str| {
+            a.chars()
+                .flat_map(char::to_lowercase)
+                .eq(b.chars().flat_map(char::to_lowercase))
+        };
+        let ignoring_case = self
+            .pairs()
+            .filter(|(left, right)| eq_ignore_case(left, pair.0) && eq_ignore_case(right, pair.1))
+            .min();
+        if ignoring_case.is_some() {
+            return ignoring_case;
+        }
+
+        let same_right = self
+            .pairs_with_second(pair.1)
+            .min_by_key(|p| (edit_distance(p.0, pair.0), *p));
+        if same_right.is_some() {
+            return same_right;
+        }
+
+        self.pairs()
+            .min_by_key(|p| (edit_distance(p.0, pair.0) + edit_distance(p.1, pair.1), *p))
+    }
+
+    /// Finds the pair to continue `prompt` from, see [`Chain::continue_str()`].
+    fn prompt_pair<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prompt: &str,
+        fuzzy: bool,
+    ) -> Option<TokenPairRef<'_>> {
+        let mut tokens = prompt.split_word_bounds().rev();
+        let last = tokens.next();
+        let second_last = tokens.next();
+
+        if let (Some(second_last), Some(last)) = (second_last, last) {
+            let found = if fuzzy {
+                self.closest_pair(&(second_last, last))
+            } else {
+                self.known_pair(&(second_last, last))
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
+        if let Some(last) = last {
+            let ending_with_last = self.pairs_with_second(last).choose(rng);
+            if ending_with_last.is_some() {
+                return ending_with_last;
+            }
+        }
+        self.start_tokens(rng)
+    }
+}
+
+/// The amount of characters that must be inserted, removed or replaced to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replace = prev[j] + usize::from(ca != *cb);
+            curr[j + 1] = replace.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::edit_distance;
+    use crate::Chain;
+
+    #[test]
+    fn falls_back_to_last_token() {
+        let chain = Chain::from_text("a x b. c x d. c x d").unwrap();
+        // "q x" is unknown, so every pair ending with "x" is used
+        let suggestions = chain.suggest_next("q x", 5);
+        assert_eq!(suggestions, vec![(" ", 1.0)]);
+
+        let suggestions = chain.suggest_next("q x ", 5);
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[0].0, "d");
+        assert!((suggestions[0].1 - 2.0 / 3.0).abs() < 1e-9);
+
+        assert!(chain.suggest_next("", 5).is_empty());
+        assert!(chain.suggest_next("c x ", 0).is_empty());
+    }
+
+    #[test]
+    fn continues_unknown_prompts() {
+        let chain = Chain::from_text("I am a cat").unwrap();
+        let mut rng = thread_rng();
+
+        // Only "a" is known
+        assert_eq!(chain.continue_str(&mut rng, "Oh, a", 2), "Oh, a cat");
+        // Nothing is known, so a random pair is continued
+        let text = chain.continue_str(&mut rng, "Hi", 3);
+        assert!(text.starts_with("Hi") && text.len() > 2);
+        assert_eq!(chain.continue_str(&mut rng, "", 0), "");
+    }
+
+    #[test]
+    fn closest_pair_and_edit_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("åäö", "åäö"), 0);
+
+        let chain = Chain::from_text("Hello there. Hello world").unwrap();
+        assert_eq!(chain.closest_pair(&("HELLO", " ")), Some(("Hello", " ")));
+        assert_eq!(chain.closest_pair(&("Helo", " ")), Some(("Hello", " ")));
+        assert_eq!(chain.closest_pair(&(" ", "thre")), Some((" ", "there")));
+
+        let text = chain.continue_str_fuzzy(&mut thread_rng(), "Say helo ", 1);
+        assert!(text == "Say helo there" || text == "Say helo world");
+    }
+}
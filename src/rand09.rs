@@ -0,0 +1,69 @@
+//! Using random number generators from `rand` 0.9 with this crate, which is built on `rand` 0.8.
+//! Requires the `rand09` feature.
+//!
+//! All generation methods take a `rand` 0.8 [`Rng`](rand::Rng). Since `rand` 0.9 comes with
+//! its own, incompatible, traits, its generators (such as `rand::rng()`) must be wrapped in a
+//! [`Rand09`] first.
+//!
+//! ```
+//! # use markovish::Chain;
+//! use markovish::rand09::Rand09;
+//!
+//! let chain = Chain::from_text("I am a chain of tokens.").unwrap();
+//! let mut rng = Rand09(rand09::rng());
+//! let generated = chain.generate_str(&mut rng, 10).unwrap();
+//! assert_eq!(generated.len(), 10);
+//! ```
+
+use rand_core09::RngCore as RngCore09;
+
+/// Wraps a random number generator from `rand` 0.9, so that it can be used where a `rand` 0.8
+/// [`Rng`](rand::Rng) is expected. Requires the `rand09` feature.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rand09<R>(pub R);
+
+impl<R> Rand09<R> {
+    /// Returns the wrapped generator.
+    pub fn into_inner(self) -> R {
+        self.0
+    }
+}
+
+impl<R: RngCore09> rand::RngCore for Rand09<R> {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    /// Never fails, since generators implementing `RngCore` in `rand` 0.9 are infallible.
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand09::rngs::StdRng;
+    use rand09::SeedableRng;
+
+    use super::Rand09;
+    use crate::Chain;
+
+    #[test]
+    fn seeded_generation_is_deterministic() {
+        let chain = Chain::from_text("a b a c a b a d a b").unwrap();
+        let generate = || {
+            let mut rng = Rand09(StdRng::seed_from_u64(7));
+            chain.generate_str(&mut rng, 20).unwrap().concat()
+        };
+        assert_eq!(generate(), generate());
+    }
+}
@@ -0,0 +1,262 @@
+//! Sentence boundaries recorded while feeding a [`ChainBuilder`] using
+//! [`ChainBuilder::feed_sentences()`], used to start and stop generation at the same places a
+//! sentence starts and ends in the source text.
+
+use hashbrown::HashSet;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::chain::FeedResult;
+use crate::token::{Token, TokenPair, TokenPairRef, TokenRef};
+use crate::{Chain, ChainBuilder};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The first and last two tokens of every sentence fed to a [`ChainBuilder`] using
+/// [`ChainBuilder::feed_sentences()`]. Sentences are found using
+/// [`unicode_segmentation::UnicodeSegmentation::split_sentence_bounds()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SentenceBounds {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::sorted::set"))]
+    starts: HashSet<TokenPair>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::sorted::set"))]
+    ends: HashSet<TokenPair>,
+}
+
+impl SentenceBounds {
+    /// Returns `true` if no sentences have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Returns an iterator of the first two tokens of every recorded sentence.
+    pub fn starts(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        self.starts.iter().map(TokenPair::as_ref)
+    }
+
+    /// Returns `true` if a sentence has ended with the tokens in `pair`.
+    pub fn is_end(&self, pair: &TokenPairRef<'_>) -> bool {
+        self.ends.contains(pair)
+    }
+
+    /// Returns an iterator of the last two tokens of every recorded sentence.
+    pub(crate) fn ends(&self) -> impl Iterator<Item = TokenPairRef<'_>> {
+        self.ends.iter().map(TokenPair::as_ref)
+    }
+
+    /// Creates sentence bounds from the first and last two tokens of sentences.
+    #[cfg(feature = "json")]
+    pub(crate) fn from_pairs(
+        starts: impl IntoIterator<Item = TokenPair>,
+        ends: impl IntoIterator<Item = TokenPair>,
+    ) -> Self {
+        Self {
+            starts: starts.into_iter().collect(),
+            ends: ends.into_iter().collect(),
+        }
+    }
+
+    /// Keeps only the sentence starts and ends for which `f` returns `true`.
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&TokenPair) -> bool) {
+        self.starts.retain(&mut f);
+        self.ends.retain(f);
+    }
+
+    /// Keeps only the sentence starts for which `f` returns `true`. Ends are kept, since they
+    /// usually have no next token and so are not pairs of a chain anyway.
+    pub(crate) fn retain_starts(&mut self, f: impl FnMut(&TokenPair) -> bool) {
+        self.starts.retain(f);
+    }
+
+    /// Keeps only the sentence ends for which `f` returns `true`.
+    pub(crate) fn retain_ends(&mut self, f: impl FnMut(&TokenPair) -> bool) {
+        self.ends.retain(f);
+    }
+
+    /// Shrinks the capacity of the sets of starts and ends as much as possible.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.starts.shrink_to_fit();
+        self.ends.shrink_to_fit();
+    }
+
+    /// The sets of sentence starts and ends.
+    pub(crate) fn sets(&self) -> [&HashSet<TokenPair>; 2] {
+        [&self.starts, &self.ends]
+    }
+
+    /// Returns the sentence starts and ends recorded in both `self` and `other`.
+    pub(crate) fn intersection(&self, other: &SentenceBounds) -> SentenceBounds {
+        Self {
+            starts: self.starts.intersection(&other.starts).cloned().collect(),
+            ends: self.ends.intersection(&other.ends).cloned().collect(),
+        }
+    }
+
+    /// Replaces every token with what `f` returns for it.
+    pub(crate) fn map_tokens(self, mut f: impl FnMut(&str) -> Token) -> Self {
+        let mut map_pairs = |pairs: HashSet<TokenPair>| {
+            pairs
+                .into_iter()
+                .map(|TokenPair(left, right)| TokenPair(f(&left), f(&right)))
+                .collect()
+        };
+        Self {
+            starts: map_pairs(self.starts),
+            ends: map_pairs(self.ends),
+        }
+    }
+
+    /// Records a sentence, if it has at least two tokens. Returns the length in bytes of the
+    /// tokens of starts and ends that had not been recorded before.
+    fn record(&mut self, tokens: &[TokenRef<'_>]) -> usize {
+        let ([first, second, ..], [.., second_last, last]) = (tokens, tokens) else {
+            return 0;
+        };
+        let mut added = 0;
+        if self.starts.insert(TokenPair::new(first, second)) {
+            added += first.len() + second.len();
+        }
+        if self.ends.insert(TokenPair::new(second_last, last)) {
+            added += second_last.len() + last.len();
+        }
+        added
+    }
+
+    /// Records every sentence in `content`, split like in [`ChainBuilder::feed_sentences()`].
+    /// Returns the length in bytes of the tokens of starts and ends that had not been recorded
+    /// before.
+    pub(crate) fn record_sentences(&mut self, content: &str) -> usize {
+        content
+            .split_sentence_bounds()
+            .map(|sentence| {
+                let tokens: Vec<_> = sentence.trim_end().split_word_bounds().collect();
+                self.record(&tokens)
+            })
+            .sum()
+    }
+}
+
+impl ChainBuilder {
+    /// Feeds the builder with `content` exactly like [`ChainBuilder::feed_str()`], but also
+    /// records where each sentence starts and ends. The recorded sentences are kept in the built
+    /// chain, and are used by [`Chain::sentence_start_tokens()`] and
+    /// [`Chain::generate_sentence()`].
+    ///
+    /// Whitespace after a sentence is not considered part of it, so the end of a sentence is
+    /// usually a word followed by a punctuation mark.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let cb = ChainBuilder::new()
+    ///     .feed_sentences("I am a cat. You are a dog!")
+    ///     .into_cb();
+    /// let chain = cb.build().unwrap();
+    /// let mut starts: Vec<_> = chain.sentence_bounds().starts().collect();
+    /// starts.sort();
+    /// assert_eq!(starts, vec![("I", " "), ("You", " ")]);
+    /// ```
+    pub fn feed_sentences(self, content: &str) -> FeedResult {
+        let mut updated = self.feed_str(content)?;
+        updated.chain_builder.sentences.record_sentences(content);
+        Ok(updated)
+    }
+}
+
+impl Chain {
+    /// Returns the sentence boundaries recorded using [`ChainBuilder::feed_sentences()`]. If
+    /// the chain was only fed in other ways, it is empty.
+    pub fn sentence_bounds(&self) -> &SentenceBounds {
+        &self.sentences
+    }
+
+    /// Randomly chooses the first two tokens of a sentence in the source text. Unlike
+    /// [`Chain::start_tokens()`], this means that generated text will start like a sentence.
+    ///
+    /// If no sentences have been recorded using [`ChainBuilder::feed_sentences()`], `None` is
+    /// returned.
+    pub fn sentence_start_tokens<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenPairRef<'_>> {
+        self.sentences.starts().choose(rng)
+    }
+
+    /// Generates a single sentence of at most `max_tokens` tokens (including the start tokens).
+    /// The sentence starts like a sentence in the source text, and ends once it reaches the end
+    /// of one. Unlike [`Chain::generate_str()`], generation is never restarted, so the sentence
+    /// also ends if there is no possible next token.
+    ///
+    /// If no sentences have been recorded using [`ChainBuilder::feed_sentences()`], `None` is
+    /// returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::ChainBuilder;
+    /// # use rand::thread_rng;
+    /// use markovish::IntoChainBuilder;
+    ///
+    /// let cb = ChainBuilder::new().feed_sentences("I am a cat. I am a dog.").into_cb();
+    /// let chain = cb.build().unwrap();
+    /// let sentence = chain.generate_sentence(&mut thread_rng(), 100).unwrap().concat();
+    /// assert!(sentence == "I am a cat." || sentence == "I am a dog.");
+    /// ```
+    pub fn generate_sentence<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        max_tokens: usize,
+    ) -> Option<Vec<&str>> {
+        let (mut left, mut right) = self.sentence_start_tokens(rng)?;
+        let mut res: Vec<_> = [left, right].into_iter().take(max_tokens).collect();
+
+        while res.len() < max_tokens && !self.sentences.is_end(&(left, right)) {
+            let Some(next) = self.generate_next_token(rng, &(left, right)) else {
+                break;
+            };
+            res.push(next);
+            left = right;
+            right = next;
+        }
+
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn records_sentence_ends() {
+        let cb = ChainBuilder::new()
+            .feed_sentences("Hello there.  Who are you?\n")
+            .into_cb();
+        let chain = cb.build().unwrap();
+        let bounds = chain.sentence_bounds();
+        assert!(bounds.is_end(&("there", ".")));
+        assert!(bounds.is_end(&("you", "?")));
+        assert!(!bounds.is_end(&(".", " ")));
+        assert_eq!(bounds.starts().count(), 2);
+
+        // Sentences that do not end before `max_tokens` are cut short
+        let sentence = chain.generate_sentence(&mut thread_rng(), 2).unwrap();
+        assert_eq!(sentence.len(), 2);
+    }
+
+    #[test]
+    fn feed_str_records_nothing() {
+        let chain = ChainBuilder::new()
+            .feed_str("Hello there. Who are you?")
+            .into_cb()
+            .build()
+            .unwrap();
+        assert!(chain.sentence_bounds().is_empty());
+        assert_eq!(chain.generate_sentence(&mut thread_rng(), 10), None);
+    }
+}
@@ -0,0 +1,54 @@
+//! Serializes hash maps and sets in sorted order, so that the same chain is always serialized to
+//! the same bytes no matter the order its tokens were added in.
+
+use hashbrown::{HashMap, HashSet};
+use serde::{Serialize, Serializer};
+
+/// A map that is serialized with its keys in sorted order.
+pub(crate) struct SortedMap<'a, K, V>(pub(crate) &'a HashMap<K, V>);
+
+impl<K: Ord + Serialize, V: Serialize> Serialize for SortedMap<'_, K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = self.0.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        serializer.collect_map(entries)
+    }
+}
+
+/// Serializes `map` with its keys in sorted order, for use with `serialize_with`.
+pub(crate) fn map<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Ord + Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    SortedMap(map).serialize(serializer)
+}
+
+/// Serializes a map of maps with the keys of both in sorted order, for use with
+/// `serialize_with`.
+pub(crate) fn nested_map<K, K2, V, S>(
+    map: &HashMap<K, HashMap<K2, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    K: Ord + Serialize,
+    K2: Ord + Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    let mut entries: Vec<_> = map.iter().map(|(k, v)| (k, SortedMap(v))).collect();
+    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    serializer.collect_map(entries)
+}
+
+/// Serializes `set` in sorted order, for use with `serialize_with`.
+pub(crate) fn set<T, S>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Ord + Serialize,
+    S: Serializer,
+{
+    let mut items: Vec<_> = set.iter().collect();
+    items.sort_unstable();
+    serializer.collect_seq(items)
+}
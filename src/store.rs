@@ -0,0 +1,165 @@
+//! A [`TransitionStore`] is anything that knows which tokens may follow a pair of tokens. It is
+//! the boundary between generating text and how a chain is stored, be it in memory like a
+//! [`Chain`] or on disk.
+
+use std::ops::Deref;
+
+use rand::Rng;
+
+use crate::distribution::TokenDistribution;
+use crate::token::{TokenPairRef, TokenRef};
+use crate::Chain;
+
+/// Storage of the [`TokenDistribution`] of each [`TokenPair`](crate::token::TokenPair) in a second
+/// order Markov chain.
+///
+/// Implementors only have to provide lookups, and get generation of tokens for free.
+pub trait TransitionStore {
+    /// A handle to a distribution, which may either be borrowed from the store or loaded from
+    /// somewhere else.
+    type Dist<'a>: Deref<Target = TokenDistribution>
+    where
+        Self: 'a;
+
+    /// A token of the store. Stores that keep their tokens in memory can lend them out, while
+    /// others have to hand out owned [`Token`](crate::token::Token)s.
+    type Token<'a>: AsRef<str> + Clone
+    where
+        Self: 'a;
+
+    /// Finds the distribution of tokens that may follow `prev`, if the pair has ever been seen.
+    fn lookup(&self, prev: &TokenPairRef<'_>) -> Option<Self::Dist<'_>>;
+
+    /// Randomly chooses a pair that is known to be able to generate a new token. If the store is
+    /// empty, `None` is returned.
+    fn random_pair<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+    ) -> Option<(Self::Token<'_>, Self::Token<'_>)>;
+
+    /// Generates a random new token using the previous tokens.
+    ///
+    /// If the store has never seen the `prev` tokens together, `None` is returned.
+    fn generate_next<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+    ) -> Option<Self::Token<'_>>;
+
+    /// Generates `n` tokens following `prev`, a pair of the store such as one from
+    /// [`TransitionStore::random_pair()`]. If two tokens are found that have never been seen
+    /// together, two new starting tokens are chosen using [`TransitionStore::random_pair()`];
+    /// see [`Chain::generate_n_tokens()`].
+    ///
+    /// If the store has never seen the `prev` tokens together, `None` is returned.
+    fn generate_tokens<'a, R: Rng + ?Sized>(
+        &'a self,
+        rng: &mut R,
+        prev: &(Self::Token<'a>, Self::Token<'a>),
+        n: usize,
+    ) -> Option<Vec<Self::Token<'a>>> {
+        if n < 1 {
+            return Some(Vec::new());
+        }
+
+        let first = self.generate_next(rng, &(prev.0.as_ref(), prev.1.as_ref()))?;
+        let mut res = Vec::with_capacity(n);
+        res.push(first.clone());
+        let mut walk = Walk::new(Some((prev.1.clone(), first)));
+        while res.len() < n {
+            let Some(next) = walk.step_in(self, rng) else {
+                break;
+            };
+            res.push(next);
+        }
+        Some(res)
+    }
+}
+
+impl TransitionStore for Chain {
+    type Dist<'a> = &'a TokenDistribution;
+    type Token<'a> = TokenRef<'a>;
+
+    fn lookup(&self, prev: &TokenPairRef<'_>) -> Option<Self::Dist<'_>> {
+        self.get_distribution(prev)
+    }
+
+    fn random_pair<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<TokenPairRef<'_>> {
+        self.start_tokens(rng)
+    }
+
+    fn generate_next<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        prev: &TokenPairRef<'_>,
+    ) -> Option<TokenRef<'_>> {
+        self.generate_next_token(rng, prev)
+    }
+}
+
+/// Generates text one token at a time from the last two tokens, starting over from a new pair
+/// whenever there is no possible next token. All generation that starts over at dead ends goes
+/// through this, so that it is done the same way everywhere.
+#[derive(Clone, Debug)]
+pub(crate) struct Walk<T> {
+    /// The last two tokens, or `None` if the walk has to start over.
+    prev: Option<(T, T)>,
+    /// The second token of the pair the walk just started over from, which is returned next.
+    pending: Option<T>,
+}
+
+impl<T: Clone> Walk<T> {
+    /// Creates a walk continuing after `prev`, or starting over right away if it is `None`.
+    pub(crate) fn new(prev: Option<(T, T)>) -> Self {
+        Self {
+            prev,
+            pending: None,
+        }
+    }
+
+    /// Makes the next step start over, as if there was no possible next token.
+    pub(crate) fn start_over(&mut self) {
+        self.prev = None;
+        self.pending = None;
+    }
+
+    /// Returns the next token, chosen by `next` from the last two tokens. If `next` finds
+    /// nothing, the walk starts over from the pair chosen by `restart`, and its two tokens are
+    /// returned by this and the following step. Only returns `None` if `restart` does.
+    pub(crate) fn step<R: ?Sized>(
+        &mut self,
+        rng: &mut R,
+        next: impl FnOnce(&mut R, &(T, T)) -> Option<T>,
+        restart: impl FnOnce(&mut R) -> Option<(T, T)>,
+    ) -> Option<T> {
+        if let Some(token) = self.pending.take() {
+            return Some(token);
+        }
+        if let Some(prev) = &mut self.prev {
+            if let Some(token) = next(rng, prev) {
+                prev.0 = std::mem::replace(&mut prev.1, token.clone());
+                return Some(token);
+            }
+        }
+
+        let (left, right) = restart(rng)?;
+        self.pending = Some(right.clone());
+        self.prev = Some((left.clone(), right));
+        Some(left)
+    }
+
+    /// Like [`Walk::step()`], using the tokens of `store` and starting over from
+    /// [`TransitionStore::random_pair()`].
+    pub(crate) fn step_in<'a, S, R>(&mut self, store: &'a S, rng: &mut R) -> Option<T>
+    where
+        S: TransitionStore<Token<'a> = T> + ?Sized,
+        R: Rng + ?Sized,
+        T: AsRef<str>,
+    {
+        self.step(
+            rng,
+            |rng, (left, right)| store.generate_next(rng, &(left.as_ref(), right.as_ref())),
+            |rng| store.random_pair(rng),
+        )
+    }
+}
@@ -0,0 +1,325 @@
+//! Generating an endless stream of text in pieces, such as when sending a never ending HTTP
+//! response, or one sentence at a time to a chat bot.
+
+use std::borrow::Cow;
+
+use rand::Rng;
+
+use crate::store::Walk;
+use crate::token::{TokenPairRef, TokenRef};
+use crate::Chain;
+
+/// An endless iterator of generated text, split into chunks of roughly the same size. Created
+/// using [`Chain::chunks()`].
+#[derive(Debug)]
+pub struct Chunks<'a, R: ?Sized> {
+    chain: &'a Chain,
+    rng: &'a mut R,
+    approx_chunk_bytes: usize,
+    walk: Walk<TokenRef<'a>>,
+    escape_html: bool,
+}
+
+/// Escapes the characters of `token` that have a special meaning in HTML (`&`, `<`, `>`, `"` and
+/// `'`), so that it can be put inside both elements and attributes. Tokens without any such
+/// characters are returned as they are.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::stream::escape_html;
+///
+/// assert_eq!(escape_html("cat"), "cat");
+/// assert_eq!(escape_html("<b>&"), "&lt;b&gt;&amp;");
+/// ```
+pub fn escape_html(token: &str) -> Cow<'_, str> {
+    if !token.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(token);
+    }
+
+    let mut escaped = String::with_capacity(token.len() + 8);
+    for c in token.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+impl<R: ?Sized> Chunks<'_, R> {
+    /// Escapes every generated token using [`escape_html()`], so that the chunks can be sent as
+    /// part of an HTML document. The size of a chunk includes the escapes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("if a < b && b < c").unwrap();
+    /// let mut rng = thread_rng();
+    /// let chunk = chain.chunks(&mut rng, 100).escape_html().next().unwrap();
+    /// assert!(!chunk.contains('<'));
+    /// ```
+    pub fn escape_html(mut self) -> Self {
+        self.escape_html = true;
+        self
+    }
+}
+
+impl<'a, R: Rng + ?Sized> Chunks<'a, R> {
+    /// Generates a single token, restarting from new start tokens if needed. Returns `None` only
+    /// if the chain has no start tokens.
+    fn next_token(&mut self) -> Option<TokenRef<'a>> {
+        self.walk.step_in(self.chain, self.rng)
+    }
+}
+
+impl<R: Rng + ?Sized> Iterator for Chunks<'_, R> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = String::with_capacity(self.approx_chunk_bytes);
+        while chunk.is_empty() || chunk.len() < self.approx_chunk_bytes {
+            let token = self.next_token()?;
+            if self.escape_html {
+                chunk.push_str(&escape_html(token));
+            } else {
+                chunk.push_str(token);
+            }
+        }
+        Some(chunk)
+    }
+}
+
+impl Chain {
+    /// Returns an endless iterator of generated text, split into chunks of about
+    /// `approx_chunk_bytes` bytes. Tokens are never split between chunks, so a chunk is as
+    /// short as possible while still being at least `approx_chunk_bytes` long, and is
+    /// therefore usually a bit longer. Every chunk contains at least one token.
+    ///
+    /// Like [`Chain::generate_n_tokens()`], new start tokens are chosen whenever there is no
+    /// possible next token.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let mut rng = thread_rng();
+    /// for chunk in chain.chunks(&mut rng, 64).take(3) {
+    ///     assert!(chunk.len() >= 64);
+    ///     // Send the chunk somewhere...
+    /// }
+    /// ```
+    pub fn chunks<'a, R: Rng + ?Sized>(
+        &'a self,
+        rng: &'a mut R,
+        approx_chunk_bytes: usize,
+    ) -> Chunks<'a, R> {
+        Chunks {
+            chain: self,
+            rng,
+            approx_chunk_bytes,
+            walk: Walk::new(None),
+            escape_html: false,
+        }
+    }
+}
+
+/// The most tokens in a sentence from [`Sentences`], unless set using
+/// [`Sentences::max_tokens()`].
+const DEFAULT_MAX_SENTENCE_TOKENS: usize = 200;
+
+/// How many times [`Sentences`] starts a sentence over after reaching a dead end, before it gives
+/// up and returns the sentence as it is.
+const MAX_SENTENCE_RESTARTS: usize = 100;
+
+/// An endless iterator of generated sentences. Created using [`Chain::sentences()`].
+#[derive(Debug)]
+pub struct Sentences<'a, R: ?Sized> {
+    chain: &'a Chain,
+    rng: &'a mut R,
+    /// Pairs that sentences start from
+    starts: Vec<TokenPairRef<'a>>,
+    /// If the start pairs are part of the sentence, or only come before it
+    include_starts: bool,
+    max_tokens: usize,
+}
+
+impl<R: ?Sized> Sentences<'_, R> {
+    /// Sets the most tokens a sentence may have. Sentences that have not ended by then are cut
+    /// short. The default is 200.
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+}
+
+impl<'a, R: Rng + ?Sized> Sentences<'a, R> {
+    /// Generates a sentence into `tokens`. Returns `false` if there was no possible next token
+    /// before the sentence ended.
+    fn generate_into(&mut self, tokens: &mut Vec<TokenRef<'a>>) -> bool {
+        let (mut left, mut right) = self.starts[self.rng.gen_range(0..self.starts.len())];
+        if self.include_starts {
+            tokens.extend([left, right]);
+        }
+
+        let is_end = |pair: &TokenPairRef<'_>| {
+            self.chain.sentences.is_end(pair) || pair.1.trim_end().ends_with(['.', '!', '?'])
+        };
+        while tokens.len() < self.max_tokens && !(tokens.len() >= 2 && is_end(&(left, right))) {
+            let Some(next) = self.chain.generate_next_token(self.rng, &(left, right)) else {
+                return false;
+            };
+            tokens.push(next);
+            left = right;
+            right = next;
+        }
+        true
+    }
+}
+
+impl<R: Rng + ?Sized> Iterator for Sentences<'_, R> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.starts.is_empty() {
+            return None;
+        }
+
+        let mut tokens = Vec::new();
+        for _ in 0..=MAX_SENTENCE_RESTARTS {
+            tokens.clear();
+            if self.generate_into(&mut tokens) {
+                break;
+            }
+        }
+        Some(tokens.concat().trim().to_string())
+    }
+}
+
+impl Chain {
+    /// Returns an endless iterator of generated sentences, one sentence per item, such as to
+    /// send to a chat one line at a time.
+    ///
+    /// If the chain was fed using
+    /// [`ChainBuilder::feed_sentences()`](crate::ChainBuilder::feed_sentences), sentences start
+    /// and end like in the source text. Otherwise a sentence starts after a token ending with `.`,
+    /// `!` or `?` (or anywhere, if there are none), and ends with such a token. A sentence is cut
+    /// short if it becomes too long, see [`Sentences::max_tokens()`]. If there is no possible next
+    /// token before a sentence ends, it is thrown away and a new sentence is generated instead,
+    /// unless that has already happened 100 times. Whitespace around sentences is removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// # use rand::thread_rng;
+    /// let chain = Chain::from_text("I am a cat. You are a dog! Who am I?").unwrap();
+    /// let mut rng = thread_rng();
+    /// for sentence in chain.sentences(&mut rng).take(5) {
+    ///     assert!(sentence.ends_with(['.', '!', '?']));
+    /// }
+    /// ```
+    pub fn sentences<'a, R: Rng + ?Sized>(&'a self, rng: &'a mut R) -> Sentences<'a, R> {
+        let mut starts: Vec<_> = self.sentences.starts().collect();
+        let mut include_starts = true;
+        if starts.is_empty() {
+            // Pairs right after the end of a sentence, like "." " "
+            starts = self
+                .pairs()
+                .filter(|(left, right)| {
+                    left.ends_with(['.', '!', '?']) && right.chars().all(char::is_whitespace)
+                })
+                .collect();
+            include_starts = false;
+        }
+        if starts.is_empty() {
+            starts = self.pairs().collect();
+            include_starts = true;
+        }
+
+        Sentences {
+            chain: self,
+            rng,
+            starts,
+            include_starts,
+            max_tokens: DEFAULT_MAX_SENTENCE_TOKENS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::escape_html;
+    use crate::Chain;
+
+    #[test]
+    fn chunks_never_split_tokens() {
+        let chain = Chain::from_text("Hello there. General Kenobi!").unwrap();
+        let mut rng = thread_rng();
+        let words = ["Hello", "there", "General", "Kenobi", ".", "!", " "];
+
+        for chunk in chain.chunks(&mut rng, 10).take(50) {
+            assert!(chunk.len() >= 10);
+            // No chunk is longer than it has to be
+            assert!(chunk.len() < 10 + "General".len());
+        }
+
+        // Zero means a single token per chunk
+        let chunk = chain.chunks(&mut rng, 0).next().unwrap();
+        assert!(words.contains(&chunk.as_str()));
+    }
+
+    #[test]
+    fn sentences_start_after_terminators() {
+        let chain = Chain::from_text("I am a cat. You are a dog! Who am I? Me").unwrap();
+        let mut rng = thread_rng();
+        for sentence in chain.sentences(&mut rng).take(20) {
+            let first = sentence.split(' ').next().unwrap();
+            // "Me" is a dead end, so it is never a sentence on its own
+            assert!(["You", "Who"].contains(&first), "{sentence}");
+            assert!(sentence.ends_with(['.', '!', '?']));
+        }
+
+        // Without any terminators, sentences are cut short
+        let chain = Chain::from_text("a a a a a").unwrap();
+        let sentence = chain.sentences(&mut rng).max_tokens(5).next().unwrap();
+        assert!(sentence == "a a a" || sentence == "a a", "{sentence}");
+    }
+
+    #[test]
+    fn sentences_start_over_at_dead_ends() {
+        // "I" " " is followed by "run" or "stop", but only "run" ends a sentence
+        let chain = Chain::from_text("I run. I stop").unwrap();
+        let mut rng = thread_rng();
+        for sentence in chain.sentences(&mut rng).take(20) {
+            assert_eq!(sentence, "I run.");
+        }
+
+        // Without a way to end a sentence, the last try is returned
+        let chain = Chain::from_text("I stop").unwrap();
+        let sentence = chain.sentences(&mut rng).next().unwrap();
+        assert!(sentence.ends_with("stop"), "{sentence}");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(escape_html(r#""I'm""#), "&quot;I&#39;m&quot;");
+
+        let chain = Chain::from_text("<p>Hi & bye</p>").unwrap();
+        let mut rng = thread_rng();
+        for chunk in chain.chunks(&mut rng, 20).escape_html().take(20) {
+            assert!(!chunk.contains(['<', '>']));
+            assert_eq!(chunk.matches('&').count(), chunk.matches(';').count());
+        }
+    }
+}
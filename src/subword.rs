@@ -0,0 +1,147 @@
+//! Splitting text into subword units using a vocabulary from the Hugging Face
+//! [`tokenizers`](https://crates.io/crates/tokenizers) library, such as BPE or WordPiece. Requires
+//! the `tokenizers` feature.
+//!
+//! Subword tokens work better than [`WordBounds`](crate::tokenize::WordBounds) for languages where
+//! words are hard to find or very long, and keep the vocabulary of the chain small. Since
+//! subword tokens cannot simply be joined together, generated tokens should be turned back into
+//! text using [`HfTokenizer::detokenize()`].
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::detokenize::Detokenizer;
+use crate::tokenize::Tokenizer;
+
+/// The error type of the `tokenizers` library.
+pub use tokenizers::Error as HfError;
+
+/// A [`Tokenizer`] using a Hugging Face `tokenizers` vocabulary. Requires the `tokenizers`
+/// feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use markovish::ChainBuilder;
+/// # use rand::thread_rng;
+/// use markovish::subword::HfTokenizer;
+/// use markovish::IntoChainBuilder;
+///
+/// let tokenizer = HfTokenizer::from_file("tokenizer.json").unwrap();
+/// let cb = ChainBuilder::new().feed_with(&tokenizer, "Some text to learn from").into_cb();
+/// let chain = cb.build().unwrap();
+///
+/// let tokens = chain.generate_str(&mut thread_rng(), 100).unwrap();
+/// println!("{}", tokenizer.detokenize(&tokens).unwrap());
+/// ```
+#[derive(Clone, Debug)]
+pub struct HfTokenizer {
+    inner: tokenizers::Tokenizer,
+}
+
+impl HfTokenizer {
+    pub fn new(inner: tokenizers::Tokenizer) -> Self {
+        Self { inner }
+    }
+
+    /// Loads a tokenizer from a `tokenizer.json` file, like the ones published with models on
+    /// the Hugging Face Hub.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, HfError> {
+        tokenizers::Tokenizer::from_file(path).map(Self::new)
+    }
+
+    /// Loads a tokenizer from the contents of a `tokenizer.json` file.
+    pub fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Self, HfError> {
+        tokenizers::Tokenizer::from_bytes(bytes).map(Self::new)
+    }
+
+    /// Returns the wrapped tokenizer.
+    pub fn inner(&self) -> &tokenizers::Tokenizer {
+        &self.inner
+    }
+
+    /// Turns generated tokens back into text, using the decoder of the vocabulary. Special tokens
+    /// are skipped.
+    ///
+    /// Returns an error if a token is not in the vocabulary, which can only happen if the chain
+    /// was fed using something else than this tokenizer.
+    pub fn detokenize(&self, tokens: &[&str]) -> Result<String, HfError> {
+        let ids = tokens
+            .iter()
+            .map(|t| {
+                self.inner
+                    .token_to_id(t)
+                    .ok_or_else(|| format!("token {t:?} is not in the vocabulary").into())
+            })
+            .collect::<Result<Vec<_>, HfError>>()?;
+        self.inner.decode(&ids, true)
+    }
+}
+
+impl From<tokenizers::Tokenizer> for HfTokenizer {
+    fn from(value: tokenizers::Tokenizer) -> Self {
+        Self::new(value)
+    }
+}
+
+impl Tokenizer for HfTokenizer {
+    /// Returns the tokens of the encoded text, without any special tokens. If the text cannot be
+    /// encoded, no tokens are returned.
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        match self.inner.encode(text, false) {
+            Ok(encoding) => encoding
+                .get_tokens()
+                .iter()
+                .map(|t| Cow::Owned(t.clone()))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl Detokenizer for HfTokenizer {
+    /// Like [`HfTokenizer::detokenize()`], but tokens that are not in the vocabulary are skipped.
+    /// If decoding fails, an empty string is returned.
+    fn detokenize(&self, tokens: &[&str]) -> String {
+        let ids: Vec<_> = tokens
+            .iter()
+            .filter_map(|t| self.inner.token_to_id(t))
+            .collect();
+        self.inner.decode(&ids, true).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HfTokenizer;
+    use crate::tokenize::Tokenizer;
+
+    const WORD_LEVEL: &str = r#"{
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "Whitespace" },
+        "post_processor": null,
+        "decoder": null,
+        "model": {
+            "type": "WordLevel",
+            "vocab": { "[UNK]": 0, "I": 1, "am": 2, "a": 3, "cat": 4, ".": 5 },
+            "unk_token": "[UNK]"
+        }
+    }"#;
+
+    #[test]
+    fn tokenize_and_detokenize() {
+        let tokenizer = HfTokenizer::from_bytes(WORD_LEVEL).unwrap();
+        let tokens = tokenizer.tokenize("I am a dog.");
+        assert_eq!(tokens, vec!["I", "am", "a", "[UNK]", "."]);
+
+        assert_eq!(
+            tokenizer.detokenize(&["I", "am", "a", "cat"]).unwrap(),
+            "I am a cat"
+        );
+        assert!(tokenizer.detokenize(&["dog"]).is_err());
+    }
+}
@@ -1,12 +1,14 @@
-//! At the heart of a [`Chain`](crate::Chain) is a [`Token`]. In fact, this is just a String. But we make a
-//! distinction here: A Token is any atomic piece of text.
+//! At the heart of a [`Chain`](crate::Chain) is a [`Token`]. In fact, this is just a String (or a
+//! [`CompactString`](https://docs.rs/compact_str) if the `compact_str` feature is enabled). But we
+//! make a distinction here: A Token is any atomic piece of text.
 //!
 //! When using [`ChainBuilder::feed_str()`](crate::chain::ChainBuilder::feed_str()),
 //! it is the output of [`unicode_segmentation::UnicodeSegmentation::split_word_bounds()`]; that is,
 //! it can be a word, a symbol like `"`, or something else. See that crate for more information.
 //!
 //! If you want more control of what you want a token to be, you can use
-//! [`ChainBuilder::feed_tokens()`](crate::chain::ChainBuilder::feed_tokens()).
+//! [`ChainBuilder::feed_tokens()`](crate::chain::ChainBuilder::feed_tokens()), or one of the
+//! tokenizers in [`crate::tokenize`].
 
 use hashbrown::Equivalent;
 
@@ -14,8 +16,14 @@ use hashbrown::Equivalent;
 use serde::{Deserialize, Serialize};
 
 /// Representation of a string segment.
+#[cfg(not(feature = "compact_str"))]
 pub type Token = String;
 
+/// Representation of a string segment. Since the `compact_str` feature is enabled, short tokens
+/// are stored inline without any heap allocation.
+#[cfg(feature = "compact_str")]
+pub type Token = compact_str::CompactString;
+
 /// An owned pair of [`Token`]s.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -29,13 +37,13 @@ pub type TokenPairRef<'a> = (TokenRef<'a>, TokenRef<'a>);
 
 impl TokenPair {
     pub fn new(left: &str, right: &str) -> Self {
-        Self(left.to_string(), right.to_string())
+        Self(Token::from(left), Token::from(right))
     }
 }
 
 impl<'a> From<&TokenPairRef<'a>> for TokenPair {
     fn from(value: &TokenPairRef) -> Self {
-        Self(value.0.to_string(), value.1.to_string())
+        Self(Token::from(value.0), Token::from(value.1))
     }
 }
 
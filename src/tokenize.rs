@@ -0,0 +1,730 @@
+//! Different ways of splitting text into [`Token`](crate::token::Token)s, for when the default
+//! [`ChainBuilder::feed_str()`] does not fit your text. Use them with
+//! [`ChainBuilder::feed_with()`].
+//!
+//! # Examples
+//!
+//! ```
+//! # use markovish::ChainBuilder;
+//! # use rand::thread_rng;
+//! use markovish::tokenize::Words;
+//! use markovish::IntoChainBuilder;
+//!
+//! let cb = ChainBuilder::new().feed_with(&Words, "I am here. I am gone.").into_cb();
+//! let chain = cb.build().unwrap();
+//! assert_eq!(
+//!     chain.generate_next_token(&mut thread_rng(), &("am", "here.")),
+//!     Some("I")
+//! );
+//! ```
+
+use std::borrow::Cow;
+use std::iter;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::chain::{FeedResult, UpdatedChainBuilder};
+use crate::ChainBuilder;
+
+/// Splits a text into tokens.
+pub trait Tokenizer {
+    /// Returns the tokens of `text`, in order. Tokens are usually slices of `text`, but may be
+    /// changed or created by the tokenizer.
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>>;
+
+    /// Returns the tokens of `text` split into segments. No token is ever generated from tokens
+    /// in another segment, so this is used for boundaries the chain should never cross, like
+    /// lines in a log.
+    ///
+    /// By default the whole text is a single segment.
+    fn segments<'a>(&self, text: &'a str) -> Vec<Vec<Cow<'a, str>>> {
+        vec![self.tokenize(text)]
+    }
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for &T {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        (**self).tokenize(text)
+    }
+
+    fn segments<'a>(&self, text: &'a str) -> Vec<Vec<Cow<'a, str>>> {
+        (**self).segments(text)
+    }
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for Box<T> {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        (**self).tokenize(text)
+    }
+
+    fn segments<'a>(&self, text: &'a str) -> Vec<Vec<Cow<'a, str>>> {
+        (**self).segments(text)
+    }
+}
+
+/// The default tokenizer, used by [`ChainBuilder::feed_str()`]. Splits text using
+/// [`unicode_segmentation::UnicodeSegmentation::split_word_bounds()`], so that words,
+/// punctuation and whitespace all become their own tokens.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WordBounds;
+
+impl Tokenizer for WordBounds {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        text.split_word_bounds().map(Cow::Borrowed).collect()
+    }
+}
+
+/// A [`Tokenizer`] using a function, created using [`from_fn()`].
+#[derive(Clone, Copy, Debug)]
+pub struct FnTokenizer<F> {
+    f: F,
+}
+
+/// Creates a [`Tokenizer`] from a function splitting a text into tokens. This is the easiest way
+/// to use any segmentation you want, for example a dictionary-based word segmenter for languages
+/// that do not use spaces between words (such as Japanese, Chinese, or Thai), where
+/// [`WordBounds`] can only split the text into single characters.
+///
+/// # Examples
+///
+/// ```
+/// use std::borrow::Cow;
+/// use markovish::tokenize::{from_fn, Tokenizer};
+///
+/// // A very simple "dictionary" segmenter, which knows two words
+/// let dictionary = ["猫", "です"];
+/// let tokenizer = from_fn(|text| {
+///     let mut tokens = Vec::new();
+///     let mut rest = text;
+///     while let Some(c) = rest.chars().next() {
+///         let len = dictionary
+///             .iter()
+///             .find(|word| rest.starts_with(*word))
+///             .map_or(c.len_utf8(), |word| word.len());
+///         tokens.push(Cow::Borrowed(&rest[..len]));
+///         rest = &rest[len..];
+///     }
+///     tokens
+/// });
+/// assert_eq!(tokenizer.tokenize("猫です"), vec!["猫", "です"]);
+/// ```
+pub fn from_fn<F>(f: F) -> FnTokenizer<F>
+where
+    F: for<'a> Fn(&'a str) -> Vec<Cow<'a, str>>,
+{
+    FnTokenizer { f }
+}
+
+impl<F> Tokenizer for FnTokenizer<F>
+where
+    F: for<'a> Fn(&'a str) -> Vec<Cow<'a, str>>,
+{
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        (self.f)(text)
+    }
+}
+
+/// Splits text on whitespace, so that punctuation stays attached to its word (`"end."` is a
+/// single token) and whitespace is not part of any token.
+///
+/// This keeps the chain from generating punctuation on its own, and since every token is a
+/// word, the chain looks further back in the text. Since whitespace is removed, generated tokens
+/// must be joined with a space.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::tokenize::{Tokenizer, Words};
+///
+/// assert_eq!(Words.tokenize("Hello,  world!\n"), vec!["Hello,", "world!"]);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Words;
+
+impl Tokenizer for Words {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        text.split_whitespace().map(Cow::Borrowed).collect()
+    }
+}
+
+/// Wraps another tokenizer, replacing every run of whitespace tokens with a single `" "` token.
+/// This keeps inconsistent indentation and spacing from creating lots of different whitespace
+/// tokens.
+///
+/// If `keep_newlines` is set, runs containing a newline become a single `"\n"` instead, so that
+/// the chain can still generate line breaks.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::tokenize::{CollapseWhitespace, Tokenizer, WordBounds};
+///
+/// let collapse = CollapseWhitespace::new(WordBounds);
+/// assert_eq!(collapse.tokenize("a \t b\n\n  c").concat(), "a b c");
+///
+/// let keep_newlines = CollapseWhitespace::new(WordBounds).keep_newlines(true);
+/// assert_eq!(keep_newlines.tokenize("a \t b\n\n  c").concat(), "a b\nc");
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CollapseWhitespace<T> {
+    inner: T,
+    keep_newlines: bool,
+}
+
+impl<T> CollapseWhitespace<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            keep_newlines: false,
+        }
+    }
+
+    /// Sets if whitespace containing a newline should become `"\n"` instead of `" "`.
+    pub fn keep_newlines(mut self, keep_newlines: bool) -> Self {
+        self.keep_newlines = keep_newlines;
+        self
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for CollapseWhitespace<T> {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        let mut tokens: Vec<Cow<'a, str>> = Vec::new();
+        // Whether the last token is collapsed whitespace, and if it contains a newline
+        let mut in_whitespace = false;
+        let mut has_newline = false;
+
+        for token in self.inner.tokenize(text) {
+            if token.is_empty() || !token.chars().all(char::is_whitespace) {
+                in_whitespace = false;
+                tokens.push(token);
+                continue;
+            }
+
+            has_newline = (in_whitespace && has_newline) || token.contains('\n');
+            let collapsed = if self.keep_newlines && has_newline {
+                "\n"
+            } else {
+                " "
+            };
+            if in_whitespace {
+                // Unwrap is safe, since we are in whitespace after a pushed token
+                *tokens.last_mut().unwrap() = Cow::Borrowed(collapsed);
+            } else {
+                tokens.push(Cow::Borrowed(collapsed));
+            }
+            in_whitespace = true;
+        }
+
+        tokens
+    }
+}
+
+/// A class of words that are all replaced by the same placeholder token by [`TokenClasses`].
+#[derive(Clone, Debug)]
+pub struct TokenClass {
+    placeholder: String,
+    matches: fn(&str) -> bool,
+}
+
+impl TokenClass {
+    /// Creates a class of all words for which `matches` returns `true`. The words are
+    /// whitespace-separated, so they may include punctuation.
+    pub fn new(placeholder: impl Into<String>, matches: fn(&str) -> bool) -> Self {
+        Self {
+            placeholder: placeholder.into(),
+            matches,
+        }
+    }
+
+    /// Integers such as `42` or `-7`, replaced by `<NUM>`.
+    pub fn integers() -> Self {
+        Self::new("<NUM>", |word| {
+            let digits = word.strip_prefix('-').unwrap_or(word);
+            !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+        })
+    }
+
+    /// Words starting with `http://`, `https://` or `www.`, replaced by `<URL>`.
+    pub fn urls() -> Self {
+        Self::new("<URL>", |word| {
+            ["http://", "https://", "www."]
+                .iter()
+                .any(|prefix| word.starts_with(prefix))
+        })
+    }
+
+    /// Returns the token that words in this class are replaced by.
+    pub fn placeholder(&self) -> &str {
+        &self.placeholder
+    }
+}
+
+/// Wraps another tokenizer, replacing every word in some [`TokenClass`] with the placeholder of
+/// that class. Useful for numbers or links, which otherwise are mostly tokens that are only
+/// seen once.
+///
+/// Words are found by splitting on whitespace before the wrapped tokenizer is used, so that
+/// something like a link is replaced as a whole. The rest of the text is split by the wrapped
+/// tokenizer as usual.
+///
+/// Since generated text will contain the placeholders, use [`TokenClasses::substitute()`] to
+/// replace them with something else.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::tokenize::{TokenClass, TokenClasses, Tokenizer, WordBounds};
+///
+/// let classes = TokenClasses::new(WordBounds)
+///     .with(TokenClass::integers())
+///     .with(TokenClass::urls());
+/// let tokens = classes.tokenize("I have 12 links, see https://example.com/a?b=c");
+/// assert_eq!(tokens.concat(), "I have <NUM> links, see <URL>");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TokenClasses<T> {
+    inner: T,
+    classes: Vec<TokenClass>,
+}
+
+impl<T> TokenClasses<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            classes: Vec::new(),
+        }
+    }
+
+    /// Adds a class of words to replace. If a word is in several classes, the one added first
+    /// is used.
+    pub fn with(mut self, class: TokenClass) -> Self {
+        self.classes.push(class);
+        self
+    }
+
+    /// Replaces the placeholders in `tokens` with the result of `replace`, which is called with
+    /// the placeholder. All other tokens are kept as they are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use markovish::tokenize::{TokenClass, TokenClasses, WordBounds};
+    ///
+    /// let classes = TokenClasses::new(WordBounds).with(TokenClass::integers());
+    /// let generated = ["I", " ", "am", " ", "<NUM>"];
+    /// let replaced = classes.substitute(generated, |_| "42".to_string());
+    /// assert_eq!(replaced.concat(), "I am 42");
+    /// ```
+    pub fn substitute<'t>(
+        &self,
+        tokens: impl IntoIterator<Item = &'t str>,
+        mut replace: impl FnMut(&str) -> String,
+    ) -> Vec<Cow<'t, str>> {
+        tokens
+            .into_iter()
+            .map(|token| {
+                if self.classes.iter().any(|c| c.placeholder == token) {
+                    Cow::Owned(replace(token))
+                } else {
+                    Cow::Borrowed(token)
+                }
+            })
+            .collect()
+    }
+
+    fn class_of(&self, word: &str) -> Option<&TokenClass> {
+        self.classes.iter().find(|c| (c.matches)(word))
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for TokenClasses<T> {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        let mut tokens = Vec::new();
+        // Start of the text that has not been tokenized yet
+        let mut rest = 0;
+        let mut word_start = None;
+
+        // Adding whitespace at the end makes sure that the last word is checked as well
+        for (i, c) in text.char_indices().chain(iter::once((text.len(), ' '))) {
+            if !c.is_whitespace() {
+                word_start.get_or_insert(i);
+                continue;
+            }
+            let Some(start) = word_start.take() else {
+                continue;
+            };
+            if let Some(class) = self.class_of(&text[start..i]) {
+                tokens.extend(self.inner.tokenize(&text[rest..start]));
+                tokens.push(Cow::Owned(class.placeholder.clone()));
+                rest = i;
+            }
+        }
+
+        tokens.extend(self.inner.tokenize(&text[rest..]));
+        tokens
+    }
+}
+
+/// Wraps another tokenizer, handling the parts of Markdown that do not make sense to split into
+/// tokens:
+///
+/// - Fenced code blocks are skipped, or kept as a single token if `keep_code_blocks` is set.
+/// - Links and images only keep their text, and not their target.
+/// - Heading markers (such as `##`) are kept as a single token.
+///
+/// Everything else is split by the wrapped tokenizer.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::tokenize::{Markdown, Tokenizer, WordBounds};
+///
+/// let text = "## Usage\nSee [the docs](https://example.com).\n```rust\nlet x = 1;\n```\n";
+/// let tokens = Markdown::new(WordBounds).tokenize(text);
+/// assert_eq!(tokens.concat(), "## Usage\nSee the docs.\n\n");
+/// assert_eq!(tokens[0], "##");
+///
+/// let tokens = Markdown::new(WordBounds).keep_code_blocks(true).tokenize(text);
+/// assert!(tokens.contains(&"```rust\nlet x = 1;\n```".into()));
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Markdown<T> {
+    inner: T,
+    keep_code_blocks: bool,
+}
+
+/// A part of a Markdown text, either text to split or a token to keep as it is.
+enum MarkdownPiece<'a> {
+    /// The start and end of text to split.
+    Text(usize, usize),
+    Atomic(&'a str),
+}
+
+impl<T> Markdown<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            keep_code_blocks: false,
+        }
+    }
+
+    /// Sets if fenced code blocks should be kept as a single token instead of being skipped.
+    pub fn keep_code_blocks(mut self, keep_code_blocks: bool) -> Self {
+        self.keep_code_blocks = keep_code_blocks;
+        self
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for Markdown<T> {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        let mut pieces = Vec::new();
+        let mut offset = 0;
+        // The fence and start of the code block we are in, if any
+        let mut fence: Option<(&str, usize)> = None;
+
+        for line in text.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let trimmed = line.trim_start();
+
+            if let Some((marker, block_start)) = fence {
+                if trimmed.starts_with(marker) {
+                    fence = None;
+                    let block_end = start + line.trim_end_matches(['\r', '\n']).len();
+                    if self.keep_code_blocks {
+                        pieces.push(MarkdownPiece::Atomic(&text[block_start..block_end]));
+                    }
+                    pieces.push(MarkdownPiece::Text(block_end, offset));
+                }
+                continue;
+            }
+            if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+                fence = Some((marker, start));
+                continue;
+            }
+
+            let mut pos = start;
+            let hashes = trimmed.bytes().take_while(|&b| b == b'#').count();
+            let after_hashes = &trimmed[hashes..];
+            if (1..=6).contains(&hashes)
+                && (after_hashes.starts_with([' ', '\t']) || after_hashes.trim().is_empty())
+            {
+                let marker_start = start + line.len() - trimmed.len();
+                pieces.push(MarkdownPiece::Text(start, marker_start));
+                pieces.push(MarkdownPiece::Atomic(
+                    &text[marker_start..marker_start + hashes],
+                ));
+                pos = marker_start + hashes;
+            }
+            push_without_link_targets(text, pos, offset, &mut pieces);
+        }
+
+        // A code block that is never closed lasts until the end of the text
+        if let (Some((_, block_start)), true) = (fence, self.keep_code_blocks) {
+            pieces.push(MarkdownPiece::Atomic(&text[block_start..]));
+        }
+
+        let mut tokens = Vec::new();
+        let mut pieces = pieces.into_iter().peekable();
+        while let Some(piece) = pieces.next() {
+            match piece {
+                MarkdownPiece::Text(start, mut end) => {
+                    // Text right after other text is split together, so that the wrapped
+                    // tokenizer sees as much as possible at once
+                    while let Some(&MarkdownPiece::Text(next_start, next_end)) = pieces.peek() {
+                        if next_start != end {
+                            break;
+                        }
+                        end = next_end;
+                        pieces.next();
+                    }
+                    if start < end {
+                        tokens.extend(self.inner.tokenize(&text[start..end]));
+                    }
+                }
+                MarkdownPiece::Atomic(token) => tokens.push(Cow::Borrowed(token)),
+            }
+        }
+        tokens
+    }
+}
+
+/// Adds the text between `start` and `end` to `pieces`, but without the targets (and brackets)
+/// of links and images.
+fn push_without_link_targets(
+    text: &str,
+    start: usize,
+    end: usize,
+    pieces: &mut Vec<MarkdownPiece<'_>>,
+) {
+    let line = &text[start..end];
+    let mut kept = 0;
+    let mut search = 0;
+
+    while let Some(open) = line[search..].find('[').map(|i| search + i) {
+        let Some(close) = line[open..].find("](").map(|i| open + i) else {
+            break;
+        };
+        let Some(target_end) = line[close..].find(')').map(|i| close + i + 1) else {
+            break;
+        };
+
+        let is_image = line[..open].ends_with('!');
+        pieces.push(MarkdownPiece::Text(
+            start + kept,
+            start + open - usize::from(is_image),
+        ));
+        pieces.push(MarkdownPiece::Text(start + open + 1, start + close));
+        kept = target_end;
+        search = target_end;
+    }
+
+    pieces.push(MarkdownPiece::Text(start + kept, end));
+}
+
+/// What to do with newlines when splitting text, see [`Newlines`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NewlinePolicy {
+    /// Newlines are tokens like any other whitespace. This is what
+    /// [`ChainBuilder::feed_str()`] does.
+    #[default]
+    Keep,
+    /// Whitespace containing newlines becomes a single space, so that the chain never generates
+    /// newlines. Useful for prose that has been wrapped at a fixed width.
+    Collapse,
+    /// Every line is a segment of its own (see [`Tokenizer::segments()`]), so that the chain
+    /// never continues one line with the next. Useful for logs, or any text where each line
+    /// stands on its own. Newlines are not part of any token.
+    Segment,
+}
+
+/// Wraps another tokenizer, handling newlines according to a [`NewlinePolicy`].
+///
+/// Since [`NewlinePolicy::Segment`] splits the text into segments, this should be the outermost
+/// wrapper if several are used.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::tokenize::{NewlinePolicy, Newlines, Tokenizer, WordBounds};
+///
+/// let text = "Roses are red,\nviolets are blue";
+/// let collapse = Newlines::new(WordBounds, NewlinePolicy::Collapse);
+/// assert_eq!(collapse.tokenize(text).concat(), "Roses are red, violets are blue");
+///
+/// let segment = Newlines::new(WordBounds, NewlinePolicy::Segment);
+/// assert_eq!(segment.segments(text).len(), 2);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Newlines<T> {
+    inner: T,
+    policy: NewlinePolicy,
+}
+
+impl<T> Newlines<T> {
+    pub fn new(inner: T, policy: NewlinePolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl<T: Tokenizer> Tokenizer for Newlines<T> {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Cow<'a, str>> {
+        let tokens = self.inner.tokenize(text);
+        if self.policy != NewlinePolicy::Collapse {
+            return tokens;
+        }
+
+        let mut collapsed = Vec::with_capacity(tokens.len());
+        // The run of whitespace tokens since the last other token
+        let mut run = Vec::new();
+        let flush = |run: &mut Vec<Cow<'a, str>>, collapsed: &mut Vec<Cow<'a, str>>| {
+            if run.iter().any(|t| t.contains(['\n', '\r'])) {
+                run.clear();
+                collapsed.push(Cow::Borrowed(" "));
+            } else {
+                collapsed.append(run);
+            }
+        };
+        for token in tokens {
+            if !token.is_empty() && token.chars().all(char::is_whitespace) {
+                run.push(token);
+            } else {
+                flush(&mut run, &mut collapsed);
+                collapsed.push(token);
+            }
+        }
+        flush(&mut run, &mut collapsed);
+        collapsed
+    }
+
+    fn segments<'a>(&self, text: &'a str) -> Vec<Vec<Cow<'a, str>>> {
+        match self.policy {
+            NewlinePolicy::Segment => text.lines().map(|l| self.inner.tokenize(l)).collect(),
+            _ => vec![self.tokenize(text)],
+        }
+    }
+}
+
+impl ChainBuilder {
+    /// Feeds the builder with the tokens `tokenizer` splits `content` into. Each segment (see
+    /// [`Tokenizer::segments()`]) is fed on its own, and this fails only if every segment was
+    /// too short, like [`ChainBuilder::feed_str()`].
+    ///
+    /// Feeding the same builder using different tokenizers might result in odd output, see
+    /// [`ChainBuilder::feed_tokens()`].
+    pub fn feed_with<T: Tokenizer + ?Sized>(self, tokenizer: &T, content: &str) -> FeedResult {
+        let mut cb = self;
+        let mut fed = None;
+        for segment in tokenizer.segments(content) {
+            match cb.feed_tokens(segment.iter().map(|t| t.as_ref())) {
+                Ok(updated) => {
+                    let (new_pairs, updated_pairs) = fed.unwrap_or((0, 0));
+                    fed = Some((
+                        new_pairs + updated.new_pairs,
+                        updated_pairs + updated.updated_pairs,
+                    ));
+                    cb = updated.chain_builder;
+                }
+                Err(not_updated) => cb = not_updated,
+            }
+        }
+
+        match fed {
+            Some((new_pairs, updated_pairs)) => Ok(UpdatedChainBuilder {
+                chain_builder: cb,
+                new_pairs,
+                updated_pairs,
+            }),
+            None => Err(cb),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CollapseWhitespace, Markdown, NewlinePolicy, Newlines, TokenClass, TokenClasses, Tokenizer,
+        WordBounds, Words,
+    };
+    use crate::{Chain, ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn word_bounds_is_the_default() {
+        let text = "I am-full!of?cats and I am full of dogs";
+        let fed = ChainBuilder::new()
+            .feed_with(&WordBounds, text)
+            .into_cb()
+            .build()
+            .unwrap();
+        assert_eq!(
+            fed.fingerprint(),
+            Chain::from_text(text).unwrap().fingerprint()
+        );
+        assert_eq!(WordBounds.tokenize("a b").concat(), "a b");
+    }
+
+    #[test]
+    fn token_classes_replace_whole_words() {
+        let classes = TokenClasses::new(Words)
+            .with(TokenClass::integers())
+            .with(TokenClass::urls());
+        assert_eq!(
+            classes.tokenize("1 a2 -3 www.a.b 4."),
+            vec!["<NUM>", "a2", "<NUM>", "<URL>", "4."]
+        );
+
+        let classes = TokenClasses::new(WordBounds).with(TokenClass::integers());
+        assert_eq!(classes.tokenize("7"), vec!["<NUM>"]);
+        assert_eq!(classes.tokenize("  7 "), vec!["  ", "<NUM>", " "]);
+    }
+
+    #[test]
+    fn markdown_edge_cases() {
+        let md = Markdown::new(Words);
+        // Not headings, since there is no space or too many markers
+        assert_eq!(md.tokenize("#tag ####### x"), vec!["#tag", "#######", "x"]);
+        assert_eq!(
+            md.tokenize("![alt text](a.png) [x] (y)"),
+            vec!["alt", "text", "[x]", "(y)"]
+        );
+
+        let unclosed = "a\n~~~\ncode";
+        assert_eq!(md.tokenize(unclosed), vec!["a"]);
+        let kept = Markdown::new(Words)
+            .keep_code_blocks(true)
+            .tokenize(unclosed);
+        assert_eq!(kept, vec!["a", "~~~\ncode"]);
+    }
+
+    #[test]
+    fn segments_are_fed_separately() {
+        let segment = Newlines::new(WordBounds, NewlinePolicy::Segment);
+        let updated = ChainBuilder::new()
+            .feed_with(&segment, "a b\nx\r\nc d")
+            .unwrap();
+        // "x" is too short to be fed, and "b" is never followed by anything
+        assert_eq!(updated.new_pairs, 2);
+        let chain = updated.chain_builder.build().unwrap();
+        assert_eq!(chain.pairs_with_first("b").count(), 0);
+        assert!(!chain.contains_token("\n"));
+
+        assert!(ChainBuilder::new().feed_with(&segment, "a\nb").is_err());
+
+        let collapse = Newlines::new(WordBounds, NewlinePolicy::Collapse);
+        assert_eq!(
+            collapse.tokenize("a \n  b  c\n"),
+            vec!["a", " ", "b", "  ", "c", " "]
+        );
+    }
+
+    #[test]
+    fn collapsed_whitespace_is_shared() {
+        let tokens = CollapseWhitespace::new(WordBounds).tokenize("a\t\tb  c \r\n d");
+        assert_eq!(tokens, vec!["a", " ", "b", " ", "c", " ", "d"]);
+
+        let tokens = CollapseWhitespace::new(WordBounds)
+            .keep_newlines(true)
+            .tokenize(" a\n  b");
+        assert_eq!(tokens, vec![" ", "a", "\n", "b"]);
+    }
+}
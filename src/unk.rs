@@ -0,0 +1,158 @@
+//! Chains where tokens only seen once (hapax legomena) are replaced by a shared [`UNK`] token
+//! when the chain is built. Rare words make up a large part of most texts but are almost never
+//! useful for predicting what comes next, so this makes chains smaller. When generating, [`UNK`]
+//! is replaced by a random rare token, so the output keeps some of their flavor.
+
+use hashbrown::{HashMap, HashSet};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::distribution::Weight;
+use crate::token::Token;
+use crate::{Chain, ChainBuilder};
+
+/// The token that replaces rare tokens in an [`UnkChain`].
+pub const UNK: &str = "<UNK>";
+
+/// A [`Chain`] where every token only seen once has been replaced by [`UNK`]. Created using
+/// [`ChainBuilder::build_with_unk()`].
+///
+/// # Examples
+///
+/// ```
+/// # use markovish::ChainBuilder;
+/// # use rand::thread_rng;
+/// use markovish::unk::UNK;
+/// use markovish::IntoChainBuilder;
+///
+/// let cb = ChainBuilder::new()
+///     .feed_str("I am a cat. I am a dog. I am a bird.")
+///     .into_cb();
+/// let chain = cb.build_with_unk().unwrap();
+/// assert_eq!(chain.chain().count(&("a", " "), UNK), Some(3));
+///
+/// let text = chain.generate_str(&mut thread_rng(), 20).unwrap();
+/// assert!(!text.contains(&UNK));
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnkChain {
+    chain: Chain,
+    rare: Vec<Token>,
+}
+
+impl UnkChain {
+    /// Returns the chain, where rare tokens have been replaced by [`UNK`].
+    pub fn chain(&self) -> &Chain {
+        &self.chain
+    }
+
+    /// Returns an iterator of the tokens that were replaced by [`UNK`]. The order is unspecified.
+    pub fn rare_tokens(&self) -> impl Iterator<Item = &str> {
+        self.rare.iter().map(Token::as_str)
+    }
+
+    /// Returns a random rare token if `token` is [`UNK`], and otherwise `token` itself.
+    pub fn resolve<'a, R: Rng + ?Sized>(&'a self, rng: &mut R, token: &'a str) -> &'a str {
+        if token != UNK {
+            return token;
+        }
+        self.rare.choose(rng).map_or(token, Token::as_str)
+    }
+
+    /// Generates `n` tokens like [`Chain::generate_str()`], replacing every [`UNK`] with a random
+    /// rare token.
+    pub fn generate_str<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<Vec<&str>> {
+        let mut tokens = self.chain.generate_str(rng, n)?;
+        for token in tokens.iter_mut() {
+            *token = self.resolve(rng, token);
+        }
+        Some(tokens)
+    }
+
+    /// Like [`UnkChain::generate_str()`], but the tokens are joined into a [`String`].
+    pub fn generate_string<R: Rng + ?Sized>(&self, rng: &mut R, n: usize) -> Option<String> {
+        self.generate_str(rng, n).map(|tokens| tokens.concat())
+    }
+
+    /// Returns the chain, where rare tokens have been replaced by [`UNK`].
+    pub fn into_chain(self) -> Chain {
+        self.chain
+    }
+}
+
+impl ChainBuilder {
+    /// Builds the chain like [`ChainBuilder::build()`], but first replaces every token that has
+    /// only been seen once with [`UNK`]. The replaced tokens are kept, so that [`UNK`] can be
+    /// replaced by one of them when generating.
+    ///
+    /// Tokens are counted by how many times they follow a pair, so the first two tokens of a
+    /// text are never counted. If the texts already contain [`UNK`], it is treated like any
+    /// other token.
+    pub fn build_with_unk(self) -> Result<UnkChain, ChainBuilder> {
+        let mut counts: HashMap<&str, Weight> = HashMap::new();
+        for b in self.map.values() {
+            for (token, n) in &b.map {
+                let count = counts.entry(token.as_str()).or_default();
+                *count = count.saturating_add(*n);
+            }
+        }
+        let rare: HashSet<Token> = counts
+            .into_iter()
+            .filter(|(token, n)| *n == 1 && *token != UNK)
+            .map(|(token, _)| Token::from(token))
+            .collect();
+
+        let cb = if rare.is_empty() {
+            self
+        } else {
+            self.map_tokens(|t| {
+                if rare.contains(t) {
+                    Token::from(UNK)
+                } else {
+                    Token::from(t)
+                }
+            })
+        };
+        let chain = cb.build()?;
+        Ok(UnkChain {
+            chain,
+            rare: rare.into_iter().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::UNK;
+    use crate::{ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn hapax_tokens_become_unk() {
+        let cb = ChainBuilder::new()
+            .feed_str("the cat and the dog and the cow")
+            .into_cb();
+        let chain = cb.build_with_unk().unwrap();
+
+        let mut rare: Vec<_> = chain.rare_tokens().collect();
+        rare.sort();
+        assert_eq!(rare, vec!["cat", "cow", "dog"]);
+        assert_eq!(chain.chain().count(&("the", " "), UNK), Some(3));
+        assert!(!chain.chain().contains_token("cat"));
+
+        let mut rng = thread_rng();
+        assert_eq!(chain.resolve(&mut rng, "the"), "the");
+        assert!(rare.contains(&chain.resolve(&mut rng, UNK)));
+
+        // Nothing is rare, so nothing is replaced
+        let cb = ChainBuilder::new().feed_str("a a a a a a").into_cb();
+        let chain = cb.build_with_unk().unwrap();
+        assert_eq!(chain.rare_tokens().count(), 0);
+        assert!(!chain.chain().contains_token(UNK));
+    }
+}
@@ -0,0 +1,124 @@
+//! Integrity checks of a [`Chain`], see [`Chain::verify()`].
+
+use std::fmt;
+
+use crate::token::{Token, TokenPair};
+use crate::Chain;
+
+/// A problem found by [`Chain::verify()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// A left token is stored without any right tokens.
+    EmptyLeft(Token),
+    /// A sentence start is not a pair of the chain, so it can not be generated from.
+    DanglingSentenceStart(TokenPair),
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyLeft(token) => write!(f, "left token {token:?} has no pairs"),
+            Self::DanglingSentenceStart(TokenPair(left, right)) => {
+                write!(f, "sentence start ({left:?}, {right:?}) is not a pair")
+            }
+        }
+    }
+}
+
+/// The result of [`Chain::verify()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// The amount of pairs that were checked.
+    pub pairs: usize,
+    /// Every problem that was found, in no particular order.
+    pub issues: Vec<Issue>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Chain {
+    /// Checks that the chain is consistent: every left token must have at least one pair, and
+    /// sentence starts must be pairs of the chain.
+    ///
+    /// The distributions themselves are not checked, since they are validated whenever they are
+    /// created or deserialized, and their sampling tables are always built from their weights.
+    ///
+    /// Chains created by this crate should always pass, but it is a good idea to verify chains
+    /// from elsewhere before shipping them, such as ones built by hand or deserialized from
+    /// untrusted sources.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use markovish::Chain;
+    /// let chain = Chain::from_text("I am a cat. I am a dog.").unwrap();
+    /// let report = chain.verify();
+    /// assert!(report.is_ok());
+    /// assert_eq!(report.pairs, chain.pairs().count());
+    /// ```
+    pub fn verify(&self) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        for (left, rights) in &self.map {
+            if rights.is_empty() {
+                report.issues.push(Issue::EmptyLeft(left.clone()));
+            }
+            report.pairs += rights.len();
+        }
+
+        for start in self.sentences.starts() {
+            if self.get_distribution(&start).is_none() {
+                report
+                    .issues
+                    .push(Issue::DanglingSentenceStart(TokenPair::from(&start)));
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+
+    use super::Issue;
+    use crate::distribution::TokenDistribution;
+    use crate::token::{Token, TokenPair};
+    use crate::{Chain, ChainBuilder, IntoChainBuilder};
+
+    #[test]
+    fn finds_empty_lefts() {
+        let rights = HashMap::from([(
+            Token::from("ok"),
+            TokenDistribution::try_from_parts(vec!["a".into()], vec![1]).unwrap(),
+        )]);
+        let map = HashMap::from([
+            (Token::from("x"), rights),
+            (Token::from("empty"), HashMap::new()),
+        ]);
+        let chain = Chain::from_parts(map, Default::default());
+
+        let report = chain.verify();
+        assert_eq!(report.pairs, 1);
+        assert_eq!(report.issues, vec![Issue::EmptyLeft("empty".into())]);
+    }
+
+    #[test]
+    fn finds_dangling_sentence_starts() {
+        let cb = ChainBuilder::new()
+            .feed_sentences("I am a cat. You are a dog.")
+            .into_cb();
+        let mut chain = cb.build().unwrap();
+        assert!(chain.verify().is_ok());
+
+        chain.map.remove("You");
+        assert_eq!(
+            chain.verify().issues,
+            vec![Issue::DanglingSentenceStart(TokenPair::new("You", " "))]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! A small wrapper around [`Chain`] for use from JavaScript, built using
+//! [`wasm-bindgen`](https://crates.io/crates/wasm-bindgen). Requires the `wasm` feature, which
+//! also makes random numbers work in browsers when building for `wasm32-unknown-unknown`.
+//!
+//! ```js
+//! import { JsChain } from "./pkg/markovish.js";
+//!
+//! const chain = new JsChain("I am a chain running in your browser.");
+//! console.log(chain.generateStr(50));
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Chain, ChainBuilder, IntoChainBuilder};
+
+/// A [`Chain`] that can be created and used from JavaScript. Requires the `wasm` feature.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct JsChain {
+    chain: Chain,
+}
+
+#[wasm_bindgen]
+impl JsChain {
+    /// Creates a chain from a text. Throws if the text is too short.
+    #[wasm_bindgen(constructor)]
+    pub fn new(text: &str) -> Result<JsChain, JsError> {
+        Chain::from_text(text)
+            .map(Self::from)
+            .map_err(|_| JsError::new("text is too short to create a chain"))
+    }
+
+    /// Creates a chain from several texts. Throws if every text is too short.
+    #[wasm_bindgen(js_name = fromTexts)]
+    pub fn from_texts(texts: Vec<String>) -> Result<JsChain, JsError> {
+        let mut cb = ChainBuilder::new();
+        for text in &texts {
+            cb = cb.feed_str(text).into_cb();
+        }
+        cb.build()
+            .map(Self::from)
+            .map_err(|_| JsError::new("texts are too short to create a chain"))
+    }
+
+    /// Generates a string of `n` tokens, randomly choosing a starting point.
+    #[wasm_bindgen(js_name = generateStr)]
+    pub fn generate_str(&self, n: usize) -> Option<String> {
+        self.chain
+            .generate_str(&mut rand::thread_rng(), n)
+            .map(|tokens| tokens.concat())
+    }
+
+    /// Generates a random token that may follow `left` and `right`, or `undefined` if they have
+    /// never been seen together.
+    #[wasm_bindgen(js_name = generateNextToken)]
+    pub fn generate_next_token(&self, left: &str, right: &str) -> Option<String> {
+        self.chain
+            .generate_next_token(&mut rand::thread_rng(), &(left, right))
+            .map(str::to_string)
+    }
+
+    /// Returns the amount of token pairs in the chain.
+    #[wasm_bindgen(js_name = pairCount)]
+    pub fn pair_count(&self) -> usize {
+        self.chain.pairs().count()
+    }
+}
+
+impl From<Chain> for JsChain {
+    fn from(chain: Chain) -> Self {
+        Self { chain }
+    }
+}
+
+impl From<JsChain> for Chain {
+    fn from(value: JsChain) -> Self {
+        value.chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsChain;
+
+    #[test]
+    fn generates_from_texts() {
+        let chain = JsChain::from_texts(vec!["I am here".into(), "I am there".into()])
+            .ok()
+            .unwrap();
+        assert_eq!(chain.pair_count(), 3);
+        assert_eq!(chain.generate_next_token("I", " ").as_deref(), Some("am"));
+        assert_eq!(chain.generate_str(3).map(|s| s.is_empty()), Some(false));
+    }
+}
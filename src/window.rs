@@ -0,0 +1,149 @@
+//! A builder that only remembers the most recently fed documents, for when old texts must not
+//! stay in the chain forever, such as texts written by users.
+
+use std::collections::VecDeque;
+
+use itertools::Itertools;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::token::{Token, TokenRef};
+use crate::{Chain, ChainBuilder};
+
+/// Builds chains from the last `max_documents` documents fed to it. Once that many documents
+/// have been fed, feeding a new one makes the builder forget the oldest, as if it had never
+/// been fed.
+///
+/// Every retained document is kept as tokens, so that its counts can be removed once it is
+/// forgotten.
+///
+/// # Examples
+///
+/// ```
+/// use markovish::window::WindowedChainBuilder;
+///
+/// let mut cb = WindowedChainBuilder::new(2);
+/// cb.feed_str("I am first.");
+/// cb.feed_str("I am second.");
+/// cb.feed_str("I am third.");
+///
+/// let chain = cb.build().unwrap();
+/// assert_eq!(chain.count(&("am", " "), "first"), Some(0));
+/// assert_eq!(chain.count(&("am", " "), "third"), Some(1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct WindowedChainBuilder {
+    max_documents: usize,
+    documents: VecDeque<Vec<Token>>,
+    cb: ChainBuilder,
+}
+
+impl WindowedChainBuilder {
+    /// Creates a builder that remembers at most `max_documents` documents.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `max_documents` is zero.
+    pub fn new(max_documents: usize) -> Self {
+        assert!(max_documents > 0, "must remember at least one document");
+        Self {
+            max_documents,
+            documents: VecDeque::with_capacity(max_documents),
+            cb: ChainBuilder::new(),
+        }
+    }
+
+    /// Feeds the builder with a document, split like in [`ChainBuilder::feed_str()`]. If the
+    /// builder is full, the oldest document is forgotten.
+    ///
+    /// Returns `false` if the document was too short to be used, in which case it is not
+    /// remembered and nothing is forgotten.
+    pub fn feed_str(&mut self, content: &str) -> bool {
+        self.feed_tokens(content.split_word_bounds())
+    }
+
+    /// Feeds the builder with a pre-split document, like [`WindowedChainBuilder::feed_str()`].
+    pub fn feed_tokens<'a>(&mut self, tokens: impl Iterator<Item = TokenRef<'a>>) -> bool {
+        let tokens: Vec<Token> = tokens.map(Token::from).collect();
+        if tokens.len() < 3 {
+            return false;
+        }
+
+        if self.documents.len() == self.max_documents {
+            self.forget_oldest();
+        }
+        for (left, right, next) in tokens.iter().tuple_windows() {
+            self.cb.add_occurance(&(left, right), next);
+        }
+        self.documents.push_back(tokens);
+        true
+    }
+
+    /// Forgets the oldest document, removing everything it added to the builder. Returns
+    /// `false` if there were no documents.
+    pub fn forget_oldest(&mut self) -> bool {
+        let Some(tokens) = self.documents.pop_front() else {
+            return false;
+        };
+        for (left, right, next) in tokens.iter().tuple_windows() {
+            let pair = (left.as_str(), right.as_str());
+            if let Some(b) = self.cb.map.get_mut(&pair) {
+                b.remove_token(next);
+                if b.map.is_empty() {
+                    self.cb.map.remove(&pair);
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns how many documents are remembered.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns `true` if no documents are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Returns the most documents that are remembered at once.
+    pub fn max_documents(&self) -> usize {
+        self.max_documents
+    }
+
+    /// Returns the builder containing the remembered documents.
+    pub fn builder(&self) -> &ChainBuilder {
+        &self.cb
+    }
+
+    /// Creates a chain from the remembered documents, or `None` if there are none. The builder
+    /// can still be fed afterwards.
+    pub fn build(&self) -> Option<Chain> {
+        self.cb.clone().build().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WindowedChainBuilder;
+
+    #[test]
+    fn forgets_everything_from_old_documents() {
+        let mut cb = WindowedChainBuilder::new(1);
+        assert!(!cb.feed_str("Hi"));
+        assert!(cb.feed_str("a b c"));
+        assert!(cb.feed_str("a b d"));
+        assert_eq!(cb.len(), 1);
+
+        let chain = cb.build().unwrap();
+        assert_eq!(chain.count(&("a", " "), "b"), Some(1));
+        assert_eq!(chain.count(&(" ", "b"), " "), Some(1));
+        assert_eq!(chain.count(&("b", " "), "c"), Some(0));
+        assert_eq!(chain.count(&("b", " "), "d"), Some(1));
+
+        assert!(cb.forget_oldest());
+        assert!(cb.is_empty());
+        assert!(cb.builder().map.is_empty());
+        assert!(cb.build().is_none());
+    }
+}